@@ -0,0 +1,217 @@
+use rand::Rng;
+
+use crate::{reinforcement_learning::epsilon_greedy_policy::{epsilon_greedy_index, greedy_index}, utils::{sample_index, sample_standard_normal, softmax}};
+
+// Bandit algorithms from chapter 2 of Sutton and Barto:
+// https://web.stanford.edu/class/psych209/Readings/SuttonBartoIPRLBook2ndEd.pdf
+
+pub trait BanditAgent {
+    // Agents that don't use contexts ignore them, see PerContext.
+    fn select_arm<R: Rng + ?Sized>(&mut self, context: usize, rng: &mut R) -> usize;
+    fn update(&mut self, context: usize, arm: usize, reward: f64);
+}
+
+
+#[derive(Clone, Copy, Debug)]
+pub enum StepSize {
+    SampleAverage,
+    Constant(f64),
+}
+
+#[derive(Clone, Debug)]
+pub struct EpsilonGreedyAgent {
+    pub epsilon: f64,
+    pub step_size: StepSize,
+    pub estimates: Vec<f64>,
+    counts: Vec<u32>,
+}
+
+impl EpsilonGreedyAgent {
+    pub fn sample_average(num_arms: usize, epsilon: f64) -> EpsilonGreedyAgent {
+        EpsilonGreedyAgent {
+            epsilon,
+            step_size: StepSize::SampleAverage,
+            estimates: vec![0.0; num_arms],
+            counts: vec![0; num_arms],
+        }
+    }
+
+    // Better suited to non-stationary bandits, as recent rewards weigh more.
+    pub fn constant_step_size(num_arms: usize, epsilon: f64, alpha: f64) -> EpsilonGreedyAgent {
+        EpsilonGreedyAgent {
+            step_size: StepSize::Constant(alpha),
+            ..Self::sample_average(num_arms, epsilon)
+        }
+    }
+
+    // Optimistic initial values encourage early exploration.
+    pub fn with_initial_estimate(mut self, initial_estimate: f64) -> EpsilonGreedyAgent {
+        self.estimates.fill(initial_estimate);
+        self
+    }
+}
+
+impl BanditAgent for EpsilonGreedyAgent {
+    fn select_arm<R: Rng + ?Sized>(&mut self, _context: usize, rng: &mut R) -> usize {
+        epsilon_greedy_index(&self.estimates, self.epsilon, rng)
+    }
+
+    fn update(&mut self, _context: usize, arm: usize, reward: f64) {
+        self.counts[arm] += 1;
+        let step_size = match self.step_size {
+            StepSize::SampleAverage => 1.0 / self.counts[arm] as f64,
+            StepSize::Constant(alpha) => alpha,
+        };
+        self.estimates[arm] += step_size * (reward - self.estimates[arm]);
+    }
+}
+
+
+// Upper-confidence-bound action selection, section 2.7.
+#[derive(Clone, Debug)]
+pub struct UcbAgent {
+    pub c: f64,
+    pub estimates: Vec<f64>,
+    counts: Vec<u32>,
+    time_step: u32,
+}
+
+impl UcbAgent {
+    // c = sqrt(2) gives the classic UCB1 algorithm.
+    pub fn new(num_arms: usize, c: f64) -> UcbAgent {
+        UcbAgent { c, estimates: vec![0.0; num_arms], counts: vec![0; num_arms], time_step: 0 }
+    }
+}
+
+impl BanditAgent for UcbAgent {
+    fn select_arm<R: Rng + ?Sized>(&mut self, _context: usize, rng: &mut R) -> usize {
+        self.time_step += 1;
+        let log_time = (self.time_step as f64).ln();
+        let upper_bounds: Vec<f64> = self.estimates.iter().zip(&self.counts)
+            .map(|(estimate, count)| {
+                if *count == 0 {
+                    // Untried arms are maximising
+                    f64::INFINITY
+                } else {
+                    estimate + self.c * (log_time / *count as f64).sqrt()
+                }
+            })
+            .collect();
+        greedy_index(&upper_bounds, rng)
+    }
+
+    fn update(&mut self, _context: usize, arm: usize, reward: f64) {
+        self.counts[arm] += 1;
+        self.estimates[arm] += (reward - self.estimates[arm]) / self.counts[arm] as f64;
+    }
+}
+
+
+// Gradient bandit with a softmax over action preferences, section 2.8.
+#[derive(Clone, Debug)]
+pub struct GradientBanditAgent {
+    pub alpha: f64,
+    pub use_baseline: bool,
+    pub preferences: Vec<f64>,
+    average_reward: f64,
+    time_step: u32,
+}
+
+impl GradientBanditAgent {
+    pub fn new(num_arms: usize, alpha: f64, use_baseline: bool) -> GradientBanditAgent {
+        GradientBanditAgent { alpha, use_baseline, preferences: vec![0.0; num_arms], average_reward: 0.0, time_step: 0 }
+    }
+
+    pub fn action_probabilities(&self) -> Vec<f64> {
+        softmax(&self.preferences)
+    }
+}
+
+impl BanditAgent for GradientBanditAgent {
+    fn select_arm<R: Rng + ?Sized>(&mut self, _context: usize, rng: &mut R) -> usize {
+        sample_index(&self.action_probabilities(), rng)
+    }
+
+    fn update(&mut self, _context: usize, arm: usize, reward: f64) {
+        let baseline = if self.use_baseline { self.average_reward } else { 0.0 };
+        let probabilities = self.action_probabilities();
+        for (action, preference) in self.preferences.iter_mut().enumerate() {
+            let indicator = if action == arm { 1.0 } else { 0.0 };
+            *preference += self.alpha * (reward - baseline) * (indicator - probabilities[action]);
+        }
+        self.time_step += 1;
+        self.average_reward += (reward - self.average_reward) / self.time_step as f64;
+    }
+}
+
+
+// Thompson sampling for Gaussian rewards with unit variance, using a
+// conjugate N(0, 1 / prior_precision) prior over each arm's mean.
+#[derive(Clone, Debug)]
+pub struct ThompsonSamplingAgent {
+    pub prior_precision: f64,
+    reward_sums: Vec<f64>,
+    counts: Vec<u32>,
+}
+
+impl ThompsonSamplingAgent {
+    pub fn new(num_arms: usize, prior_precision: f64) -> ThompsonSamplingAgent {
+        ThompsonSamplingAgent { prior_precision, reward_sums: vec![0.0; num_arms], counts: vec![0; num_arms] }
+    }
+
+    pub fn posterior(&self, arm: usize) -> (f64, f64) {
+        let precision = self.prior_precision + self.counts[arm] as f64;
+        let mean = self.reward_sums[arm] / precision;
+        (mean, 1.0 / precision)
+    }
+}
+
+impl BanditAgent for ThompsonSamplingAgent {
+    fn select_arm<R: Rng + ?Sized>(&mut self, _context: usize, rng: &mut R) -> usize {
+        let samples: Vec<f64> = (0..self.counts.len())
+            .map(|arm| {
+                let (mean, variance) = self.posterior(arm);
+                mean + variance.sqrt() * sample_standard_normal(rng)
+            })
+            .collect();
+        greedy_index(&samples, rng)
+    }
+
+    fn update(&mut self, _context: usize, arm: usize, reward: f64) {
+        self.counts[arm] += 1;
+        self.reward_sums[arm] += reward;
+    }
+}
+
+
+// Runs a separate copy of a context-free agent for every context.
+#[derive(Clone, Debug)]
+pub struct PerContext<A>
+where
+    A: BanditAgent
+{
+    pub agents: Vec<A>,
+}
+
+impl<A> PerContext<A>
+where
+    A: BanditAgent
+{
+    pub fn new(num_contexts: usize, make_agent: impl Fn() -> A) -> PerContext<A> {
+        PerContext { agents: (0..num_contexts).map(|_| make_agent()).collect() }
+    }
+}
+
+impl<A> BanditAgent for PerContext<A>
+where
+    A: BanditAgent
+{
+    fn select_arm<R: Rng + ?Sized>(&mut self, context: usize, rng: &mut R) -> usize {
+        self.agents[context].select_arm(context, rng)
+    }
+
+    fn update(&mut self, context: usize, arm: usize, reward: f64) {
+        self.agents[context].update(context, arm, reward);
+    }
+}
+
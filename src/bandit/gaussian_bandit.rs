@@ -0,0 +1,106 @@
+use rand::Rng;
+
+use crate::utils::sample_standard_normal;
+
+// The k-armed testbed from chapter 2 of Sutton and Barto:
+// https://web.stanford.edu/class/psych209/Readings/SuttonBartoIPRLBook2ndEd.pdf
+
+pub trait Bandit {
+    fn num_arms(&self) -> usize;
+    fn num_contexts(&self) -> usize {
+        1
+    }
+    // Draws the context the agent sees before choosing an arm.
+    fn observe_context<R: Rng + ?Sized>(&mut self, _rng: &mut R) -> usize {
+        0
+    }
+    fn pull<R: Rng + ?Sized>(&mut self, context: usize, arm: usize, rng: &mut R) -> f64;
+    fn optimal_arm(&self, context: usize) -> usize;
+}
+
+
+#[derive(Clone, Debug)]
+pub struct GaussianBandit {
+    pub means: Vec<f64>,
+    pub reward_std: f64,
+    // Standard deviation of the random walk each mean takes after every
+    // pull. Zero for a stationary bandit.
+    pub drift_std: f64,
+}
+
+impl GaussianBandit {
+    // Arm means drawn from N(0, 1), rewards from N(mean, 1).
+    pub fn stationary<R: Rng + ?Sized>(num_arms: usize, rng: &mut R) -> GaussianBandit {
+        let means = (0..num_arms).map(|_| sample_standard_normal(rng)).collect();
+        GaussianBandit { means, reward_std: 1.0, drift_std: 0.0 }
+    }
+
+    // All arm means start equal and then take independent random walks,
+    // as in exercise 2.5 of Sutton and Barto.
+    pub fn non_stationary(num_arms: usize, drift_std: f64) -> GaussianBandit {
+        GaussianBandit { means: vec![0.0; num_arms], reward_std: 1.0, drift_std }
+    }
+}
+
+impl Bandit for GaussianBandit {
+    fn num_arms(&self) -> usize {
+        self.means.len()
+    }
+
+    fn pull<R: Rng + ?Sized>(&mut self, _context: usize, arm: usize, rng: &mut R) -> f64 {
+        let reward = self.means[arm] + self.reward_std * sample_standard_normal(rng);
+        if self.drift_std > 0.0 {
+            for mean in self.means.iter_mut() {
+                *mean += self.drift_std * sample_standard_normal(rng);
+            }
+        }
+        reward
+    }
+
+    fn optimal_arm(&self, _context: usize) -> usize {
+        let mut best_arm = 0;
+        for (arm, mean) in self.means.iter().enumerate() {
+            if *mean > self.means[best_arm] {
+                best_arm = arm;
+            }
+        }
+        best_arm
+    }
+}
+
+
+// One Gaussian bandit per context, with the context drawn uniformly at
+// random and shown to the agent before each pull.
+#[derive(Clone, Debug)]
+pub struct ContextualGaussianBandit {
+    pub bandits: Vec<GaussianBandit>,
+}
+
+impl ContextualGaussianBandit {
+    pub fn stationary<R: Rng + ?Sized>(num_contexts: usize, num_arms: usize, rng: &mut R) -> ContextualGaussianBandit {
+        let bandits = (0..num_contexts).map(|_| GaussianBandit::stationary(num_arms, rng)).collect();
+        ContextualGaussianBandit { bandits }
+    }
+}
+
+impl Bandit for ContextualGaussianBandit {
+    fn num_arms(&self) -> usize {
+        self.bandits[0].num_arms()
+    }
+
+    fn num_contexts(&self) -> usize {
+        self.bandits.len()
+    }
+
+    fn observe_context<R: Rng + ?Sized>(&mut self, rng: &mut R) -> usize {
+        rng.gen_range(0..self.bandits.len())
+    }
+
+    fn pull<R: Rng + ?Sized>(&mut self, context: usize, arm: usize, rng: &mut R) -> f64 {
+        self.bandits[context].pull(0, arm, rng)
+    }
+
+    fn optimal_arm(&self, context: usize) -> usize {
+        self.bandits[context].optimal_arm(0)
+    }
+}
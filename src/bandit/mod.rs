@@ -0,0 +1,3 @@
+pub mod gaussian_bandit;
+pub mod bandit_agents;
+pub mod testbed;
@@ -0,0 +1,146 @@
+use rand::{rngs::StdRng, SeedableRng};
+
+use super::{bandit_agents::BanditAgent, gaussian_bandit::Bandit};
+
+// Averages per-step behaviour over many independent runs, each with a fresh
+// bandit and agent, as for figure 2.2 of Sutton and Barto.
+
+#[derive(Clone, Debug)]
+pub struct TestbedResults {
+    // Reward at each step, averaged over runs.
+    pub average_reward: Vec<f64>,
+    // Fraction of runs that chose the optimal arm at each step.
+    pub optimal_action: Vec<f64>,
+}
+
+impl TestbedResults {
+    pub fn mean_reward(&self) -> f64 {
+        self.average_reward.iter().sum::<f64>() / self.average_reward.len() as f64
+    }
+
+    // Fraction of optimal actions over the final `steps` steps.
+    pub fn final_optimal_action(&self, steps: usize) -> f64 {
+        let tail = &self.optimal_action[self.optimal_action.len().saturating_sub(steps)..];
+        tail.iter().sum::<f64>() / tail.len() as f64
+    }
+}
+
+// Run `num_runs` runs of `num_steps` steps each. Run i is seeded with
+// seed + i, so results are reproducible.
+pub fn run_testbed<B, A>(
+    make_bandit: impl Fn(&mut StdRng) -> B,
+    make_agent: impl Fn() -> A,
+    num_runs: u32,
+    num_steps: usize,
+    seed: u64,
+) -> TestbedResults
+where
+    B: Bandit,
+    A: BanditAgent
+{
+    let mut total_reward = vec![0.0; num_steps];
+    let mut optimal_count = vec![0_u32; num_steps];
+
+    for run in 0..num_runs {
+        let mut rng = StdRng::seed_from_u64(seed.wrapping_add(run as u64));
+        let mut bandit = make_bandit(&mut rng);
+        let mut agent = make_agent();
+
+        for step in 0..num_steps {
+            let context = bandit.observe_context(&mut rng);
+            let arm = agent.select_arm(context, &mut rng);
+            // Check before pulling, as non-stationary bandits move afterwards
+            if arm == bandit.optimal_arm(context) {
+                optimal_count[step] += 1;
+            }
+            let reward = bandit.pull(context, arm, &mut rng);
+            agent.update(context, arm, reward);
+            total_reward[step] += reward;
+        }
+    }
+
+    TestbedResults {
+        average_reward: total_reward.iter().map(|total| total / num_runs as f64).collect(),
+        optimal_action: optimal_count.iter().map(|count| *count as f64 / num_runs as f64).collect(),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::bandit::{bandit_agents::{EpsilonGreedyAgent, GradientBanditAgent, PerContext, ThompsonSamplingAgent, UcbAgent}, gaussian_bandit::{ContextualGaussianBandit, GaussianBandit}};
+
+    use super::run_testbed;
+
+    const ARMS: usize = 10;
+    const RUNS: u32 = 200;
+    const STEPS: usize = 1000;
+
+    #[test]
+    fn testbed_is_reproducible() {
+        let run = || run_testbed(
+            |rng| GaussianBandit::stationary(ARMS, rng),
+            || EpsilonGreedyAgent::sample_average(ARMS, 0.1),
+            10, 100, 7,
+        );
+        assert_eq!(run().average_reward, run().average_reward);
+    }
+
+    #[test]
+    fn greedy_agents_beat_random_choice() {
+        let random = run_testbed(
+            |rng| GaussianBandit::stationary(ARMS, rng),
+            || EpsilonGreedyAgent::sample_average(ARMS, 1.0),
+            RUNS, STEPS, 0,
+        );
+        let greedy = run_testbed(
+            |rng| GaussianBandit::stationary(ARMS, rng),
+            || EpsilonGreedyAgent::sample_average(ARMS, 0.1),
+            RUNS, STEPS, 0,
+        );
+        assert!(random.final_optimal_action(100) < 0.2);
+        assert!(greedy.final_optimal_action(100) > 0.7);
+        assert!(greedy.mean_reward() > random.mean_reward() + 0.5);
+    }
+
+    #[test]
+    fn every_agent_finds_the_optimal_arm() {
+        let ucb = run_testbed(|rng| GaussianBandit::stationary(ARMS, rng), || UcbAgent::new(ARMS, 2.0), RUNS, STEPS, 1);
+        let gradient = run_testbed(|rng| GaussianBandit::stationary(ARMS, rng), || GradientBanditAgent::new(ARMS, 0.1, true), RUNS, STEPS, 1);
+        let thompson = run_testbed(|rng| GaussianBandit::stationary(ARMS, rng), || ThompsonSamplingAgent::new(ARMS, 1.0), RUNS, STEPS, 1);
+        let optimistic = run_testbed(
+            |rng| GaussianBandit::stationary(ARMS, rng),
+            || EpsilonGreedyAgent::constant_step_size(ARMS, 0.0, 0.1).with_initial_estimate(5.0),
+            RUNS, STEPS, 1,
+        );
+        for results in [ucb, gradient, thompson, optimistic] {
+            assert!(results.final_optimal_action(100) > 0.7);
+        }
+    }
+
+    #[test]
+    fn constant_step_size_tracks_non_stationary_arms() {
+        let sample_average = run_testbed(
+            |_| GaussianBandit::non_stationary(ARMS, 0.02),
+            || EpsilonGreedyAgent::sample_average(ARMS, 0.1),
+            50, 2000, 2,
+        );
+        let constant_step_size = run_testbed(
+            |_| GaussianBandit::non_stationary(ARMS, 0.02),
+            || EpsilonGreedyAgent::constant_step_size(ARMS, 0.1, 0.1),
+            50, 2000, 2,
+        );
+        assert!(constant_step_size.final_optimal_action(500) > sample_average.final_optimal_action(500));
+    }
+
+    #[test]
+    fn per_context_agents_learn_each_context() {
+        let contexts = 4;
+        let results = run_testbed(
+            |rng| ContextualGaussianBandit::stationary(contexts, ARMS, rng),
+            || PerContext::new(contexts, || UcbAgent::new(ARMS, 2.0)),
+            RUNS, 2000, 3,
+        );
+        assert!(results.final_optimal_action(100) > 0.7);
+    }
+}
@@ -0,0 +1,276 @@
+use core::fmt;
+use rand::Rng;
+
+use crate::reinforcement_learning::generic_reinforcement_learner::{Environment, Action};
+
+// Blackjack as described in Example 5.1 of Sutton and Barto:
+// https://web.stanford.edu/class/psych209/Readings/SuttonBartoIPRLBook2ndEd.pdf
+// Cards are drawn from an infinite deck, i.e. with replacement. The player
+// always hits below 12, since no card can bust them there, so episodes start
+// from the first decision that matters. Naturals are scored like any other 21.
+
+pub const DEALER_STICKS_ON: u8 = 17;
+const BLACKJACK: u8 = 21;
+// The difference between an ace counted as 11 and as 1.
+const ACE_BONUS: u8 = 10;
+
+fn draw_card<R: Rng + ?Sized>(rng: &mut R) -> u8 {
+    // Ace counts as 1 here, face cards count as 10.
+    rng.gen_range(1..=13).min(10)
+}
+
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+#[derive(PartialEq, Eq, Hash)]
+pub struct Hand {
+    sum: u8,
+    usable_ace: bool,
+}
+
+impl Hand {
+    fn new() -> Hand {
+        Hand { sum: 0, usable_ace: false }
+    }
+
+    fn add_card(&mut self, card: u8) {
+        self.sum += card;
+        if card == 1 && !self.usable_ace && self.sum + ACE_BONUS <= BLACKJACK {
+            self.sum += ACE_BONUS;
+            self.usable_ace = true;
+        }
+        if self.sum > BLACKJACK && self.usable_ace {
+            self.sum -= ACE_BONUS;
+            self.usable_ace = false;
+        }
+    }
+
+    fn is_bust(&self) -> bool {
+        self.sum > BLACKJACK
+    }
+}
+
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+#[derive(PartialEq, Eq, Hash)]
+pub enum BlackjackOutcome {
+    PlayerWins,
+    DealerWins,
+    Draw
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug)]
+pub enum BlackjackAction {
+    Hit,
+    Stick
+}
+
+impl Action for BlackjackAction {}
+
+impl fmt::Display for BlackjackAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BlackjackAction::Hit => write!(f, "Hit"),
+            BlackjackAction::Stick => write!(f, "Stick"),
+        }
+    }
+}
+
+
+#[derive(PartialEq, Eq, Hash)]
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub struct BlackjackState {
+    player: Hand,
+    // The dealer's face-up card, with an ace counted as 1.
+    dealer_showing: u8,
+    outcome: Option<BlackjackOutcome>,
+}
+
+impl fmt::Display for BlackjackState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Player sum: {}", self.player.sum)?;
+        if self.player.usable_ace {
+            write!(f, " (usable ace)")?;
+        }
+        write!(f, ", dealer showing: {}", self.dealer_showing)?;
+        match self.outcome {
+            Some(BlackjackOutcome::PlayerWins) => write!(f, ", player wins"),
+            Some(BlackjackOutcome::DealerWins) => write!(f, ", dealer wins"),
+            Some(BlackjackOutcome::Draw) => write!(f, ", draw"),
+            None => Ok(())
+        }
+    }
+}
+
+impl Environment for BlackjackState {
+    type A = BlackjackAction;
+
+    fn initial_state() -> BlackjackState {
+        Self::sample_initial_state(&mut rand::thread_rng())
+    }
+
+    fn next_state(&self, action: &BlackjackAction) -> Self {
+        self.sample_next_state(action, &mut rand::thread_rng())
+    }
+
+    fn sample_initial_state<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        let mut player = Hand::new();
+        while player.sum < 12 {
+            player.add_card(draw_card(rng));
+        }
+        BlackjackState { player, dealer_showing: draw_card(rng), outcome: None }
+    }
+
+    fn sample_next_state<R: Rng + ?Sized>(&self, action: &BlackjackAction, rng: &mut R) -> Self {
+        let mut next = *self;
+        match action {
+            BlackjackAction::Hit => {
+                next.player.add_card(draw_card(rng));
+                if next.player.is_bust() {
+                    next.outcome = Some(BlackjackOutcome::DealerWins);
+                }
+            },
+            BlackjackAction::Stick => {
+                let dealer = Self::play_dealer(self.dealer_showing, rng);
+                next.outcome = Some(Self::compare_hands(&self.player, &dealer));
+            }
+        }
+        next
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.outcome.is_some()
+    }
+
+    fn available_actions(&self) -> Vec<BlackjackAction> {
+        if self.is_terminal() {
+            return Vec::new();
+        }
+        vec![BlackjackAction::Hit, BlackjackAction::Stick]
+    }
+
+    fn get_reward(_state: &Self, _action: &BlackjackAction, next_state: &Self) -> f64 {
+        match next_state.outcome {
+            Some(BlackjackOutcome::PlayerWins) => 1.0,
+            Some(BlackjackOutcome::DealerWins) => -1.0,
+            Some(BlackjackOutcome::Draw) | None => 0.0
+        }
+    }
+}
+
+impl BlackjackState {
+    pub fn player_sum(&self) -> u8 {
+        self.player.sum
+    }
+
+    pub fn usable_ace(&self) -> bool {
+        self.player.usable_ace
+    }
+
+    pub fn dealer_showing(&self) -> u8 {
+        self.dealer_showing
+    }
+
+    pub fn outcome(&self) -> Option<BlackjackOutcome> {
+        self.outcome
+    }
+
+    // The dealer turns over their hidden card and hits until reaching DEALER_STICKS_ON.
+    fn play_dealer<R: Rng + ?Sized>(showing: u8, rng: &mut R) -> Hand {
+        let mut dealer = Hand::new();
+        dealer.add_card(showing);
+        dealer.add_card(draw_card(rng));
+        while dealer.sum < DEALER_STICKS_ON {
+            dealer.add_card(draw_card(rng));
+        }
+        dealer
+    }
+
+    fn compare_hands(player: &Hand, dealer: &Hand) -> BlackjackOutcome {
+        if dealer.is_bust() || player.sum > dealer.sum {
+            BlackjackOutcome::PlayerWins
+        } else if player.sum < dealer.sum {
+            BlackjackOutcome::DealerWins
+        } else {
+            BlackjackOutcome::Draw
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::reinforcement_learning::generic_reinforcement_learner::Environment;
+
+    use super::{BlackjackAction, BlackjackOutcome, BlackjackState, Hand, DEALER_STICKS_ON};
+
+    #[test]
+    fn hand_counts_aces_as_eleven_while_usable() {
+        let mut hand = Hand::new();
+        hand.add_card(1);
+        hand.add_card(6);
+        assert_eq!(hand, Hand { sum: 17, usable_ace: true });
+
+        hand.add_card(10);
+        assert_eq!(hand, Hand { sum: 17, usable_ace: false });
+
+        hand.add_card(1);
+        assert_eq!(hand, Hand { sum: 18, usable_ace: false });
+    }
+
+    #[test]
+    fn blackjack_episodes_start_at_twelve_or_more() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..1000 {
+            let state = BlackjackState::sample_initial_state(&mut rng);
+            assert!((12..=21).contains(&state.player_sum()));
+            assert!((1..=10).contains(&state.dealer_showing()));
+            assert!(!state.is_terminal());
+        }
+    }
+
+    #[test]
+    fn blackjack_stick_ends_the_episode() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..1000 {
+            let state = BlackjackState::sample_initial_state(&mut rng);
+            let next_state = state.sample_next_state(&BlackjackAction::Stick, &mut rng);
+            assert!(next_state.is_terminal());
+            let reward = BlackjackState::get_reward(&state, &BlackjackAction::Stick, &next_state);
+            match next_state.outcome() {
+                Some(BlackjackOutcome::PlayerWins) => assert_eq!(reward, 1.0),
+                Some(BlackjackOutcome::DealerWins) => assert_eq!(reward, -1.0),
+                Some(BlackjackOutcome::Draw) => assert_eq!(reward, 0.0),
+                None => unreachable!(),
+            }
+        }
+    }
+
+    #[test]
+    fn blackjack_dealer_hits_until_seventeen() {
+        let mut rng = StdRng::seed_from_u64(2);
+        for showing in 1..=10 {
+            let dealer = BlackjackState::play_dealer(showing, &mut rng);
+            assert!(dealer.sum >= DEALER_STICKS_ON);
+        }
+    }
+
+    #[test]
+    fn blackjack_same_seed_gives_same_episode() {
+        let play = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut state = BlackjackState::sample_initial_state(&mut rng);
+            let mut states = vec![state];
+            while !state.is_terminal() {
+                let action = if state.player_sum() < 18 { BlackjackAction::Hit } else { BlackjackAction::Stick };
+                state = state.sample_next_state(&action, &mut rng);
+                states.push(state);
+            }
+            states
+        };
+        assert_eq!(play(3), play(3));
+    }
+}
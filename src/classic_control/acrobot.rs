@@ -0,0 +1,238 @@
+use core::fmt;
+use std::f64::consts::PI;
+use rand::Rng;
+
+use crate::reinforcement_learning::{discretised_state::Discretised, generic_reinforcement_learner::{Action, ContinuousState, Environment, IndexedActions}};
+
+// The acrobot swing-up task from section 11.3 of the first edition of Sutton
+// and Barto, with the constants and 500 step limit of OpenAI Gym's Acrobot-v1.
+// The two links are swung by applying torque at the joint between them, and
+// the episode ends once the tip swings one link length above the shoulder.
+
+const LINK_LENGTH: f64 = 1.0;
+const LINK_MASS: f64 = 1.0;
+// Position of each link's centre of mass along it
+const LINK_CENTRE_OF_MASS: f64 = 0.5;
+const LINK_MOMENT_OF_INERTIA: f64 = 1.0;
+const GRAVITY: f64 = 9.8;
+pub const MAX_VELOCITY_1: f64 = 4.0 * PI;
+pub const MAX_VELOCITY_2: f64 = 9.0 * PI;
+// Seconds between state updates
+const TIME_STEP: f64 = 0.2;
+pub const MAX_STEPS: u32 = 500;
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug)]
+pub enum AcrobotAction {
+    NegativeTorque,
+    NoTorque,
+    PositiveTorque
+}
+
+impl Action for AcrobotAction {}
+
+impl fmt::Display for AcrobotAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AcrobotAction::NegativeTorque => write!(f, "-1"),
+            AcrobotAction::NoTorque => write!(f, "0"),
+            AcrobotAction::PositiveTorque => write!(f, "+1"),
+        }
+    }
+}
+
+
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub struct Acrobot {
+    // Angle of the first link from hanging straight down
+    pub angle_1: f64,
+    // Angle of the second link relative to the first
+    pub angle_2: f64,
+    pub velocity_1: f64,
+    pub velocity_2: f64,
+    pub steps: u32,
+}
+
+pub type DiscreteAcrobot = Discretised<Acrobot>;
+
+impl fmt::Display for Acrobot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f, "angles: ({:.3}, {:.3}), velocities: ({:.3}, {:.3}), step: {}",
+            self.angle_1, self.angle_2, self.velocity_1, self.velocity_2, self.steps
+        )
+    }
+}
+
+impl Environment for Acrobot {
+    type A = AcrobotAction;
+
+    fn initial_state() -> Self {
+        Self::sample_initial_state(&mut rand::thread_rng())
+    }
+
+    fn sample_initial_state<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        Acrobot {
+            angle_1: rng.gen_range(-0.1..0.1),
+            angle_2: rng.gen_range(-0.1..0.1),
+            velocity_1: rng.gen_range(-0.1..0.1),
+            velocity_2: rng.gen_range(-0.1..0.1),
+            steps: 0,
+        }
+    }
+
+    fn next_state(&self, action: &AcrobotAction) -> Self {
+        let torque = match action {
+            AcrobotAction::NegativeTorque => -1.0,
+            AcrobotAction::NoTorque => 0.0,
+            AcrobotAction::PositiveTorque => 1.0,
+        };
+        let state = [self.angle_1, self.angle_2, self.velocity_1, self.velocity_2];
+        let [angle_1, angle_2, velocity_1, velocity_2] = Self::runge_kutta_step(state, torque);
+        Acrobot {
+            angle_1: wrap_angle(angle_1),
+            angle_2: wrap_angle(angle_2),
+            velocity_1: velocity_1.clamp(-MAX_VELOCITY_1, MAX_VELOCITY_1),
+            velocity_2: velocity_2.clamp(-MAX_VELOCITY_2, MAX_VELOCITY_2),
+            steps: self.steps + 1,
+        }
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.reached_goal() || self.steps >= MAX_STEPS
+    }
+
+    fn available_actions(&self) -> Vec<AcrobotAction> {
+        if self.is_terminal() {
+            return Vec::new();
+        }
+        vec![AcrobotAction::NegativeTorque, AcrobotAction::NoTorque, AcrobotAction::PositiveTorque]
+    }
+
+    // Minus one for every step that doesn't reach the goal.
+    fn get_reward(_state: &Self, _action: &AcrobotAction, next_state: &Self) -> f64 {
+        if next_state.reached_goal() { 0.0 } else { -1.0 }
+    }
+}
+
+impl ContinuousState for Acrobot {
+    fn observation(&self) -> Vec<f64> {
+        vec![self.angle_1, self.angle_2, self.velocity_1, self.velocity_2]
+    }
+
+    fn observation_bounds() -> Vec<(f64, f64)> {
+        vec![(-PI, PI), (-PI, PI), (-MAX_VELOCITY_1, MAX_VELOCITY_1), (-MAX_VELOCITY_2, MAX_VELOCITY_2)]
+    }
+}
+
+impl IndexedActions for Acrobot {
+    fn num_actions() -> usize {
+        3
+    }
+
+    fn action_index(action: &AcrobotAction) -> usize {
+        match action {
+            AcrobotAction::NegativeTorque => 0,
+            AcrobotAction::NoTorque => 1,
+            AcrobotAction::PositiveTorque => 2,
+        }
+    }
+}
+
+impl Acrobot {
+    pub fn tip_height(&self) -> f64 {
+        -LINK_LENGTH * self.angle_1.cos() - LINK_LENGTH * (self.angle_1 + self.angle_2).cos()
+    }
+
+    pub fn reached_goal(&self) -> bool {
+        self.tip_height() > LINK_LENGTH
+    }
+
+    // Time derivative of [angle_1, angle_2, velocity_1, velocity_2], using the
+    // equations of motion from the book.
+    fn derivatives(state: [f64; 4], torque: f64) -> [f64; 4] {
+        let [angle_1, angle_2, velocity_1, velocity_2] = state;
+        let (m, l, lc, i) = (LINK_MASS, LINK_LENGTH, LINK_CENTRE_OF_MASS, LINK_MOMENT_OF_INERTIA);
+        let d1 = m * lc.powi(2) + m * (l.powi(2) + lc.powi(2) + 2.0 * l * lc * angle_2.cos()) + 2.0 * i;
+        let d2 = m * (lc.powi(2) + l * lc * angle_2.cos()) + i;
+        let phi2 = m * lc * GRAVITY * (angle_1 + angle_2 - PI / 2.0).cos();
+        let phi1 = -m * l * lc * velocity_2.powi(2) * angle_2.sin()
+            - 2.0 * m * l * lc * velocity_2 * velocity_1 * angle_2.sin()
+            + (m * lc + m * l) * GRAVITY * (angle_1 - PI / 2.0).cos()
+            + phi2;
+        let acceleration_2 = (torque + d2 / d1 * phi1 - m * l * lc * velocity_1.powi(2) * angle_2.sin() - phi2)
+            / (m * lc.powi(2) + i - d2.powi(2) / d1);
+        let acceleration_1 = -(d2 * acceleration_2 + phi1) / d1;
+        [velocity_1, velocity_2, acceleration_1, acceleration_2]
+    }
+
+    // Fourth-order Runge-Kutta integration over one time step.
+    fn runge_kutta_step(state: [f64; 4], torque: f64) -> [f64; 4] {
+        let offset = |state: [f64; 4], derivative: [f64; 4], scale: f64| -> [f64; 4] {
+            let mut result = state;
+            for (value, change) in result.iter_mut().zip(derivative) {
+                *value += scale * change;
+            }
+            result
+        };
+        let k1 = Self::derivatives(state, torque);
+        let k2 = Self::derivatives(offset(state, k1, TIME_STEP / 2.0), torque);
+        let k3 = Self::derivatives(offset(state, k2, TIME_STEP / 2.0), torque);
+        let k4 = Self::derivatives(offset(state, k3, TIME_STEP), torque);
+        let mut result = state;
+        for (index, value) in result.iter_mut().enumerate() {
+            *value += TIME_STEP / 6.0 * (k1[index] + 2.0 * k2[index] + 2.0 * k3[index] + k4[index]);
+        }
+        result
+    }
+}
+
+fn wrap_angle(angle: f64) -> f64 {
+    (angle + PI).rem_euclid(2.0 * PI) - PI
+}
+
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use crate::reinforcement_learning::generic_reinforcement_learner::{ContinuousState, Environment};
+
+    use super::{Acrobot, AcrobotAction, MAX_STEPS};
+
+    #[test]
+    fn acrobot_hanging_still_stays_down() {
+        let mut state = Acrobot { angle_1: 0.0, angle_2: 0.0, velocity_1: 0.0, velocity_2: 0.0, steps: 0 };
+        while !state.is_terminal() {
+            state = state.next_state(&AcrobotAction::NoTorque);
+        }
+        assert!(!state.reached_goal());
+        assert_eq!(state.steps, MAX_STEPS);
+        assert!(state.angle_1.abs() < 1e-9 && state.angle_2.abs() < 1e-9);
+    }
+
+    #[test]
+    fn acrobot_stays_within_its_bounds() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut state = Acrobot::sample_initial_state(&mut rng);
+        while !state.is_terminal() {
+            let actions = state.available_actions();
+            state = state.next_state(&actions[rng.gen_range(0..actions.len())]);
+            for (value, (low, high)) in state.observation().iter().zip(Acrobot::observation_bounds()) {
+                assert!(low <= *value && *value <= high);
+            }
+        }
+    }
+
+    #[test]
+    fn acrobot_swings_up_by_pumping_energy() {
+        let mut state = Acrobot { angle_1: 0.0, angle_2: 0.0, velocity_1: 0.0, velocity_2: 0.0, steps: 0 };
+        while !state.is_terminal() {
+            // Torque in the direction the second link is swinging
+            let action = if state.velocity_2 >= 0.0 { AcrobotAction::PositiveTorque } else { AcrobotAction::NegativeTorque };
+            state = state.next_state(&action);
+        }
+        assert!(state.reached_goal());
+    }
+}
@@ -0,0 +1,192 @@
+use core::fmt;
+use rand::Rng;
+
+use crate::reinforcement_learning::{discretised_state::Discretised, generic_reinforcement_learner::{Action, ContinuousState, Environment, IndexedActions}};
+
+// The cart-pole balancing task of Barto, Sutton and Anderson (1983), with
+// the constants and limits used by OpenAI Gym's CartPole-v1.
+
+const GRAVITY: f64 = 9.8;
+const CART_MASS: f64 = 1.0;
+const POLE_MASS: f64 = 0.1;
+const TOTAL_MASS: f64 = CART_MASS + POLE_MASS;
+// Half the length of the pole
+const POLE_HALF_LENGTH: f64 = 0.5;
+const POLE_MASS_LENGTH: f64 = POLE_MASS * POLE_HALF_LENGTH;
+const FORCE: f64 = 10.0;
+// Seconds between state updates
+const TIME_STEP: f64 = 0.02;
+
+pub const X_LIMIT: f64 = 2.4;
+pub const ANGLE_LIMIT: f64 = 12.0 * 2.0 * std::f64::consts::PI / 360.0;
+pub const MAX_STEPS: u32 = 500;
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug)]
+pub enum CartPoleAction {
+    Left,
+    Right
+}
+
+impl Action for CartPoleAction {}
+
+impl fmt::Display for CartPoleAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CartPoleAction::Left => write!(f, "Left"),
+            CartPoleAction::Right => write!(f, "Right"),
+        }
+    }
+}
+
+
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub struct CartPole {
+    pub x: f64,
+    pub x_velocity: f64,
+    pub angle: f64,
+    pub angular_velocity: f64,
+    pub steps: u32,
+}
+
+pub type DiscreteCartPole = Discretised<CartPole>;
+
+impl fmt::Display for CartPole {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f, "x: {:.3}, x velocity: {:.3}, angle: {:.3}, angular velocity: {:.3}, step: {}",
+            self.x, self.x_velocity, self.angle, self.angular_velocity, self.steps
+        )
+    }
+}
+
+impl Environment for CartPole {
+    type A = CartPoleAction;
+
+    fn initial_state() -> Self {
+        Self::sample_initial_state(&mut rand::thread_rng())
+    }
+
+    fn sample_initial_state<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        CartPole {
+            x: rng.gen_range(-0.05..0.05),
+            x_velocity: rng.gen_range(-0.05..0.05),
+            angle: rng.gen_range(-0.05..0.05),
+            angular_velocity: rng.gen_range(-0.05..0.05),
+            steps: 0,
+        }
+    }
+
+    fn next_state(&self, action: &CartPoleAction) -> Self {
+        let force = match action {
+            CartPoleAction::Left => -FORCE,
+            CartPoleAction::Right => FORCE,
+        };
+        let (sin, cos) = self.angle.sin_cos();
+        let temp = (force + POLE_MASS_LENGTH * self.angular_velocity.powi(2) * sin) / TOTAL_MASS;
+        let angular_acceleration = (GRAVITY * sin - cos * temp)
+            / (POLE_HALF_LENGTH * (4.0 / 3.0 - POLE_MASS * cos.powi(2) / TOTAL_MASS));
+        let x_acceleration = temp - POLE_MASS_LENGTH * angular_acceleration * cos / TOTAL_MASS;
+
+        // Euler integration
+        CartPole {
+            x: self.x + TIME_STEP * self.x_velocity,
+            x_velocity: self.x_velocity + TIME_STEP * x_acceleration,
+            angle: self.angle + TIME_STEP * self.angular_velocity,
+            angular_velocity: self.angular_velocity + TIME_STEP * angular_acceleration,
+            steps: self.steps + 1,
+        }
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.has_fallen() || self.steps >= MAX_STEPS
+    }
+
+    fn available_actions(&self) -> Vec<CartPoleAction> {
+        if self.is_terminal() {
+            return Vec::new();
+        }
+        vec![CartPoleAction::Left, CartPoleAction::Right]
+    }
+
+    // One point for every step the pole stays up.
+    fn get_reward(_state: &Self, _action: &CartPoleAction, _next_state: &Self) -> f64 {
+        1.0
+    }
+}
+
+impl ContinuousState for CartPole {
+    fn observation(&self) -> Vec<f64> {
+        vec![self.x, self.x_velocity, self.angle, self.angular_velocity]
+    }
+
+    // The velocities are unbounded, so these are the range they normally stay in.
+    fn observation_bounds() -> Vec<(f64, f64)> {
+        vec![(-X_LIMIT, X_LIMIT), (-3.0, 3.0), (-ANGLE_LIMIT, ANGLE_LIMIT), (-3.5, 3.5)]
+    }
+}
+
+impl IndexedActions for CartPole {
+    fn num_actions() -> usize {
+        2
+    }
+
+    fn action_index(action: &CartPoleAction) -> usize {
+        match action {
+            CartPoleAction::Left => 0,
+            CartPoleAction::Right => 1,
+        }
+    }
+}
+
+impl CartPole {
+    pub fn has_fallen(&self) -> bool {
+        self.x.abs() > X_LIMIT || self.angle.abs() > ANGLE_LIMIT
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::reinforcement_learning::{epsilon_greedy_policy::EpsilonGreedyPolicy, generic_reinforcement_learner::Environment, q_learning_learner::QLearner};
+
+    use super::{CartPole, CartPoleAction, DiscreteCartPole, MAX_STEPS};
+
+    #[test]
+    fn cart_pole_falls_when_always_pushed_one_way() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut state = CartPole::sample_initial_state(&mut rng);
+        let mut total_reward = 0.0;
+        while !state.is_terminal() {
+            let next_state = state.next_state(&CartPoleAction::Right);
+            total_reward += CartPole::get_reward(&state, &CartPoleAction::Right, &next_state);
+            state = next_state;
+        }
+        assert!(state.has_fallen());
+        assert!(state.steps < 50);
+        assert_eq!(total_reward, state.steps as f64);
+    }
+
+    #[test]
+    fn cart_pole_episodes_end_at_the_step_limit() {
+        // Push against the way the pole is falling
+        let mut state = CartPole { x: 0.0, x_velocity: 0.0, angle: 0.0, angular_velocity: 0.0, steps: 0 };
+        while !state.is_terminal() {
+            let action = if state.angle + state.angular_velocity > 0.0 { CartPoleAction::Right } else { CartPoleAction::Left };
+            state = state.next_state(&action);
+        }
+        assert!(!state.has_fallen());
+        assert_eq!(state.steps, MAX_STEPS);
+    }
+
+    #[test]
+    fn q_learner_trains_on_discretised_cart_pole() {
+        let mut q_learner: QLearner<DiscreteCartPole> = QLearner::new(0.1, 0.99);
+        let policy = EpsilonGreedyPolicy::new(0.1);
+        q_learner.q_learning(&policy, 100);
+        assert!(!q_learner.q_values.is_empty());
+    }
+}
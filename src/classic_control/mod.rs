@@ -0,0 +1,3 @@
+pub mod cart_pole;
+pub mod mountain_car;
+pub mod acrobot;
@@ -0,0 +1,172 @@
+use core::fmt;
+use rand::Rng;
+
+use crate::reinforcement_learning::{discretised_state::Discretised, generic_reinforcement_learner::{Action, ContinuousState, Environment, IndexedActions}};
+
+// The mountain car task from section 10.1 of Sutton and Barto:
+// https://web.stanford.edu/class/psych209/Readings/SuttonBartoIPRLBook2ndEd.pdf
+// with the 200 step limit used by OpenAI Gym's MountainCar-v0.
+
+pub const MIN_POSITION: f64 = -1.2;
+pub const MAX_POSITION: f64 = 0.6;
+pub const MAX_SPEED: f64 = 0.07;
+pub const GOAL_POSITION: f64 = 0.5;
+pub const MAX_STEPS: u32 = 200;
+const POWER: f64 = 0.001;
+const GRAVITY: f64 = 0.0025;
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug)]
+pub enum MountainCarAction {
+    Left,
+    Coast,
+    Right
+}
+
+impl Action for MountainCarAction {}
+
+impl fmt::Display for MountainCarAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MountainCarAction::Left => write!(f, "Left"),
+            MountainCarAction::Coast => write!(f, "Coast"),
+            MountainCarAction::Right => write!(f, "Right"),
+        }
+    }
+}
+
+
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub struct MountainCar {
+    pub position: f64,
+    pub velocity: f64,
+    pub steps: u32,
+}
+
+pub type DiscreteMountainCar = Discretised<MountainCar>;
+
+impl fmt::Display for MountainCar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "position: {:.3}, velocity: {:.4}, step: {}", self.position, self.velocity, self.steps)
+    }
+}
+
+impl Environment for MountainCar {
+    type A = MountainCarAction;
+
+    fn initial_state() -> Self {
+        Self::sample_initial_state(&mut rand::thread_rng())
+    }
+
+    fn sample_initial_state<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        MountainCar { position: rng.gen_range(-0.6..-0.4), velocity: 0.0, steps: 0 }
+    }
+
+    fn next_state(&self, action: &MountainCarAction) -> Self {
+        let throttle = match action {
+            MountainCarAction::Left => -1.0,
+            MountainCarAction::Coast => 0.0,
+            MountainCarAction::Right => 1.0,
+        };
+        let mut velocity = self.velocity + throttle * POWER - GRAVITY * (3.0 * self.position).cos();
+        velocity = velocity.clamp(-MAX_SPEED, MAX_SPEED);
+        let position = (self.position + velocity).clamp(MIN_POSITION, MAX_POSITION);
+        if position == MIN_POSITION && velocity < 0.0 {
+            // The car hits the wall on the left and stops
+            velocity = 0.0;
+        }
+        MountainCar { position, velocity, steps: self.steps + 1 }
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.reached_goal() || self.steps >= MAX_STEPS
+    }
+
+    fn available_actions(&self) -> Vec<MountainCarAction> {
+        if self.is_terminal() {
+            return Vec::new();
+        }
+        vec![MountainCarAction::Left, MountainCarAction::Coast, MountainCarAction::Right]
+    }
+
+    // Minus one for every step until the goal is reached.
+    fn get_reward(_state: &Self, _action: &MountainCarAction, _next_state: &Self) -> f64 {
+        -1.0
+    }
+}
+
+impl ContinuousState for MountainCar {
+    fn observation(&self) -> Vec<f64> {
+        vec![self.position, self.velocity]
+    }
+
+    fn observation_bounds() -> Vec<(f64, f64)> {
+        vec![(MIN_POSITION, MAX_POSITION), (-MAX_SPEED, MAX_SPEED)]
+    }
+}
+
+impl IndexedActions for MountainCar {
+    fn num_actions() -> usize {
+        3
+    }
+
+    fn action_index(action: &MountainCarAction) -> usize {
+        match action {
+            MountainCarAction::Left => 0,
+            MountainCarAction::Coast => 1,
+            MountainCarAction::Right => 2,
+        }
+    }
+}
+
+impl MountainCar {
+    pub fn reached_goal(&self) -> bool {
+        self.position >= GOAL_POSITION
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::reinforcement_learning::generic_reinforcement_learner::Environment;
+
+    use super::{MountainCar, MountainCarAction, MAX_STEPS, MIN_POSITION};
+
+    fn run_episode(policy: impl Fn(&MountainCar) -> MountainCarAction) -> (MountainCar, f64) {
+        let mut state = MountainCar { position: -0.5, velocity: 0.0, steps: 0 };
+        let mut total_reward = 0.0;
+        while !state.is_terminal() {
+            let action = policy(&state);
+            let next_state = state.next_state(&action);
+            total_reward += MountainCar::get_reward(&state, &action, &next_state);
+            state = next_state;
+        }
+        (state, total_reward)
+    }
+
+    #[test]
+    fn mountain_car_cannot_drive_straight_up() {
+        let (state, total_reward) = run_episode(|_| MountainCarAction::Right);
+        assert!(!state.reached_goal());
+        assert_eq!(state.steps, MAX_STEPS);
+        assert_eq!(total_reward, -(MAX_STEPS as f64));
+    }
+
+    #[test]
+    fn mountain_car_reaches_the_goal_by_building_momentum() {
+        let (state, total_reward) = run_episode(|state| {
+            if state.velocity < 0.0 { MountainCarAction::Left } else { MountainCarAction::Right }
+        });
+        assert!(state.reached_goal());
+        assert!(total_reward > -(MAX_STEPS as f64));
+    }
+
+    #[test]
+    fn mountain_car_stops_at_the_left_wall() {
+        let state = MountainCar { position: MIN_POSITION + 0.01, velocity: -0.05, steps: 0 };
+        let next_state = state.next_state(&MountainCarAction::Left);
+        assert_eq!(next_state.position, MIN_POSITION);
+        assert_eq!(next_state.velocity, 0.0);
+    }
+}
@@ -0,0 +1,626 @@
+use core::fmt;
+use std::io::{self, BufRead};
+use rand::Rng;
+
+use crate::{human_play::{format_value, parse_command, HumanInput, TwoPlayerGame}, reinforcement_learning::{generic_reinforcement_learner::{Environment, Action, IndexedActions, Symmetric, TensorEncoding, TextEncoding}}, utils::prompt};
+
+pub const COLUMNS: usize = 7;
+pub const ROWS: usize = 6;
+
+// Each column takes ROWS + 1 bits, the extra bit being an always-empty
+// sentinel so that shifted lines never wrap from one column into the next.
+const COLUMN_HEIGHT: usize = ROWS + 1;
+const BOTTOM_ROW: u64 = {
+    let mut mask = 0;
+    let mut column = 0;
+    while column < COLUMNS {
+        mask |= 1 << (column * COLUMN_HEIGHT);
+        column += 1;
+    }
+    mask
+};
+const FULL_BOARD: u64 = BOTTOM_ROW * ((1 << ROWS) - 1);
+
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+#[derive(PartialEq, Eq, Hash)]
+pub enum Disc {
+    Empty,
+    Red,
+    Yellow
+}
+
+impl fmt::Display for Disc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Disc::Empty => write!(f, " "),
+            Disc::Red => write!(f, "R"),
+            Disc::Yellow => write!(f, "Y"),
+        }
+    }
+}
+
+impl TryFrom<char> for Disc {
+    type Error = String;
+
+    fn try_from(char: char) -> Result<Self, Self::Error> {
+        match char {
+            'R' => Ok(Disc::Red),
+            'Y' => Ok(Disc::Yellow),
+            ' ' => Ok(Disc::Empty),
+            _ => Err(format!("Invalid char {}", char))
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug)]
+pub struct ConnectFourMove {
+    column: usize,
+}
+
+impl ConnectFourMove {
+    pub fn new(column: usize) -> ConnectFourMove {
+        ConnectFourMove { column }
+    }
+
+    pub fn column(&self) -> usize {
+        self.column
+    }
+}
+
+impl Action for ConnectFourMove {}
+
+impl fmt::Display for ConnectFourMove {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.column)
+    }
+}
+
+
+/*
+ Board struct
+*/
+#[derive(PartialEq, Eq, Hash)]
+#[derive(Clone, Copy)]
+pub struct ConnectFourBoard {
+    /*
+    * One bitboard per player. Bit (column * 7 + row) is set when that player
+    * has a disc in the cell, with row 0 at the bottom of the board.
+    */
+    red: u64,
+    yellow: u64,
+    pub current_player: Disc,
+}
+
+impl fmt::Display for ConnectFourBoard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.grid(3))
+    }
+}
+
+impl Environment for ConnectFourBoard {
+    type A = ConnectFourMove;
+
+    fn initial_state() -> ConnectFourBoard {
+        Self::sample_initial_state(&mut rand::thread_rng())
+    }
+
+    fn sample_initial_state<R: Rng + ?Sized>(rng: &mut R) -> ConnectFourBoard {
+        let who_starts = rng.gen_range(1..=2);
+        let player = if who_starts == 1 {
+            Disc::Red
+        } else {
+            Disc::Yellow
+        };
+        ConnectFourBoard { red: 0, yellow: 0, current_player: player }
+    }
+
+    fn next_state(&self, action: &ConnectFourMove) -> Self {
+        let mut clone = *self;
+        clone.drop_disc(action.column, self.current_player);
+        clone.change_player();
+        clone
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.has_someone_won().is_some()
+    }
+
+    fn alternates_players() -> bool {
+        true
+    }
+
+    fn available_actions(&self) -> Vec<ConnectFourMove> {
+        (0..COLUMNS)
+            .map(ConnectFourMove::new)
+            .filter(|this_move| self.is_valid_move(*this_move))
+            .collect()
+    }
+
+    fn get_reward(state: &Self, _action: &ConnectFourMove, next_state: &Self) -> f64 {
+        match next_state.has_someone_won() {
+            Some(disc) if disc == state.current_player => 1.0,
+            _ => 0.0
+        }
+    }
+}
+
+
+impl TryFrom<String> for ConnectFourBoard {
+    type Error = String;
+
+    /*
+    * Reads the board row by row from the top, as it is displayed.
+    * Discs must rest on the bottom or on top of another disc.
+    */
+    fn try_from(str: String) -> Result<Self, Self::Error> {
+        let chars: Vec<char> = str.chars().collect();
+        if chars.len() != COLUMNS * ROWS {
+            return Err("Wrong length".to_string())
+        }
+
+        let mut board = ConnectFourBoard::new();
+        for row in 0..ROWS {
+            for column in 0..COLUMNS {
+                let char = chars[(ROWS - 1 - row) * COLUMNS + column];
+                match Disc::try_from(char)? {
+                    Disc::Empty => {},
+                    disc => {
+                        if board.column_height(column) != row {
+                            return Err(format!("Floating disc in column {column}"));
+                        }
+                        board.drop_disc(column, disc);
+                    }
+                }
+            }
+        }
+        Ok(board)
+    }
+}
+
+// The rows from the top, as read by TryFrom<String>, followed by the player
+// to move.
+impl TextEncoding for ConnectFourBoard {
+    fn to_text(&self) -> String {
+        let mut text = String::new();
+        for row in (0..ROWS).rev() {
+            for column in 0..COLUMNS {
+                text.push_str(&self.get(column, row).to_string());
+            }
+        }
+        text.push_str(&self.current_player.to_string());
+        text
+    }
+
+    fn from_text(text: &str) -> Result<Self, String> {
+        let mut chars: Vec<char> = text.chars().collect();
+        let current_player = match chars.pop().map(Disc::try_from) {
+            Some(Ok(Disc::Empty)) | None => return Err("Missing player to move".to_string()),
+            Some(player) => player?,
+        };
+        let mut board = Self::try_from(chars.into_iter().collect::<String>())?;
+        board.current_player = current_player;
+        Ok(board)
+    }
+}
+
+impl IndexedActions for ConnectFourBoard {
+    fn num_actions() -> usize {
+        COLUMNS
+    }
+
+    fn action_index(action: &ConnectFourMove) -> usize {
+        action.column
+    }
+}
+
+impl TensorEncoding for ConnectFourBoard {
+    fn encoding_len() -> usize {
+        2 * COLUMNS * ROWS
+    }
+
+    // One plane marking the current player's discs followed by one marking
+    // the opponent's, each column by column from the bottom.
+    fn encode(&self) -> Vec<f64> {
+        let (own, opponent) = match self.current_player {
+            Disc::Red => (self.red, self.yellow),
+            _ => (self.yellow, self.red),
+        };
+        let mut encoding = vec![0.0; 2 * COLUMNS * ROWS];
+        for column in 0..COLUMNS {
+            for row in 0..ROWS {
+                let bit = Self::translate_coords_to_bit(column, row);
+                let position = column * ROWS + row;
+                if own & bit != 0 {
+                    encoding[position] = 1.0;
+                } else if opponent & bit != 0 {
+                    encoding[COLUMNS * ROWS + position] = 1.0;
+                }
+            }
+        }
+        encoding
+    }
+}
+
+// The board is symmetric under mirroring its columns.
+impl Symmetric for ConnectFourBoard {
+    fn num_symmetries() -> usize {
+        2
+    }
+
+    fn transform(&self, symmetry: usize) -> Self {
+        if symmetry == 0 {
+            return *self;
+        }
+        ConnectFourBoard { red: Self::mirror(self.red), yellow: Self::mirror(self.yellow), current_player: self.current_player }
+    }
+
+    fn transform_action(action: &ConnectFourMove, symmetry: usize) -> ConnectFourMove {
+        if symmetry == 0 { *action } else { ConnectFourMove::new(COLUMNS - 1 - action.column) }
+    }
+
+    fn inverse_symmetry(symmetry: usize) -> usize {
+        symmetry
+    }
+
+    fn canonical_symmetry(&self) -> usize {
+        let mirrored = self.transform(1);
+        if (mirrored.red, mirrored.yellow) < (self.red, self.yellow) { 1 } else { 0 }
+    }
+}
+
+impl ConnectFourBoard {
+    fn new() -> ConnectFourBoard {
+        ConnectFourBoard::initial_state()
+    }
+
+    // The board with every cell centred in `width` characters.
+    fn grid(&self, width: usize) -> String {
+        let border = format!("+{}\n", format!("{}+", "-".repeat(width)).repeat(COLUMNS));
+        let mut string = String::from("|");
+        for column in 0..COLUMNS {
+            string.push_str(format!("{column:^width$}|").as_str());
+        }
+        string.push('\n');
+        string.push_str(border.as_str());
+        for row in (0..ROWS).rev() {
+            string.push('|');
+            for column in 0..COLUMNS {
+                string.push_str(format!("{:^width$}|", self.get(column, row).to_string()).as_str());
+            }
+            string.push('\n');
+        }
+        string.push_str(border.as_str());
+        string
+    }
+
+    fn translate_coords_to_bit(column: usize, row: usize) -> u64 {
+        1 << (column * COLUMN_HEIGHT + row)
+    }
+
+    fn mirror(discs: u64) -> u64 {
+        let column_mask = (1 << COLUMN_HEIGHT) - 1;
+        (0..COLUMNS).fold(0, |mirrored, column| {
+            let bits = (discs >> (column * COLUMN_HEIGHT)) & column_mask;
+            mirrored | bits << ((COLUMNS - 1 - column) * COLUMN_HEIGHT)
+        })
+    }
+
+    fn occupied(&self) -> u64 {
+        self.red | self.yellow
+    }
+
+    pub fn get(&self, column: usize, row: usize) -> Disc {
+        let bit = Self::translate_coords_to_bit(column, row);
+        if self.red & bit != 0 {
+            Disc::Red
+        } else if self.yellow & bit != 0 {
+            Disc::Yellow
+        } else {
+            Disc::Empty
+        }
+    }
+
+    fn column_height(&self, column: usize) -> usize {
+        let column_bits = (self.occupied() >> (column * COLUMN_HEIGHT)) & ((1 << ROWS) - 1);
+        column_bits.trailing_ones() as usize
+    }
+
+    fn drop_disc(&mut self, column: usize, disc: Disc) {
+        let bit = Self::translate_coords_to_bit(column, self.column_height(column));
+        match disc {
+            Disc::Red => self.red |= bit,
+            Disc::Yellow => self.yellow |= bit,
+            Disc::Empty => panic!("Cannot drop an empty disc"),
+        }
+    }
+
+    pub fn is_valid_move(&self, action: ConnectFourMove) -> bool {
+        action.column < COLUMNS && self.column_height(action.column) < ROWS
+    }
+
+    fn change_player(&mut self) {
+        match self.current_player {
+            Disc::Red => self.current_player = Disc::Yellow,
+            Disc::Yellow => self.current_player = Disc::Red,
+            _ => panic!("Unknown Player"),
+        };
+    }
+
+    fn has_four_in_a_row(discs: u64) -> bool {
+        // Vertical, horizontal, and the two diagonals
+        for direction in [1, COLUMN_HEIGHT, COLUMN_HEIGHT - 1, COLUMN_HEIGHT + 1] {
+            let pairs = discs & (discs >> direction);
+            if pairs & (pairs >> (2 * direction)) != 0 {
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn has_someone_won(&self) -> Option<Disc> {
+        if Self::has_four_in_a_row(self.red) {
+            return Some(Disc::Red);
+        }
+        if Self::has_four_in_a_row(self.yellow) {
+            return Some(Disc::Yellow);
+        }
+        if self.occupied() == FULL_BOARD {
+            // Indicates a draw
+            return Some(Disc::Empty);
+        }
+        // Nobody has won yet
+        None
+    }
+}
+
+
+#[allow(clippy::result_unit_err)]
+pub fn get_move_input<R>(board: &ConnectFourBoard, reader: R) -> Result<HumanInput<ConnectFourMove>, ()>
+    where R: BufRead
+{
+    let mut output = io::stdout();
+
+    let column_str = prompt(reader, &mut output, &format!("Player {}, input your column: \n", board.current_player));
+    if let Some(command) = parse_command(&column_str) {
+        return Ok(command);
+    }
+
+    let column: usize = match column_str.trim().parse() {
+        Ok(num) => num,
+        Err(_) => {
+            println!("Invalid column: {}", column_str);
+            return Err(());
+        },
+    };
+    let human_move = ConnectFourMove { column };
+    if board.is_valid_move(human_move) {
+        Ok(HumanInput::Move(human_move))
+    } else {
+        println!("Invalid move, please choose a different column.");
+        Err(())
+    }
+}
+
+
+impl TwoPlayerGame for ConnectFourBoard {
+    type Player = Disc;
+
+    fn players() -> [Disc; 2] {
+        [Disc::Red, Disc::Yellow]
+    }
+
+    fn starting_with(player: Disc) -> Self {
+        ConnectFourBoard { red: 0, yellow: 0, current_player: player }
+    }
+
+    fn player_to_move(&self) -> Disc {
+        self.current_player
+    }
+
+    fn winner(&self) -> Option<Disc> {
+        self.has_someone_won().filter(|winner| *winner != Disc::Empty)
+    }
+
+    fn read_move<R: BufRead>(&self, reader: R) -> Option<HumanInput<ConnectFourMove>> {
+        get_move_input(self, reader).ok()
+    }
+
+    // Values go in a row under the columns they drop into.
+    fn show_values(&self, values: &[(ConnectFourMove, f64)], recommended: &ConnectFourMove) -> String {
+        let mut string = self.grid(7);
+        string.push('|');
+        for column in 0..COLUMNS {
+            let cell = match values.iter().find(|(action, _)| action.column == column) {
+                Some((action, value)) => format_value(*value, action == recommended),
+                None => String::new(),
+            };
+            string.push_str(format!("{cell:^7}|").as_str());
+        }
+        string.push('\n');
+        string
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::human_play::{HumanInput, TwoPlayerGame};
+    use crate::reinforcement_learning::generic_reinforcement_learner::{Environment, Symmetric};
+
+    use super::{ConnectFourBoard, ConnectFourMove, Disc, COLUMNS, ROWS, get_move_input};
+
+    fn board_from(rows: [&str; 6]) -> ConnectFourBoard {
+        match ConnectFourBoard::try_from(rows.concat()) {
+            Ok(res) => res,
+            Err(err) => panic!("{err}"),
+        }
+    }
+
+    #[test]
+    fn connect_four_discs_fall_to_the_bottom() {
+        let board = ConnectFourBoard::new();
+        let board = board.next_state(&ConnectFourMove::new(3));
+        let board = board.next_state(&ConnectFourMove::new(3));
+        assert_ne!(board.get(3, 0), Disc::Empty);
+        assert_ne!(board.get(3, 1), Disc::Empty);
+        assert_ne!(board.get(3, 0), board.get(3, 1));
+        assert_eq!(board.get(3, 2), Disc::Empty);
+    }
+
+    #[test]
+    fn connect_four_wins_in_every_direction() {
+        let horizontal = board_from([
+            "       ",
+            "       ",
+            "       ",
+            "       ",
+            "YYY    ",
+            "RRRR   ",
+        ]);
+        assert_eq!(horizontal.has_someone_won(), Some(Disc::Red));
+
+        let vertical = board_from([
+            "       ",
+            "       ",
+            "Y      ",
+            "YR     ",
+            "YR     ",
+            "YR     ",
+        ]);
+        assert_eq!(vertical.has_someone_won(), Some(Disc::Yellow));
+
+        let diagonal = board_from([
+            "       ",
+            "       ",
+            "   R   ",
+            "  RY   ",
+            " RYY   ",
+            "RYYR   ",
+        ]);
+        assert_eq!(diagonal.has_someone_won(), Some(Disc::Red));
+
+        let anti_diagonal = board_from([
+            "       ",
+            "       ",
+            "Y      ",
+            "RY     ",
+            "RRY    ",
+            "RRRY Y ",
+        ]);
+        assert_eq!(anti_diagonal.has_someone_won(), Some(Disc::Yellow));
+    }
+
+    #[test]
+    fn connect_four_does_not_wrap_across_columns() {
+        let board = board_from([
+            "R      ",
+            "R      ",
+            "R      ",
+            "Y      ",
+            "Y      ",
+            "YR     ",
+        ]);
+        assert_eq!(board.has_someone_won(), None);
+    }
+
+    #[test]
+    fn connect_four_draws_correctly() {
+        let board = board_from([
+            "RRYYRRY",
+            "YYRRYYR",
+            "RRYYRRY",
+            "YYRRYYR",
+            "RRYYRRY",
+            "YYRRYYR",
+        ]);
+        assert!(board.is_terminal());
+        assert_eq!(board.has_someone_won(), Some(Disc::Empty));
+    }
+
+    #[test]
+    fn connect_four_rejects_floating_discs() {
+        let result = ConnectFourBoard::try_from([
+            "       ",
+            "       ",
+            "       ",
+            "       ",
+            "   R   ",
+            "       ",
+        ].concat());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn connect_four_full_columns_are_not_available() {
+        let board = board_from([
+            "R      ",
+            "Y      ",
+            "R      ",
+            "Y      ",
+            "R      ",
+            "Y      ",
+        ]);
+        assert_eq!(board.available_actions().len(), 6);
+        assert!(!board.is_valid_move(ConnectFourMove::new(0)));
+        assert!(!board.is_valid_move(ConnectFourMove::new(7)));
+    }
+
+    #[test]
+    fn get_move_input_parses_input_correctly() {
+        let board = ConnectFourBoard::new();
+
+        let input = b"4";
+        assert_eq!(get_move_input(&board, &input[..]), Ok(HumanInput::Move(ConnectFourMove::new(4))));
+
+        let input = b"7";
+        assert_eq!(get_move_input(&board, &input[..]), Err(()));
+
+        let input = b"a";
+        assert_eq!(get_move_input(&board, &input[..]), Err(()));
+
+        let input = b"q";
+        assert_eq!(get_move_input(&board, &input[..]), Ok(HumanInput::Quit));
+    }
+
+    #[test]
+    fn mirrored_boards_share_a_canonical_form() {
+        let board = board_from([
+            "       ",
+            "       ",
+            "       ",
+            "       ",
+            "Y      ",
+            "RR Y   ",
+        ]);
+        let mirrored = board.transform(1);
+        assert_eq!(mirrored.get(COLUMNS - 1, 0), Disc::Red);
+        assert_eq!(mirrored.get(COLUMNS - 1, 1), Disc::Yellow);
+        assert!(mirrored.transform(1) == board);
+        assert!(board.transform(board.canonical_symmetry()) == mirrored.transform(mirrored.canonical_symmetry()));
+
+        let action = ConnectFourMove::new(1);
+        assert!(board.next_state(&action).transform(1) == mirrored.next_state(&ConnectFourBoard::transform_action(&action, 1)));
+    }
+
+    #[test]
+    fn values_are_shown_under_their_columns() {
+        let board = board_from([
+            "       ",
+            "       ",
+            "       ",
+            "       ",
+            "       ",
+            "   R   ",
+        ]);
+        let values: Vec<(ConnectFourMove, f64)> = (0..COLUMNS).map(|column| (ConnectFourMove::new(column), column as f64 / 10.0)).collect();
+        let overlay = board.show_values(&values, &ConnectFourMove::new(3));
+        let rows: Vec<&str> = overlay.lines().collect();
+        assert_eq!(rows.len(), ROWS + 4);
+        assert_eq!(rows[ROWS + 1], "|       |       |       |   R   |       |       |       |");
+        assert_eq!(rows[ROWS + 3], "| +0.00 | +0.10 | +0.20 |[+0.30]| +0.40 | +0.50 | +0.60 |");
+        // The board itself is drawn as before
+        assert!(board.to_string().starts_with("| 0 | 1 | 2 | 3 | 4 | 5 | 6 |\n+---+"));
+    }
+}
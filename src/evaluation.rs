@@ -0,0 +1,524 @@
+use core::fmt;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+use crate::reinforcement_learning::epsilon_greedy_policy::greedy_index;
+use crate::reinforcement_learning::generic_reinforcement_learner::{Environment, ReinforcementLearner, State};
+
+// Measures how well an agent plays a two-player game by playing many games
+// against reference opponents. Works for any Environment whose players
+// alternate, reading the result off the reward for the final move: positive
+// if the player who made it won, negative if they lost and zero for a draw.
+
+pub trait Agent<S>
+where
+    S: Environment
+{
+    fn choose_action(&mut self, state: &S) -> S::A;
+}
+
+// A trained learner, playing its greedy action.
+pub struct LearnerAgent<'a, L> {
+    pub learner: &'a L,
+}
+
+impl<'a, L> LearnerAgent<'a, L> {
+    pub fn new(learner: &'a L) -> Self {
+        LearnerAgent { learner }
+    }
+}
+
+impl<'a, S, L> Agent<S> for LearnerAgent<'a, L>
+where
+    S: Environment,
+    L: ReinforcementLearner<S>
+{
+    fn choose_action(&mut self, state: &S) -> S::A {
+        self.learner.get_best_action(state)
+    }
+}
+
+
+pub struct RandomAgent {
+    rng: StdRng,
+}
+
+impl RandomAgent {
+    pub fn new(seed: u64) -> RandomAgent {
+        RandomAgent { rng: StdRng::seed_from_u64(seed) }
+    }
+}
+
+impl<S> Agent<S> for RandomAgent
+where
+    S: Environment
+{
+    fn choose_action(&mut self, state: &S) -> S::A {
+        match state.available_actions().choose(&mut self.rng) {
+            Some(action) => action.clone(),
+            None => panic!("No actions available, state is terminal?"),
+        }
+    }
+}
+
+
+// Looks one move ahead: takes a winning move if there is one, otherwise
+// avoids moves that let the opponent win straight away, which in
+// tic-tac-toe and Connect Four means blocking their lines. Picks at random
+// among whatever is left.
+pub struct HeuristicAgent {
+    rng: StdRng,
+}
+
+impl HeuristicAgent {
+    pub fn new(seed: u64) -> HeuristicAgent {
+        HeuristicAgent { rng: StdRng::seed_from_u64(seed) }
+    }
+
+    fn is_winning_move<S: Environment>(state: &S, action: &S::A) -> bool {
+        let next_state = state.next_state(action);
+        next_state.is_terminal() && S::get_result(state, action, &next_state) > 0.0
+    }
+}
+
+impl<S> Agent<S> for HeuristicAgent
+where
+    S: Environment
+{
+    fn choose_action(&mut self, state: &S) -> S::A {
+        let actions = state.available_actions();
+        if let Some(action) = actions.iter().find(|action| Self::is_winning_move(state, *action)) {
+            return action.clone();
+        }
+        let safe_actions: Vec<S::A> = actions.iter()
+            .filter(|action| {
+                let next_state = state.next_state(*action);
+                next_state.is_terminal() || !next_state.available_actions().iter().any(|reply| Self::is_winning_move(&next_state, reply))
+            })
+            .cloned()
+            .collect();
+        let candidates = if safe_actions.is_empty() { actions } else { safe_actions };
+        match candidates.choose(&mut self.rng) {
+            Some(action) => action.clone(),
+            None => panic!("No actions available, state is terminal?"),
+        }
+    }
+}
+
+
+// Plays perfectly by searching the whole game tree with negamax, memoising
+// the value of each state for the player to move. Only practical for small
+// games such as tic-tac-toe and Nim. Ties between equally good moves are
+// broken at random, so that games against it vary.
+pub struct PerfectAgent<S>
+where
+    S: State
+{
+    values: HashMap<S, f64>,
+    rng: StdRng,
+}
+
+impl<S> PerfectAgent<S>
+where
+    S: State
+{
+    pub fn new(seed: u64) -> Self {
+        PerfectAgent { values: HashMap::new(), rng: StdRng::seed_from_u64(seed) }
+    }
+
+    // The value of taking the action, for the player taking it.
+    pub fn action_value(&mut self, state: &S, action: &S::A) -> f64 {
+        let next_state = state.next_state(action);
+        let reward = S::get_result(state, action, &next_state);
+        if next_state.is_terminal() {
+            reward
+        } else {
+            reward - self.state_value(&next_state)
+        }
+    }
+
+    // The value of the state for the player to move, under perfect play.
+    pub fn state_value(&mut self, state: &S) -> f64 {
+        if state.is_terminal() {
+            return 0.0;
+        }
+        if let Some(value) = self.values.get(state) {
+            return *value;
+        }
+        let value = state.available_actions()
+            .iter()
+            .map(|action| self.action_value(state, action))
+            .fold(f64::NEG_INFINITY, f64::max);
+        self.values.insert(state.clone(), value);
+        value
+    }
+}
+
+impl<S> Agent<S> for PerfectAgent<S>
+where
+    S: State
+{
+    fn choose_action(&mut self, state: &S) -> S::A {
+        let actions_and_values: Vec<(S::A, f64)> = state.available_actions()
+            .into_iter()
+            .map(|action| {
+                let value = self.action_value(state, &action);
+                (action, value)
+            })
+            .collect();
+        let best = actions_and_values.iter().map(|(_, value)| *value).fold(f64::NEG_INFINITY, f64::max);
+        let best_actions: Vec<&S::A> = actions_and_values.iter()
+            .filter(|(_, value)| *value == best)
+            .map(|(action, _)| action)
+            .collect();
+        match best_actions.choose(&mut self.rng) {
+            Some(action) => (*action).clone(),
+            None => panic!("No actions available, state is terminal?"),
+        }
+    }
+}
+
+
+// Monte Carlo tree search with the UCT rule. Each move grows a fresh search
+// tree from the current state by running `iterations` simulations, each of
+// which walks down the tree choosing the child with the best upper
+// confidence bound, adds one new node, plays randomly to the end of the game
+// and backs the result up the path. The most visited move is played.
+pub struct MctsAgent<S>
+where
+    S: State
+{
+    pub iterations: u32,
+    // Weight of the exploration bonus in the upper confidence bound
+    pub exploration: f64,
+    rng: StdRng,
+    state: PhantomData<S>,
+}
+
+// Visit counts and total returns of each action from a state in the tree,
+// from the point of view of the player to move.
+struct MctsNode<A> {
+    visits: u32,
+    actions: Vec<(A, u32, f64)>,
+}
+
+impl<S> MctsAgent<S>
+where
+    S: State
+{
+    pub fn new(iterations: u32, seed: u64) -> Self {
+        MctsAgent { iterations, exploration: 2.0f64.sqrt(), rng: StdRng::seed_from_u64(seed), state: PhantomData }
+    }
+
+    // Tries each action once, then the one with the highest
+    // mean + exploration * sqrt(ln N / n).
+    fn select(&mut self, node: &MctsNode<S::A>) -> usize {
+        let untried: Vec<usize> = (0..node.actions.len()).filter(|index| node.actions[*index].1 == 0).collect();
+        if let Some(index) = untried.choose(&mut self.rng) {
+            return *index;
+        }
+        let log_visits = (node.visits as f64).ln();
+        let bounds: Vec<f64> = node.actions.iter()
+            .map(|(_, visits, total)| total / *visits as f64 + self.exploration * (log_visits / *visits as f64).sqrt())
+            .collect();
+        greedy_index(&bounds, &mut self.rng)
+    }
+
+    fn simulate(&mut self, root: &S, tree: &mut HashMap<S, MctsNode<S::A>>) {
+        // Steps taken inside the tree, with the index of the action taken
+        let mut tree_path: Vec<(S, usize)> = Vec::new();
+        let mut rewards = Vec::new();
+        let mut state = root.clone();
+
+        // Selection and expansion
+        while !state.is_terminal() {
+            let node = tree.entry(state.clone()).or_insert_with(|| MctsNode {
+                visits: 0,
+                actions: state.available_actions().into_iter().map(|action| (action, 0, 0.0)).collect(),
+            });
+            let index = self.select(node);
+            let (action, visits, _) = &node.actions[index];
+            let expanding = *visits == 0;
+            let next_state = state.next_state(action);
+            rewards.push(S::get_result(&state, action, &next_state));
+            tree_path.push((state, index));
+            state = next_state;
+            // Stop at the first action that hasn't been tried yet
+            if expanding {
+                break;
+            }
+        }
+
+        // Random playout
+        while !state.is_terminal() {
+            let action = match state.available_actions().choose(&mut self.rng) {
+                Some(action) => action.clone(),
+                None => panic!("No actions available, state is terminal?"),
+            };
+            let next_state = state.next_state(&action);
+            rewards.push(S::get_result(&state, &action, &next_state));
+            state = next_state;
+        }
+
+        // Backpropagation, with each step's return from its mover's view
+        let mut returns = vec![0.0; rewards.len()];
+        let mut future_return = 0.0;
+        for (t, reward) in rewards.iter().enumerate().rev() {
+            future_return = reward + if S::alternates_players() { -future_return } else { future_return };
+            returns[t] = future_return;
+        }
+        for ((state, index), step_return) in tree_path.into_iter().zip(returns) {
+            if let Some(node) = tree.get_mut(&state) {
+                node.visits += 1;
+                node.actions[index].1 += 1;
+                node.actions[index].2 += step_return;
+            }
+        }
+    }
+}
+
+impl<S> Agent<S> for MctsAgent<S>
+where
+    S: State
+{
+    fn choose_action(&mut self, state: &S) -> S::A {
+        let mut tree = HashMap::new();
+        for _ in 0..self.iterations.max(1) {
+            self.simulate(state, &mut tree);
+        }
+        let node = match tree.remove(state) {
+            Some(node) => node,
+            None => panic!("No actions available, state is terminal?"),
+        };
+        let visits: Vec<f64> = node.actions.iter().map(|(_, visits, _)| *visits as f64).collect();
+        let best = greedy_index(&visits, &mut self.rng);
+        node.actions.into_iter().nth(best).unwrap().0
+    }
+}
+
+
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+#[derive(PartialEq, Eq)]
+pub enum Outcome {
+    Win,
+    Draw,
+    Loss
+}
+
+impl Outcome {
+    // The outcome for the player whose move ended the game with this reward.
+    pub fn from_reward(reward: f64) -> Outcome {
+        if reward > 0.0 {
+            Outcome::Win
+        } else if reward < 0.0 {
+            Outcome::Loss
+        } else {
+            Outcome::Draw
+        }
+    }
+
+    // The same result seen by the other player.
+    pub fn reversed(self) -> Outcome {
+        match self {
+            Outcome::Win => Outcome::Loss,
+            Outcome::Draw => Outcome::Draw,
+            Outcome::Loss => Outcome::Win,
+        }
+    }
+}
+
+// Plays one game from the initial state and returns the outcome for the
+// first player.
+pub fn play_game<S: Environment>(first: &mut dyn Agent<S>, second: &mut dyn Agent<S>) -> Outcome {
+    let mut state = S::initial_state();
+    let mut first_to_move = true;
+    loop {
+        let action = if first_to_move { first.choose_action(&state) } else { second.choose_action(&state) };
+        let next_state = state.next_state(&action);
+        if next_state.is_terminal() {
+            let mover_outcome = Outcome::from_reward(S::get_result(&state, &action, &next_state));
+            return if first_to_move { mover_outcome } else { mover_outcome.reversed() };
+        }
+        state = next_state;
+        first_to_move = !first_to_move;
+    }
+}
+
+
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+#[derive(Default)]
+pub struct EvaluationResults {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl EvaluationResults {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    pub fn record(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Win => self.wins += 1,
+            Outcome::Draw => self.draws += 1,
+            Outcome::Loss => self.losses += 1,
+        }
+    }
+
+    // The same results from the opponent's point of view.
+    pub fn reversed(&self) -> EvaluationResults {
+        EvaluationResults { wins: self.losses, draws: self.draws, losses: self.wins }
+    }
+
+    pub fn win_rate(&self) -> f64 {
+        self.wins as f64 / self.games() as f64
+    }
+
+    pub fn draw_rate(&self) -> f64 {
+        self.draws as f64 / self.games() as f64
+    }
+
+    pub fn loss_rate(&self) -> f64 {
+        self.losses as f64 / self.games() as f64
+    }
+
+    // 95% confidence intervals for each rate.
+    pub fn win_interval(&self) -> (f64, f64) {
+        wilson_interval(self.wins, self.games(), Z_95)
+    }
+
+    pub fn draw_interval(&self) -> (f64, f64) {
+        wilson_interval(self.draws, self.games(), Z_95)
+    }
+
+    pub fn loss_interval(&self) -> (f64, f64) {
+        wilson_interval(self.losses, self.games(), Z_95)
+    }
+}
+
+impl fmt::Display for EvaluationResults {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rates = [
+            ("won", self.win_rate(), self.win_interval()),
+            ("drew", self.draw_rate(), self.draw_interval()),
+            ("lost", self.loss_rate(), self.loss_interval()),
+        ];
+        write!(f, "{} games:", self.games())?;
+        for (name, rate, (low, high)) in rates {
+            write!(f, " {name} {:.1}% [{:.1}%, {:.1}%]", 100.0 * rate, 100.0 * low, 100.0 * high)?;
+        }
+        Ok(())
+    }
+}
+
+
+// The z-score of a two-sided 95% interval
+pub const Z_95: f64 = 1.959964;
+
+// Wilson score interval for a binomial proportion, which unlike the normal
+// approximation stays within [0, 1] and behaves near 0% and 100%.
+pub fn wilson_interval(successes: u32, trials: u32, z: f64) -> (f64, f64) {
+    if trials == 0 {
+        return (0.0, 1.0);
+    }
+    let n = trials as f64;
+    let p = successes as f64 / n;
+    let denominator = 1.0 + z * z / n;
+    let centre = (p + z * z / (2.0 * n)) / denominator;
+    let half_width = z / denominator * (p * (1.0 - p) / n + z * z / (4.0 * n * n)).sqrt();
+    ((centre - half_width).max(0.0), (centre + half_width).min(1.0))
+}
+
+
+// Plays num_games games between the agent and the opponent, with the agent
+// going first in even-numbered games and second in odd-numbered ones.
+// Results are from the agent's point of view.
+pub fn evaluate<S: Environment>(agent: &mut dyn Agent<S>, opponent: &mut dyn Agent<S>, num_games: u32) -> EvaluationResults {
+    let mut results = EvaluationResults::default();
+    for game in 0..num_games {
+        let outcome = if game % 2 == 0 {
+            play_game(agent, opponent)
+        } else {
+            play_game(opponent, agent).reversed()
+        };
+        results.record(outcome);
+    }
+    results
+}
+
+
+// Evaluates the agent against the random and heuristic opponents, and
+// against perfect play when the game is small enough to solve.
+pub fn evaluate_against_references<S: State>(agent: &mut dyn Agent<S>, num_games: u32, include_perfect: bool) -> Vec<(&'static str, EvaluationResults)> {
+    let mut results = vec![
+        ("random", evaluate(agent, &mut RandomAgent::new(0), num_games)),
+        ("heuristic", evaluate(agent, &mut HeuristicAgent::new(0), num_games)),
+    ];
+    if include_perfect {
+        results.push(("perfect", evaluate(agent, &mut PerfectAgent::new(0), num_games)));
+    }
+    results
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::nim::{NimState, StandardNim};
+    use crate::tictactoe::TicTacToeBoard;
+
+    use super::{evaluate, wilson_interval, HeuristicAgent, MctsAgent, PerfectAgent, RandomAgent, Z_95};
+
+    #[test]
+    fn wilson_interval_matches_known_values() {
+        let (low, high) = wilson_interval(50, 100, Z_95);
+        assert!((low - 0.4038).abs() < 1e-4 && (high - 0.5962).abs() < 1e-4);
+        let (low, high) = wilson_interval(0, 10, Z_95);
+        assert_eq!(low, 0.0);
+        assert!((high - 0.2775).abs() < 1e-4);
+    }
+
+    #[test]
+    fn perfect_tictactoe_never_loses() {
+        let mut perfect = PerfectAgent::<TicTacToeBoard>::new(0);
+        let results = evaluate(&mut perfect, &mut RandomAgent::new(1), 200);
+        assert_eq!(results.losses, 0);
+        assert!(results.wins > 150);
+
+        let results = evaluate(&mut perfect, &mut HeuristicAgent::new(2), 100);
+        assert_eq!(results.losses, 0);
+
+        let mut other_perfect = PerfectAgent::<TicTacToeBoard>::new(3);
+        let results = evaluate(&mut perfect, &mut other_perfect, 20);
+        assert_eq!(results.draws, 20);
+    }
+
+    #[test]
+    fn heuristic_beats_random() {
+        let results = evaluate::<TicTacToeBoard>(&mut HeuristicAgent::new(0), &mut RandomAgent::new(1), 200);
+        assert!(results.wins > 3 * results.losses);
+    }
+
+    #[test]
+    fn mcts_beats_random_and_holds_perfect_play() {
+        let mut mcts = MctsAgent::<TicTacToeBoard>::new(1000, 0);
+        let results = evaluate(&mut mcts, &mut RandomAgent::new(1), 20);
+        assert!(results.wins >= 15 && results.losses == 0);
+
+        let results = evaluate(&mut mcts, &mut PerfectAgent::new(2), 10);
+        assert!(results.draws >= 8);
+    }
+
+    #[test]
+    fn games_alternate_who_starts() {
+        // 3, 4, 5 Nim is a first player win, so perfect players split the games.
+        let mut first = PerfectAgent::<NimState<StandardNim>>::new(0);
+        let mut second = PerfectAgent::<NimState<StandardNim>>::new(1);
+        let results = evaluate(&mut first, &mut second, 10);
+        assert_eq!((results.wins, results.draws, results.losses), (5, 0, 5));
+    }
+}
@@ -0,0 +1,511 @@
+use core::fmt;
+use std::io::{self, BufRead};
+
+use rand::Rng;
+
+use crate::evaluation::{Outcome, PerfectAgent};
+use crate::reinforcement_learning::generic_reinforcement_learner::{ReinforcementLearner, State};
+use crate::reinforcement_learning::shaping::{Potential, Shaped};
+use crate::utils::{prompt, show_machine_thinking};
+
+// Playing against a trained learner at the terminal. The human picks a side
+// and who moves first, then plays matches of best of N games with a running
+// score, until they turn down a rematch or type "quit" at any prompt. When
+// asked for a move they can also ask for the value of every move, either as
+// the learner sees it or, in small games, under perfect play, and each game
+// ends with a review of their moves against the ones the learner prefers.
+
+// What the human asked for when prompted for a move.
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+#[derive(PartialEq, Eq)]
+pub enum HumanInput<M> {
+    Move(M),
+    // Show the learner's value of each move
+    Hint,
+    // Show the value of each move under perfect play
+    Minimax,
+    Quit,
+}
+
+// The command the line gives instead of a move, if any.
+pub fn parse_command<M>(line: &str) -> Option<HumanInput<M>> {
+    if is_quit(line) {
+        return Some(HumanInput::Quit);
+    }
+    match line.trim().to_lowercase().as_str() {
+        "hint" | "h" => Some(HumanInput::Hint),
+        "minimax" | "m" => Some(HumanInput::Minimax),
+        _ => None,
+    }
+}
+
+// Whether the line asks to stop playing. Running out of input counts too, so
+// that piped input ends the session instead of being asked for forever.
+pub fn is_quit(line: &str) -> bool {
+    line.is_empty() || matches!(line.trim().to_lowercase().as_str(), "quit" | "q" | "exit")
+}
+
+
+// A two-player board game that a human can play against the machine.
+pub trait TwoPlayerGame: State {
+    type Player: Copy + PartialEq + fmt::Display;
+
+    // Both players, the human playing the second by default
+    fn players() -> [Self::Player; 2];
+    // The empty board, with the given player to move
+    fn starting_with(player: Self::Player) -> Self;
+    fn player_to_move(&self) -> Self::Player;
+    // The winner of a finished game, or None if it was drawn
+    fn winner(&self) -> Option<Self::Player>;
+    // Asks for the human's move. None means the input wasn't valid, after
+    // saying why.
+    fn read_move<R: BufRead>(&self, reader: R) -> Option<HumanInput<Self::A>>;
+    // The board with the value of each available move drawn over it, formatted
+    // by format_value, and the recommended move picked out.
+    fn show_values(&self, values: &[(Self::A, f64)], recommended: &Self::A) -> String;
+    // Whether the game tree is small enough to search for minimax values
+    fn can_solve() -> bool {
+        false
+    }
+}
+
+// A shaped game plays just like the game itself.
+impl<G, P> TwoPlayerGame for Shaped<G, P>
+where
+    G: TwoPlayerGame,
+    P: Potential<G>
+{
+    type Player = G::Player;
+
+    fn players() -> [Self::Player; 2] {
+        G::players()
+    }
+
+    fn starting_with(player: Self::Player) -> Self {
+        Shaped::new(G::starting_with(player))
+    }
+
+    fn player_to_move(&self) -> Self::Player {
+        self.inner().player_to_move()
+    }
+
+    fn winner(&self) -> Option<Self::Player> {
+        self.inner().winner()
+    }
+
+    fn read_move<R: BufRead>(&self, reader: R) -> Option<HumanInput<Self::A>> {
+        self.inner().read_move(reader)
+    }
+
+    fn show_values(&self, values: &[(Self::A, f64)], recommended: &Self::A) -> String {
+        self.inner().show_values(values, recommended)
+    }
+
+    fn can_solve() -> bool {
+        G::can_solve()
+    }
+}
+
+
+// A move's value as drawn over the board, in brackets when it's the
+// recommended move. Never more than seven characters for values below ten.
+pub fn format_value(value: f64, recommended: bool) -> String {
+    if recommended { format!("[{value:+.2}]") } else { format!("{value:+.2}") }
+}
+
+// The value of a state's moves for the player to move, under perfect play.
+fn minimax_values<G: TwoPlayerGame>(perfect: &mut PerfectAgent<G>, board: &G) -> Vec<(G::A, f64)> {
+    board.available_actions()
+        .into_iter()
+        .map(|action| {
+            let value = perfect.action_value(board, &action);
+            (action, value)
+        })
+        .collect()
+}
+
+// The first of the moves with the highest value.
+fn best_move<A: Clone>(values: &[(A, f64)]) -> Option<A> {
+    values.iter()
+        .fold(None, |best: Option<&(A, f64)>, candidate| match best {
+            Some(best) if best.1 >= candidate.1 => Some(best),
+            _ => Some(candidate),
+        })
+        .map(|(action, _)| action.clone())
+}
+
+// One line per move the human made, comparing it with the learner's choice.
+pub fn review<G, L>(learner: &L, human_moves: &[(G, G::A)]) -> Vec<String>
+where
+    G: TwoPlayerGame,
+    L: ReinforcementLearner<G>
+{
+    human_moves.iter()
+        .enumerate()
+        .map(|(index, (board, human_move))| {
+            let value = learner.get_action_value(board, human_move);
+            let preferred = learner.get_best_action(board);
+            let preferred_value = learner.get_action_value(board, &preferred);
+            if preferred == *human_move || preferred_value <= value {
+                format!("Move {}: {human_move} ({value:+.2}), as the agent would have played.", index + 1)
+            } else {
+                format!("Move {}: {human_move} ({value:+.2}), where the agent prefers {preferred} ({preferred_value:+.2}).", index + 1)
+            }
+        })
+        .collect()
+}
+
+
+// Who makes the first move of each game in a match.
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+#[derive(PartialEq, Eq)]
+pub enum FirstMove {
+    Human,
+    Machine,
+    Random,
+    // The human starts the first game, then the players take turns
+    Alternate,
+}
+
+impl FirstMove {
+    pub fn parse(name: &str) -> Option<FirstMove> {
+        match name.trim().to_lowercase().as_str() {
+            "you" | "me" | "human" => Some(FirstMove::Human),
+            "machine" => Some(FirstMove::Machine),
+            "" | "random" => Some(FirstMove::Random),
+            "alternate" => Some(FirstMove::Alternate),
+            _ => None,
+        }
+    }
+
+    // Whether the human moves first in the given game, counting from one.
+    fn human_starts<R: Rng + ?Sized>(self, game: u32, rng: &mut R) -> bool {
+        match self {
+            FirstMove::Human => true,
+            FirstMove::Machine => false,
+            FirstMove::Random => rng.gen_bool(0.5),
+            FirstMove::Alternate => game % 2 == 1,
+        }
+    }
+}
+
+
+pub struct MatchOptions<P> {
+    pub human: P,
+    pub first_move: FirstMove,
+    pub best_of: u32,
+}
+
+
+// Games won by each side and drawn, in one match or over a session.
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+#[derive(Default)]
+#[derive(PartialEq, Eq)]
+pub struct Score {
+    pub human: u32,
+    pub machine: u32,
+    pub draws: u32,
+}
+
+impl Score {
+    // Adds a game, with its outcome for the human.
+    pub fn record(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Win => self.human += 1,
+            Outcome::Draw => self.draws += 1,
+            Outcome::Loss => self.machine += 1,
+        }
+    }
+
+    pub fn games(&self) -> u32 {
+        self.human + self.machine + self.draws
+    }
+
+    // A best-of-n match is over once a side has won more than half of the
+    // games, or all of them have been played.
+    pub fn is_decided(&self, best_of: u32) -> bool {
+        2 * self.human > best_of || 2 * self.machine > best_of || self.games() >= best_of
+    }
+}
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "You {} - {} Machine ({} drawn)", self.human, self.machine, self.draws)
+    }
+}
+
+
+// Asks the question until the answer is accepted. None means the human quit.
+fn ask<R, T>(input: &mut R, question: &str, accept: impl Fn(&str) -> Option<T>) -> Option<T>
+where
+    R: BufRead
+{
+    loop {
+        let line = prompt(&mut *input, &mut io::stdout(), question);
+        if is_quit(&line) {
+            return None;
+        }
+        match accept(line.trim()) {
+            Some(answer) => return Some(answer),
+            None => println!("Sorry, I didn't understand {:?}.", line.trim()),
+        }
+    }
+}
+
+pub fn ask_options<G, R>(input: &mut R) -> Option<MatchOptions<G::Player>>
+where
+    G: TwoPlayerGame,
+    R: BufRead
+{
+    let [first, second] = G::players();
+    let human = ask(input, &format!("Play as {first} or {second}? [{second}] "), |answer| {
+        if answer.is_empty() {
+            Some(second)
+        } else {
+            G::players().into_iter().find(|player| player.to_string().eq_ignore_ascii_case(answer))
+        }
+    })?;
+    let first_move = ask(input, "Who moves first: you, machine, random or alternate? [random] ", FirstMove::parse)?;
+    let best_of = ask(input, "Best of how many games? [1] ", |answer| {
+        if answer.is_empty() { Some(1) } else { answer.parse().ok().filter(|games| *games > 0) }
+    })?;
+    Some(MatchOptions { human, first_move, best_of })
+}
+
+// Plays one game and returns its outcome for the human, or None if they quit.
+pub fn play_game<G, L, R>(learner: &L, input: &mut R, human: G::Player, first: G::Player) -> Option<Outcome>
+where
+    G: TwoPlayerGame,
+    L: ReinforcementLearner<G>,
+    R: BufRead
+{
+    let mut board = G::starting_with(first);
+    let mut human_moves = Vec::new();
+    // Only built when first asked for, as it solves the game
+    let mut perfect = None;
+    println!("{board}");
+    while !board.is_terminal() {
+        if board.player_to_move() == human {
+            match board.read_move(&mut *input) {
+                Some(HumanInput::Move(human_move)) => {
+                    let next_state = board.next_state(&human_move);
+                    human_moves.push((board, human_move));
+                    board = next_state;
+                },
+                Some(HumanInput::Hint) => {
+                    let recommended = learner.get_best_action(&board);
+                    println!("The agent's values, with its choice in brackets:");
+                    println!("{}", board.show_values(&learner.get_action_values(&board), &recommended));
+                    continue;
+                },
+                Some(HumanInput::Minimax) if G::can_solve() => {
+                    let values = minimax_values(perfect.get_or_insert_with(|| PerfectAgent::new(0)), &board);
+                    if let Some(recommended) = best_move(&values) {
+                        println!("Values under perfect play, with the best move in brackets:");
+                        println!("{}", board.show_values(&values, &recommended));
+                    }
+                    continue;
+                },
+                Some(HumanInput::Minimax) => {
+                    println!("This game is too big to work out perfect play, try \"hint\" instead.");
+                    continue;
+                },
+                Some(HumanInput::Quit) => return None,
+                None => continue,
+            }
+        } else {
+            show_machine_thinking();
+            board = board.next_state(&learner.get_best_action(&board));
+        }
+        println!("{board}");
+    }
+    let outcome = match board.winner() {
+        Some(winner) if winner == human => Outcome::Win,
+        Some(_) => Outcome::Loss,
+        None => Outcome::Draw,
+    };
+    match outcome {
+        Outcome::Win => println!("Player {human} has won!"),
+        Outcome::Loss => println!("Machine has won!"),
+        Outcome::Draw => println!("It's a draw!"),
+    }
+    if !human_moves.is_empty() {
+        println!("Review of your moves, with the agent's values:");
+        for line in review(learner, &human_moves) {
+            println!("{line}");
+        }
+    }
+    Some(outcome)
+}
+
+// Plays matches until the human declines a rematch or quits, and returns
+// the score over all of the games finished.
+pub fn play_session<G, L, R>(learner: &L, input: &mut R) -> Score
+where
+    G: TwoPlayerGame,
+    L: ReinforcementLearner<G>,
+    R: BufRead
+{
+    let mut total = Score::default();
+    println!("Type \"quit\" at any prompt to stop playing. When it's your move, \"hint\" shows the agent's value of each move and \"minimax\" their value under perfect play.");
+    let options = match ask_options::<G, R>(input) {
+        Some(options) => options,
+        None => return total,
+    };
+    let machine = G::players().into_iter().find(|player| *player != options.human).unwrap();
+    let mut rng = rand::thread_rng();
+    loop {
+        let mut score = Score::default();
+        while !score.is_decided(options.best_of) {
+            let game = score.games() + 1;
+            let first = if options.first_move.human_starts(game, &mut rng) { options.human } else { machine };
+            println!("==================================");
+            println!("GAME {game} OF {}", options.best_of);
+            let outcome = match play_game(learner, input, options.human, first) {
+                Some(outcome) => outcome,
+                None => {
+                    println!("Final score: {total}");
+                    return total;
+                },
+            };
+            score.record(outcome);
+            total.record(outcome);
+            println!("Score: {score}");
+        }
+        if options.best_of > 1 {
+            if score.human > score.machine {
+                println!("You have won the match!");
+            } else if score.machine > score.human {
+                println!("Machine has won the match!");
+            } else {
+                println!("The match is drawn!");
+            }
+        }
+        let rematch = ask(input, "Rematch? [y/n] ", |answer| match answer.to_lowercase().as_str() {
+            "y" | "yes" | "" => Some(true),
+            "n" | "no" => Some(false),
+            _ => None,
+        });
+        if rematch != Some(true) {
+            println!("Final score: {total}");
+            return total;
+        }
+    }
+}
+
+pub fn play_vs_human<G, L>(learner: L)
+where
+    G: TwoPlayerGame,
+    L: ReinforcementLearner<G>
+{
+    play_session(&learner, &mut io::stdin().lock());
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluation::{Outcome, PerfectAgent};
+    use crate::reinforcement_learning::generic_reinforcement_learner::ReinforcementLearner;
+    use crate::reinforcement_learning::q_learning_learner::QLearner;
+    use crate::reinforcement_learning::q_table::QTable;
+    use crate::tictactoe::{BoardEntry, TicTacToeBoard, TicTacToeMove};
+
+    use super::{ask_options, best_move, is_quit, minimax_values, parse_command, play_session, review, FirstMove, HumanInput, Score, TwoPlayerGame};
+
+    #[test]
+    fn best_of_matches_end_once_decided() {
+        let mut score = Score::default();
+        score.record(Outcome::Win);
+        assert!(!score.is_decided(3));
+        score.record(Outcome::Draw);
+        assert!(!score.is_decided(3));
+        score.record(Outcome::Win);
+        assert!(score.is_decided(3));
+        assert_eq!(score, Score { human: 2, machine: 0, draws: 1 });
+        assert_eq!(score.to_string(), "You 2 - 0 Machine (1 drawn)");
+
+        let mut drawn = Score::default();
+        drawn.record(Outcome::Win);
+        drawn.record(Outcome::Loss);
+        assert!(drawn.is_decided(2));
+    }
+
+    #[test]
+    fn options_are_asked_for_with_defaults() {
+        let mut input = &b"x\nalternate\n5\n"[..];
+        let options = ask_options::<TicTacToeBoard, _>(&mut input).unwrap();
+        assert_eq!(options.human, BoardEntry::X);
+        assert_eq!(options.first_move, FirstMove::Alternate);
+        assert_eq!(options.best_of, 5);
+
+        // Unclear answers are asked again
+        let mut input = &b"\nfirst\nme\n0\n\n"[..];
+        let options = ask_options::<TicTacToeBoard, _>(&mut input).unwrap();
+        assert_eq!(options.human, BoardEntry::O);
+        assert_eq!(options.first_move, FirstMove::Human);
+        assert_eq!(options.best_of, 1);
+
+        let mut input = &b"O\nquit\n"[..];
+        assert!(ask_options::<TicTacToeBoard, _>(&mut input).is_none());
+    }
+
+    #[test]
+    fn quitting_ends_the_session() {
+        assert!(is_quit("quit\n") && is_quit("Q") && is_quit(""));
+        assert!(!is_quit("\n"));
+
+        let learner = QLearner::<TicTacToeBoard>::new(0.1, 0.9);
+        let mut input = &b"x\nyou\n3\n1, 1\nquit\n"[..];
+        assert_eq!(play_session(&learner, &mut input), Score::default());
+        // Running out of input quits too
+        let mut input = &b"x\nyou\n"[..];
+        assert_eq!(play_session(&learner, &mut input), Score::default());
+    }
+
+    #[test]
+    fn commands_are_told_apart_from_moves() {
+        assert_eq!(parse_command::<TicTacToeMove>("hint\n"), Some(HumanInput::Hint));
+        assert_eq!(parse_command::<TicTacToeMove>("Minimax"), Some(HumanInput::Minimax));
+        assert_eq!(parse_command::<TicTacToeMove>("quit"), Some(HumanInput::Quit));
+        assert_eq!(parse_command::<TicTacToeMove>("1, 1"), None);
+    }
+
+    #[test]
+    fn hints_and_reviews_use_the_learners_values() {
+        let board = TicTacToeBoard::starting_with(BoardEntry::X);
+        let mut learner = QLearner::<TicTacToeBoard>::new(0.1, 0.9);
+        learner.q_values.set(&board, &TicTacToeMove::new(1, 1), 0.5);
+        learner.q_values.set(&board, &TicTacToeMove::new(0, 0), -0.25);
+
+        let overlay = board.show_values(&learner.get_action_values(&board), &TicTacToeMove::new(1, 1));
+        let rows: Vec<&str> = overlay.lines().collect();
+        assert_eq!(rows[0], "x\\y|   0   |   1   |   2   |");
+        assert_eq!(rows[2], " 0 | -0.25 | +0.00 | +0.00 |");
+        assert_eq!(rows[4], " 1 | +0.00 |[+0.50]| +0.00 |");
+
+        let moves = vec![(board.clone(), TicTacToeMove::new(0, 0)), (board, TicTacToeMove::new(1, 1))];
+        assert_eq!(review(&learner, &moves), vec![
+            "Move 1: (0, 0) (-0.25), where the agent prefers (1, 1) (+0.50).".to_string(),
+            "Move 2: (1, 1) (+0.50), as the agent would have played.".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn minimax_values_find_the_winning_move() {
+        let mut board = match TicTacToeBoard::try_from("XX OO    ".to_string()) {
+            Ok(res) => res,
+            Err(err) => panic!("{err}"),
+        };
+        board.current_player = BoardEntry::X;
+        let values = minimax_values(&mut PerfectAgent::new(0), &board);
+        assert_eq!(best_move(&values), Some(TicTacToeMove::new(0, 2)));
+        let value = |cell| values.iter().find(|(action, _)| *action == cell).unwrap().1;
+        assert_eq!(value(TicTacToeMove::new(0, 2)), 1.0);
+        // Lets O complete the middle row
+        assert_eq!(value(TicTacToeMove::new(2, 2)), -1.0);
+        assert!(TicTacToeBoard::can_solve());
+    }
+}
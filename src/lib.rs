@@ -0,0 +1,13 @@
+pub mod tictactoe;
+pub mod connect_four;
+pub mod blackjack;
+pub mod bandit;
+pub mod nim;
+pub mod neural_network;
+pub mod evaluation;
+pub mod tournament;
+pub mod human_play;
+pub mod sweep;
+pub mod classic_control;
+pub mod reinforcement_learning;
+pub mod utils;
@@ -1,19 +1,229 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, env, fs::File, io::{self, BufWriter}, thread};
 
-use reinforcement_learning::{q_learning_learner, epsilon_greedy_policy::EpsilonGreedyPolicy};
+use reinforced_rust::{connect_four::ConnectFourBoard, human_play, evaluation::{evaluate_against_references, HeuristicAgent, LearnerAgent, MctsAgent, PerfectAgent, RandomAgent}, neural_network::{Activation, NeuralNetwork, Optimiser}, reinforcement_learning::{dqn_learner::DqnLearner, replay_buffer::UniformReplayBuffer, generic_reinforcement_learner::{IndexedActions, ReinforcementLearner, State, Symmetric, TensorEncoding}, q_learning_learner::QLearner, q_table::{DenseQTable, QTable}, epsilon_greedy_policy::EpsilonGreedyPolicy, metrics::{MetricsLog, PeriodicEvaluation}, parallel::ParallelTrainer, symmetry::{Canonical, SymmetricLearner}, training_hooks::Checkpoint}, sweep::{parse_values, LearnerKind, Sweep}, tournament::Tournament, tictactoe::{FourByFourBoard, GomokuLiteBoard, ShapedTicTacToeBoard, TicTacToeBoard}};
 
-mod tictactoe;
-mod reinforcement_learning;
-mod utils;
+const GAMES: &str = "tictactoe, four-by-four, gomoku-lite, connect-four, tictactoe-shaped, tictactoe-dense, tictactoe-parallel, connect-four-parallel, tictactoe-symmetric, connect-four-symmetric, tictactoe-dqn, connect-four-dqn";
 
-fn main() {
+fn train<S: State>() -> QLearner<S> {
+    train_with_table(HashMap::new())
+}
 
-    let mut q_learner = q_learning_learner::QLearner {
-        q_values: HashMap::new(),
-        alpha: 0.1,
-        gamma: 0.9,
-    };
+// The discount of 0.9 is also the one the tic-tac-toe threats potential is
+// shaped for, which the learner checks.
+fn train_with_table<S: State, T: QTable<S>>(q_values: T) -> QLearner<S, T> {
+    let mut q_learner = QLearner::with_table(q_values, 0.1, 0.9);
     let policy = EpsilonGreedyPolicy::new(0.1);
     q_learner.q_learning(&policy, 100000);
-    tictactoe::play_vs_human(q_learner);
+    q_learner
+}
+
+// Trains with Hogwild updates to a shared table on every available core,
+// splitting the usual 100000 episodes between them.
+fn train_parallel<S>() -> QLearner<S>
+where
+    S: State + Send + Sync,
+    S::A: Send + Sync
+{
+    let num_workers = thread::available_parallelism().map_or(1, |workers| workers.get());
+    let mut q_learner = QLearner::new(0.1, 0.9);
+    let policy = EpsilonGreedyPolicy::new(0.1);
+    ParallelTrainer::new(num_workers, rand::random()).train_shared(&mut q_learner, &policy, 100000 / num_workers as u32);
+    q_learner
+}
+
+// Trains on positions in canonical form, so that symmetric positions share
+// their Q values.
+fn train_symmetric<S: State + Symmetric>() -> SymmetricLearner<QLearner<Canonical<S>>> {
+    SymmetricLearner::new(train::<Canonical<S>>())
+}
+
+fn train_dqn<S: TensorEncoding + IndexedActions>(num_episodes: u32) -> DqnLearner<S> {
+    let layer_sizes = [S::encoding_len(), 64, 64, S::num_actions()];
+    let network = NeuralNetwork::new(&layer_sizes, Activation::ReLU, Optimiser::adam(0.001), &mut rand::thread_rng());
+    let mut dqn_learner = DqnLearner::new(network, 0.9, UniformReplayBuffer::new(10000, rand::random()));
+    let policy = EpsilonGreedyPolicy::new(0.1);
+    dqn_learner.q_learning(&policy, num_episodes);
+    dqn_learner
+}
+
+// Plays the learner against the reference opponents and prints the results.
+fn evaluate<S: State>(learner: impl ReinforcementLearner<S>, num_games: u32, include_perfect: bool) {
+    let mut agent = LearnerAgent::new(&learner);
+    for (opponent, results) in evaluate_against_references(&mut agent, num_games, include_perfect) {
+        println!("Against {opponent}: {results}");
+    }
+}
+
+// Trains a Q-learner while recording its learning curve, evaluating it
+// every 1000 episodes, and writes the metrics to <prefix>.episodes.csv,
+// <prefix>.episodes.jsonl, <prefix>.evaluations.csv and
+// <prefix>.evaluations.jsonl.
+fn record_metrics<S: State + 'static>(prefix: &str, num_episodes: u32, include_perfect: bool) -> io::Result<()> {
+    let mut q_learner = QLearner::<S>::new(0.1, 0.9);
+    let policy = EpsilonGreedyPolicy::new(0.1);
+    let mut evaluation = PeriodicEvaluation::new(1000, 100)
+        .against("random", RandomAgent::new(0))
+        .against("heuristic", HeuristicAgent::new(0));
+    if include_perfect {
+        evaluation = evaluation.against("perfect", PerfectAgent::<S>::new(0));
+    }
+    let log = q_learner.q_learning_with_metrics(&policy, num_episodes, Some(&mut evaluation));
+    write_metrics(&log, prefix)
+}
+
+fn write_metrics(log: &MetricsLog, prefix: &str) -> io::Result<()> {
+    log.write_episodes_csv(BufWriter::new(File::create(format!("{prefix}.episodes.csv"))?))?;
+    log.write_episodes_jsonl(BufWriter::new(File::create(format!("{prefix}.episodes.jsonl"))?))?;
+    log.write_evaluations_csv(BufWriter::new(File::create(format!("{prefix}.evaluations.csv"))?))?;
+    log.write_evaluations_jsonl(BufWriter::new(File::create(format!("{prefix}.evaluations.jsonl"))?))?;
+    println!("Wrote metrics to {prefix}.episodes.csv, {prefix}.episodes.jsonl, {prefix}.evaluations.csv and {prefix}.evaluations.jsonl");
+    Ok(())
+}
+
+// Plays a round-robin tic-tac-toe tournament between the reference players,
+// Monte Carlo tree search and Q-learner checkpoints taken after 1000, 10000
+// and 100000 episodes of training, prints their Elo ratings and writes the
+// ranking to the given CSV file.
+fn run_tournament(games_per_pairing: u32, output: &str) {
+    let mut q_learner = QLearner::<TicTacToeBoard>::new(0.1, 0.9);
+    let mut snapshots = Vec::new();
+    let mut checkpoint = Checkpoint::new(1000, |episode, learner: &QLearner<TicTacToeBoard>| {
+        if matches!(episode, 1000 | 10000 | 100000) {
+            snapshots.push((format!("q-learning-{episode}"), learner.clone()));
+        }
+    });
+    q_learner.q_learning_with_hooks(&EpsilonGreedyPolicy::new(0.1), 100000, &mut [&mut checkpoint]);
+
+    let mut tournament = Tournament::new()
+        .add("random", RandomAgent::new(0))
+        .add("heuristic", HeuristicAgent::new(1))
+        .add("perfect", PerfectAgent::new(2))
+        .add("mcts-100", MctsAgent::new(100, 3))
+        .add("mcts-1000", MctsAgent::new(1000, 4));
+    for (name, snapshot) in &snapshots {
+        tournament = tournament.add(name, LearnerAgent::new(snapshot));
+    }
+    let results = tournament.play(games_per_pairing);
+    print!("{results}");
+    match File::create(output).and_then(|file| results.write_csv(BufWriter::new(file))) {
+        Ok(()) => println!("Wrote the ranking to {output}"),
+        Err(error) => println!("Could not write {output}: {error}"),
+    }
+}
+
+// Runs a hyperparameter sweep from settings such as alpha=0.05,0.1,0.2 or
+// epsilon=0.05:0.2:4, prints the configurations ranked by their score and
+// writes them to <game>.sweep.csv unless given another output.
+fn run_sweep(game: &str, settings: &[String]) -> Result<(), String> {
+    let mut sweep = Sweep::new();
+    let mut output = format!("{game}.sweep.csv");
+    for setting in settings {
+        let (name, value) = setting.split_once('=').ok_or_else(|| format!("Expected name=value, got {setting}"))?;
+        let count = || value.parse::<usize>().map_err(|_| format!("Invalid {name} {value}"));
+        match name {
+            "learner" => sweep.learners = value.split(',').map(LearnerKind::parse).collect::<Result<_, _>>()?,
+            "alpha" => sweep.alphas = parse_values(value)?,
+            "gamma" => sweep.gammas = parse_values(value)?,
+            "epsilon" => sweep.epsilons = parse_values(value)?,
+            "episodes" => sweep.episode_counts = parse_values(value)?.into_iter().map(|episodes| episodes.round() as u32).collect(),
+            "seeds" => sweep.seeds = (0..count()? as u64).collect(),
+            "games" => sweep.num_games = count()? as u32,
+            "workers" => sweep.num_workers = count()?,
+            "output" => output = value.to_string(),
+            _ => return Err(format!("Unknown setting {name}, expected one of: learner, alpha, gamma, epsilon, episodes, seeds, games, workers, output")),
+        }
+    }
+    let results = match game {
+        "tictactoe" => sweep.run::<TicTacToeBoard>(true),
+        "four-by-four" => sweep.run::<FourByFourBoard>(false),
+        "gomoku-lite" => sweep.run::<GomokuLiteBoard>(false),
+        "connect-four" => sweep.run::<ConnectFourBoard>(false),
+        _ => return Err(format!("Unknown game {game}, expected one of: tictactoe, four-by-four, gomoku-lite, connect-four")),
+    };
+    print!("{results}");
+    let file = File::create(&output).map_err(|error| format!("Could not write {output}: {error}"))?;
+    results.write_csv(BufWriter::new(file)).map_err(|error| format!("Could not write {output}: {error}"))?;
+    println!("Wrote the sweep to {output}");
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("evaluate") {
+        let game = args.get(2).cloned().unwrap_or_else(|| "tictactoe".to_string());
+        let num_games = match args.get(3).map(|games| games.parse()) {
+            Some(Ok(num_games)) => num_games,
+            Some(Err(_)) => return println!("Invalid number of games: {}", args[3]),
+            None => 1000,
+        };
+        match game.as_str() {
+            "tictactoe" => evaluate(train::<TicTacToeBoard>(), num_games, true),
+            "four-by-four" => evaluate(train::<FourByFourBoard>(), num_games, false),
+            "gomoku-lite" => evaluate(train::<GomokuLiteBoard>(), num_games, false),
+            "connect-four" => evaluate(train::<ConnectFourBoard>(), num_games, false),
+            "tictactoe-shaped" => evaluate(train::<ShapedTicTacToeBoard>(), num_games, true),
+            "tictactoe-dense" => evaluate(train_with_table(DenseQTable::<TicTacToeBoard>::new()), num_games, true),
+            "tictactoe-parallel" => evaluate(train_parallel::<TicTacToeBoard>(), num_games, true),
+            "connect-four-parallel" => evaluate(train_parallel::<ConnectFourBoard>(), num_games, false),
+            "tictactoe-symmetric" => evaluate(train_symmetric::<TicTacToeBoard>(), num_games, true),
+            "connect-four-symmetric" => evaluate(train_symmetric::<ConnectFourBoard>(), num_games, false),
+            "tictactoe-dqn" => evaluate(train_dqn::<TicTacToeBoard>(10000), num_games, true),
+            "connect-four-dqn" => evaluate(train_dqn::<ConnectFourBoard>(5000), num_games, false),
+            _ => println!("Unknown game {game}, expected one of: {GAMES}"),
+        }
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("tournament") {
+        let output = args.get(3).map_or("tournament.csv", String::as_str);
+        match args.get(2).map(|games| games.parse()) {
+            Some(Ok(games_per_pairing)) => run_tournament(games_per_pairing, output),
+            Some(Err(_)) => println!("Invalid number of games: {}", args[2]),
+            None => run_tournament(100, output),
+        }
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("sweep") {
+        let game = args.get(2).cloned().unwrap_or_else(|| "tictactoe".to_string());
+        if let Err(error) = run_sweep(&game, args.get(3..).unwrap_or_default()) {
+            println!("{error}");
+        }
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("metrics") {
+        let game = args.get(2).cloned().unwrap_or_else(|| "tictactoe".to_string());
+        let prefix = args.get(3).cloned().unwrap_or_else(|| game.clone());
+        let num_episodes = match args.get(4).map(|episodes| episodes.parse()) {
+            Some(Ok(num_episodes)) => num_episodes,
+            Some(Err(_)) => return println!("Invalid number of episodes: {}", args[4]),
+            None => 100000,
+        };
+        let result = match game.as_str() {
+            "tictactoe" => record_metrics::<TicTacToeBoard>(&prefix, num_episodes, true),
+            "four-by-four" => record_metrics::<FourByFourBoard>(&prefix, num_episodes, false),
+            "gomoku-lite" => record_metrics::<GomokuLiteBoard>(&prefix, num_episodes, false),
+            "connect-four" => record_metrics::<ConnectFourBoard>(&prefix, num_episodes, false),
+            _ => return println!("Unknown game {game}, expected one of: tictactoe, four-by-four, gomoku-lite, connect-four"),
+        };
+        if let Err(error) = result {
+            println!("Could not write metrics: {error}");
+        }
+        return;
+    }
+
+    let game = args.get(1).cloned().unwrap_or_else(|| "tictactoe".to_string());
+    match game.as_str() {
+        "tictactoe" => human_play::play_vs_human(train::<TicTacToeBoard>()),
+        "four-by-four" => human_play::play_vs_human(train::<FourByFourBoard>()),
+        "gomoku-lite" => human_play::play_vs_human(train::<GomokuLiteBoard>()),
+        "connect-four" => human_play::play_vs_human(train::<ConnectFourBoard>()),
+        "tictactoe-shaped" => human_play::play_vs_human(train::<ShapedTicTacToeBoard>()),
+        "tictactoe-dense" => human_play::play_vs_human(train_with_table(DenseQTable::<TicTacToeBoard>::new())),
+        "tictactoe-parallel" => human_play::play_vs_human(train_parallel::<TicTacToeBoard>()),
+        "connect-four-parallel" => human_play::play_vs_human(train_parallel::<ConnectFourBoard>()),
+        "tictactoe-symmetric" => human_play::play_vs_human(train_symmetric::<TicTacToeBoard>()),
+        "connect-four-symmetric" => human_play::play_vs_human(train_symmetric::<ConnectFourBoard>()),
+        "tictactoe-dqn" => human_play::play_vs_human(train_dqn::<TicTacToeBoard>(10000)),
+        "connect-four-dqn" => human_play::play_vs_human(train_dqn::<ConnectFourBoard>(5000)),
+        _ => println!("Unknown game {game}, expected one of: {GAMES}"),
+    }
 }
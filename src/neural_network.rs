@@ -0,0 +1,342 @@
+use rand::Rng;
+
+use crate::utils::sample_standard_normal;
+
+// A small multilayer perceptron trained by backpropagation on the CPU. It is
+// meant for the few thousand weights needed by board games, so everything is
+// plain Vec<f64> and loops rather than a tensor library.
+
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum Activation {
+    Identity,
+    ReLU,
+    Tanh
+}
+
+impl Activation {
+    fn apply(&self, x: f64) -> f64 {
+        match self {
+            Activation::Identity => x,
+            Activation::ReLU => x.max(0.0),
+            Activation::Tanh => x.tanh(),
+        }
+    }
+
+    // The derivative written in terms of the activation's output, which is
+    // what the forward pass keeps.
+    fn derivative(&self, output: f64) -> f64 {
+        match self {
+            Activation::Identity => 1.0,
+            Activation::ReLU => if output > 0.0 { 1.0 } else { 0.0 },
+            Activation::Tanh => 1.0 - output * output,
+        }
+    }
+}
+
+
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum Loss {
+    MeanSquaredError,
+    // Quadratic for errors smaller than delta and linear beyond, so that
+    // large errors don't produce huge gradients.
+    Huber(f64)
+}
+
+impl Loss {
+    pub fn value(&self, prediction: f64, target: f64) -> f64 {
+        let error = prediction - target;
+        match self {
+            Loss::MeanSquaredError => 0.5 * error * error,
+            Loss::Huber(delta) => {
+                if error.abs() <= *delta {
+                    0.5 * error * error
+                } else {
+                    delta * (error.abs() - 0.5 * delta)
+                }
+            }
+        }
+    }
+
+    // Derivative with respect to the prediction.
+    pub fn gradient(&self, prediction: f64, target: f64) -> f64 {
+        let error = prediction - target;
+        match self {
+            Loss::MeanSquaredError => error,
+            Loss::Huber(delta) => error.clamp(-delta, *delta),
+        }
+    }
+}
+
+
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub enum Optimiser {
+    Sgd { learning_rate: f64 },
+    // Kingma and Ba (2014), https://arxiv.org/abs/1412.6980
+    Adam { learning_rate: f64, beta_1: f64, beta_2: f64, epsilon: f64 }
+}
+
+impl Optimiser {
+    // Adam with the defaults recommended in the paper.
+    pub fn adam(learning_rate: f64) -> Optimiser {
+        Optimiser::Adam { learning_rate, beta_1: 0.9, beta_2: 0.999, epsilon: 1e-8 }
+    }
+
+    // Moves the parameters against their gradients. step is the number of
+    // updates so far, including this one, for Adam's bias correction.
+    fn update(&self, parameters: &mut [f64], gradients: &[f64], moments: &mut Moments, step: i32) {
+        match *self {
+            Optimiser::Sgd { learning_rate } => {
+                for (parameter, gradient) in parameters.iter_mut().zip(gradients) {
+                    *parameter -= learning_rate * gradient;
+                }
+            },
+            Optimiser::Adam { learning_rate, beta_1, beta_2, epsilon } => {
+                let first_correction = 1.0 - beta_1.powi(step);
+                let second_correction = 1.0 - beta_2.powi(step);
+                for (index, gradient) in gradients.iter().enumerate() {
+                    moments.first[index] = beta_1 * moments.first[index] + (1.0 - beta_1) * gradient;
+                    moments.second[index] = beta_2 * moments.second[index] + (1.0 - beta_2) * gradient * gradient;
+                    let first = moments.first[index] / first_correction;
+                    let second = moments.second[index] / second_correction;
+                    parameters[index] -= learning_rate * first / (second.sqrt() + epsilon);
+                }
+            }
+        }
+    }
+}
+
+
+// Adam's running averages of the gradient and squared gradient.
+#[derive(Clone)]
+#[derive(Debug)]
+struct Moments {
+    first: Vec<f64>,
+    second: Vec<f64>,
+}
+
+impl Moments {
+    fn new(len: usize) -> Moments {
+        Moments { first: vec![0.0; len], second: vec![0.0; len] }
+    }
+}
+
+
+// A fully connected layer, outputs = activation(weights * inputs + biases).
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct Dense {
+    pub inputs: usize,
+    pub outputs: usize,
+    /*
+    * Row-major, so the weights into output o are
+    * weights[o * inputs..(o + 1) * inputs].
+    */
+    pub weights: Vec<f64>,
+    pub biases: Vec<f64>,
+    pub activation: Activation,
+    weight_moments: Moments,
+    bias_moments: Moments,
+}
+
+impl Dense {
+    // He initialisation for ReLU layers and Xavier initialisation otherwise.
+    pub fn new<R: Rng + ?Sized>(inputs: usize, outputs: usize, activation: Activation, rng: &mut R) -> Dense {
+        let scale = match activation {
+            Activation::ReLU => (2.0 / inputs as f64).sqrt(),
+            _ => (1.0 / inputs as f64).sqrt(),
+        };
+        let weights = (0..inputs * outputs).map(|_| scale * sample_standard_normal(rng)).collect();
+        Dense {
+            inputs,
+            outputs,
+            weights,
+            biases: vec![0.0; outputs],
+            activation,
+            weight_moments: Moments::new(inputs * outputs),
+            bias_moments: Moments::new(outputs),
+        }
+    }
+
+    pub fn forward(&self, input: &[f64]) -> Vec<f64> {
+        (0..self.outputs)
+            .map(|output| {
+                let weights = &self.weights[output * self.inputs..(output + 1) * self.inputs];
+                let sum: f64 = weights.iter().zip(input).map(|(weight, x)| weight * x).sum();
+                self.activation.apply(sum + self.biases[output])
+            })
+            .collect()
+    }
+}
+
+
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct NeuralNetwork {
+    pub layers: Vec<Dense>,
+    pub optimiser: Optimiser,
+    steps: i32,
+}
+
+impl NeuralNetwork {
+    // layer_sizes runs from the input size to the output size, e.g. [18, 64, 9].
+    // Hidden layers use hidden_activation and the output layer is linear.
+    pub fn new<R: Rng + ?Sized>(layer_sizes: &[usize], hidden_activation: Activation, optimiser: Optimiser, rng: &mut R) -> NeuralNetwork {
+        if layer_sizes.len() < 2 {
+            panic!("A network needs at least an input and an output size");
+        }
+        let last = layer_sizes.len() - 2;
+        let layers = layer_sizes.windows(2)
+            .enumerate()
+            .map(|(index, sizes)| {
+                let activation = if index == last { Activation::Identity } else { hidden_activation };
+                Dense::new(sizes[0], sizes[1], activation, rng)
+            })
+            .collect();
+        NeuralNetwork { layers, optimiser, steps: 0 }
+    }
+
+    pub fn input_size(&self) -> usize {
+        self.layers[0].inputs
+    }
+
+    pub fn output_size(&self) -> usize {
+        self.layers[self.layers.len() - 1].outputs
+    }
+
+    pub fn predict(&self, input: &[f64]) -> Vec<f64> {
+        let mut activations = input.to_vec();
+        for layer in &self.layers {
+            activations = layer.forward(&activations);
+        }
+        activations
+    }
+
+    // The input followed by every layer's output.
+    fn forward_all(&self, input: &[f64]) -> Vec<Vec<f64>> {
+        let mut activations = vec![input.to_vec()];
+        for layer in &self.layers {
+            let output = layer.forward(&activations[activations.len() - 1]);
+            activations.push(output);
+        }
+        activations
+    }
+
+    // One optimiser step on the average loss over the batch, returning that
+    // average loss. Outputs whose target equals the prediction contribute
+    // no gradient, which is how a single output can be trained.
+    pub fn train_batch(&mut self, inputs: &[Vec<f64>], targets: &[Vec<f64>], loss: Loss) -> f64 {
+        self.train_weighted_batch(inputs, targets, &vec![1.0; inputs.len()], loss)
+    }
+
+    // As train_batch, with each example's loss scaled by its weight.
+    pub fn train_weighted_batch(&mut self, inputs: &[Vec<f64>], targets: &[Vec<f64>], weights: &[f64], loss: Loss) -> f64 {
+        let mut weight_gradients: Vec<Vec<f64>> = self.layers.iter().map(|layer| vec![0.0; layer.weights.len()]).collect();
+        let mut bias_gradients: Vec<Vec<f64>> = self.layers.iter().map(|layer| vec![0.0; layer.biases.len()]).collect();
+        let mut total_loss = 0.0;
+
+        for ((input, target), weight) in inputs.iter().zip(targets).zip(weights) {
+            let activations = self.forward_all(input);
+            let prediction = &activations[activations.len() - 1];
+            total_loss += weight * prediction.iter().zip(target).map(|(p, t)| loss.value(*p, *t)).sum::<f64>();
+
+            // Gradient of the loss with respect to each layer's output,
+            // propagated back from the output layer.
+            let mut output_gradients: Vec<f64> = prediction.iter().zip(target).map(|(p, t)| weight * loss.gradient(*p, *t)).collect();
+            for (index, layer) in self.layers.iter().enumerate().rev() {
+                let layer_input = &activations[index];
+                let layer_output = &activations[index + 1];
+                let mut input_gradients = vec![0.0; layer.inputs];
+                for output in 0..layer.outputs {
+                    let delta = output_gradients[output] * layer.activation.derivative(layer_output[output]);
+                    if delta == 0.0 {
+                        continue;
+                    }
+                    bias_gradients[index][output] += delta;
+                    let row = output * layer.inputs;
+                    for input in 0..layer.inputs {
+                        weight_gradients[index][row + input] += delta * layer_input[input];
+                        input_gradients[input] += delta * layer.weights[row + input];
+                    }
+                }
+                output_gradients = input_gradients;
+            }
+        }
+
+        let batch_size = inputs.len() as f64;
+        self.steps += 1;
+        for (index, layer) in self.layers.iter_mut().enumerate() {
+            for gradient in weight_gradients[index].iter_mut().chain(bias_gradients[index].iter_mut()) {
+                *gradient /= batch_size;
+            }
+            self.optimiser.update(&mut layer.weights, &weight_gradients[index], &mut layer.weight_moments, self.steps);
+            self.optimiser.update(&mut layer.biases, &bias_gradients[index], &mut layer.bias_moments, self.steps);
+        }
+        total_loss / batch_size
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::{Activation, Loss, NeuralNetwork, Optimiser};
+
+    fn xor_data() -> (Vec<Vec<f64>>, Vec<Vec<f64>>) {
+        let inputs = vec![vec![0.0, 0.0], vec![0.0, 1.0], vec![1.0, 0.0], vec![1.0, 1.0]];
+        let targets = vec![vec![0.0], vec![1.0], vec![1.0], vec![0.0]];
+        (inputs, targets)
+    }
+
+    #[test]
+    fn huber_loss_gradient_is_clipped() {
+        let huber = Loss::Huber(1.0);
+        assert_eq!(huber.gradient(0.5, 0.0), 0.5);
+        assert_eq!(huber.gradient(5.0, 0.0), 1.0);
+        assert_eq!(huber.gradient(-5.0, 0.0), -1.0);
+        assert_eq!(huber.value(3.0, 0.0), 2.5);
+        assert_eq!(Loss::MeanSquaredError.gradient(5.0, 0.0), 5.0);
+    }
+
+    #[test]
+    fn gradients_match_finite_differences() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let network = NeuralNetwork::new(&[3, 4, 2], Activation::Tanh, Optimiser::Sgd { learning_rate: 1.0 }, &mut rng);
+        let input = vec![vec![0.3, -0.7, 0.5]];
+        let target = vec![vec![0.2, -0.4]];
+        let loss = |network: &NeuralNetwork| -> f64 {
+            network.predict(&input[0]).iter().zip(&target[0]).map(|(p, t)| Loss::MeanSquaredError.value(*p, *t)).sum()
+        };
+
+        // With a learning rate of one, an SGD step subtracts the gradient.
+        let mut trained = network.clone();
+        trained.train_batch(&input, &target, Loss::MeanSquaredError);
+        for (weight, _) in network.layers[0].weights.iter().enumerate() {
+            let mut nudged = network.clone();
+            nudged.layers[0].weights[weight] += 1e-6;
+            let numerical = (loss(&nudged) - loss(&network)) / 1e-6;
+            let analytical = network.layers[0].weights[weight] - trained.layers[0].weights[weight];
+            assert!((numerical - analytical).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn network_learns_xor() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let (inputs, targets) = xor_data();
+        for optimiser in [Optimiser::Sgd { learning_rate: 0.1 }, Optimiser::adam(0.01)] {
+            let mut network = NeuralNetwork::new(&[2, 8, 1], Activation::Tanh, optimiser, &mut rng);
+            for _ in 0..3000 {
+                network.train_batch(&inputs, &targets, Loss::MeanSquaredError);
+            }
+            for (input, target) in inputs.iter().zip(&targets) {
+                assert!((network.predict(input)[0] - target[0]).abs() < 0.1);
+            }
+        }
+    }
+}
@@ -0,0 +1,382 @@
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+use crate::reinforcement_learning::generic_reinforcement_learner::{Environment, Action};
+
+// Nim and other impartial games, where both players have the same moves and
+// the optimal strategy is known in closed form through Sprague-Grundy theory.
+
+pub trait NimRules {
+    fn initial_heaps() -> Vec<u32>;
+    // Under misère play whoever takes the last object loses.
+    fn misere() -> bool {
+        false
+    }
+    // The amounts that may be taken from a heap in one move. None means any
+    // positive amount, as in ordinary Nim.
+    fn subtraction_set() -> Option<Vec<u32>> {
+        None
+    }
+}
+
+pub struct StandardNim;
+
+impl NimRules for StandardNim {
+    fn initial_heaps() -> Vec<u32> {
+        vec![3, 4, 5]
+    }
+}
+
+pub struct MisereNim;
+
+impl NimRules for MisereNim {
+    fn initial_heaps() -> Vec<u32> {
+        vec![3, 4, 5]
+    }
+
+    fn misere() -> bool {
+        true
+    }
+}
+
+// A single heap from which each player takes one, two or three objects.
+pub struct SubtractionGame;
+
+impl NimRules for SubtractionGame {
+    fn initial_heaps() -> Vec<u32> {
+        vec![21]
+    }
+
+    fn subtraction_set() -> Option<Vec<u32>> {
+        Some(vec![1, 2, 3])
+    }
+}
+
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug)]
+pub struct NimMove {
+    heap: usize,
+    take: u32,
+}
+
+impl NimMove {
+    pub fn new(heap: usize, take: u32) -> NimMove {
+        NimMove { heap, take }
+    }
+}
+
+impl Action for NimMove {}
+
+impl fmt::Display for NimMove {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "take {} from heap {}", self.take, self.heap)
+    }
+}
+
+
+pub struct NimState<R>
+where
+    R: NimRules
+{
+    heaps: Vec<u32>,
+    rules: PhantomData<R>,
+}
+
+// Implemented by hand, as deriving would require the rules to implement them too.
+impl<R: NimRules> Clone for NimState<R> {
+    fn clone(&self) -> Self {
+        NimState::new(self.heaps.clone())
+    }
+}
+
+impl<R: NimRules> PartialEq for NimState<R> {
+    fn eq(&self, other: &Self) -> bool {
+        self.heaps == other.heaps
+    }
+}
+
+impl<R: NimRules> Eq for NimState<R> {}
+
+impl<R: NimRules> Hash for NimState<R> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.heaps.hash(state);
+    }
+}
+
+impl<R: NimRules> fmt::Debug for NimState<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("NimState").field("heaps", &self.heaps).finish()
+    }
+}
+
+impl<R: NimRules> fmt::Display for NimState<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (heap, size) in self.heaps.iter().enumerate() {
+            writeln!(f, "Heap {heap}: {} ({size})", "|".repeat(*size as usize))?;
+        }
+        Ok(())
+    }
+}
+
+impl<R: NimRules> Environment for NimState<R> {
+    type A = NimMove;
+
+    fn initial_state() -> Self {
+        NimState::new(R::initial_heaps())
+    }
+
+    fn next_state(&self, action: &NimMove) -> Self {
+        let mut clone = self.clone();
+        clone.heaps[action.heap] -= action.take;
+        clone
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.heaps.iter().all(|size| *size == 0)
+    }
+
+    fn alternates_players() -> bool {
+        true
+    }
+
+    fn available_actions(&self) -> Vec<NimMove> {
+        let mut moves = Vec::new();
+        for (heap, size) in self.heaps.iter().enumerate() {
+            for take in Self::allowed_takes(*size) {
+                moves.push(NimMove { heap, take });
+            }
+        }
+        moves
+    }
+
+    fn get_reward(_state: &Self, _action: &NimMove, next_state: &Self) -> f64 {
+        if !next_state.is_terminal() { return 0.0; }
+        // The mover took the last object
+        if R::misere() { -1.0 } else { 1.0 }
+    }
+}
+
+impl<R: NimRules> NimState<R> {
+    pub fn new(heaps: Vec<u32>) -> Self {
+        NimState { heaps, rules: PhantomData }
+    }
+
+    pub fn heaps(&self) -> &[u32] {
+        &self.heaps
+    }
+
+    fn allowed_takes(size: u32) -> Vec<u32> {
+        match R::subtraction_set() {
+            Some(set) => set.into_iter().filter(|take| *take <= size).collect(),
+            None => (1..=size).collect()
+        }
+    }
+
+    // The Sprague-Grundy value of a single heap: the minimum excludant of
+    // the values of the heaps reachable from it.
+    pub fn grundy_value(size: u32) -> u32 {
+        let set = match R::subtraction_set() {
+            Some(set) => set,
+            None => return size
+        };
+        let mut values: Vec<u32> = Vec::with_capacity(size as usize + 1);
+        for heap in 0..=size {
+            let reachable: Vec<u32> = set.iter()
+                .filter(|take| **take <= heap)
+                .map(|take| values[(heap - take) as usize])
+                .collect();
+            let mex = (0..).find(|value| !reachable.contains(value)).unwrap();
+            values.push(mex);
+        }
+        values[size as usize]
+    }
+
+    pub fn nim_sum(&self) -> u32 {
+        self.heaps.iter().fold(0, |sum, size| sum ^ Self::grundy_value(*size))
+    }
+
+    // Whether the player to move can force a win.
+    pub fn is_winning_position(&self) -> bool {
+        if !R::misere() {
+            return self.nim_sum() != 0;
+        }
+        if R::subtraction_set().is_none() {
+            // Misère Nim plays like normal Nim until every heap has at most
+            // one object, then the player to move wants an even number of them.
+            if self.heaps.iter().all(|size| *size <= 1) {
+                let ones = self.heaps.iter().filter(|size| **size == 1).count();
+                return ones % 2 == 0;
+            }
+            return self.nim_sum() != 0;
+        }
+        // No closed form for misère subtraction games, so search the game tree
+        if self.is_terminal() {
+            // The opponent took the last object
+            return true;
+        }
+        self.available_actions().iter().any(|action| !self.next_state(action).is_winning_position())
+    }
+
+    // The moves that leave the opponent in a losing position. Empty if the
+    // player to move is losing against perfect play.
+    pub fn winning_moves(&self) -> Vec<NimMove> {
+        self.available_actions()
+            .into_iter()
+            .filter(|action| !self.next_state(action).is_winning_position())
+            .collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::evaluation::{play_game, LearnerAgent, Outcome, PerfectAgent};
+    use crate::reinforcement_learning::{epsilon_greedy_policy::EpsilonGreedyPolicy, generic_reinforcement_learner::{Environment, ReinforcementLearner}, league::{League, OpponentSampling}, parallel::ParallelTrainer, q_learning_learner::QLearner, replay_buffer::PrioritizedReplayBuffer};
+
+    use super::{MisereNim, NimMove, NimRules, NimState, StandardNim, SubtractionGame};
+
+    // Whether the player to move can force a win, by brute force.
+    fn solve<R: NimRules>(state: &NimState<R>) -> bool {
+        if state.is_terminal() {
+            // The opponent took the last object
+            return R::misere();
+        }
+        state.available_actions().iter().any(|action| !solve(&state.next_state(action)))
+    }
+
+    fn reachable_states<R: NimRules>() -> Vec<NimState<R>> {
+        let mut seen = HashSet::new();
+        let mut frontier = vec![NimState::<R>::initial_state()];
+        while let Some(state) = frontier.pop() {
+            if !seen.insert(state.clone()) { continue };
+            for action in state.available_actions() {
+                frontier.push(state.next_state(&action));
+            }
+        }
+        seen.into_iter().collect()
+    }
+
+    fn assert_closed_form_matches_search<R: NimRules>() {
+        for state in reachable_states::<R>() {
+            assert_eq!(state.is_winning_position(), solve(&state), "{state}");
+        }
+    }
+
+    #[test]
+    fn nim_closed_form_matches_search() {
+        assert_closed_form_matches_search::<StandardNim>();
+        assert_closed_form_matches_search::<MisereNim>();
+        assert_closed_form_matches_search::<SubtractionGame>();
+    }
+
+    #[test]
+    fn subtraction_game_grundy_values_cycle() {
+        for size in 0..=21 {
+            assert_eq!(NimState::<SubtractionGame>::grundy_value(size), size % 4);
+        }
+    }
+
+    #[test]
+    fn nim_only_offers_legal_moves() {
+        let state = NimState::<SubtractionGame>::new(vec![2]);
+        assert_eq!(state.available_actions(), vec![NimMove::new(0, 1), NimMove::new(0, 2)]);
+
+        let state = NimState::<StandardNim>::new(vec![0, 2, 1]);
+        assert_eq!(state.available_actions(), vec![NimMove::new(1, 1), NimMove::new(1, 2), NimMove::new(2, 1)]);
+    }
+
+    struct SmallNim;
+
+    impl NimRules for SmallNim {
+        fn initial_heaps() -> Vec<u32> {
+            vec![2, 2, 3]
+        }
+    }
+
+    struct SmallMisereNim;
+
+    impl NimRules for SmallMisereNim {
+        fn initial_heaps() -> Vec<u32> {
+            vec![2, 2, 3]
+        }
+
+        fn misere() -> bool {
+            true
+        }
+    }
+
+    fn new_q_learner<R: NimRules>() -> QLearner<NimState<R>> {
+        QLearner::new(0.5, 0.9)
+    }
+
+    fn assert_q_learner_plays_optimally<R: NimRules>() {
+        let mut q_learner = new_q_learner::<R>();
+        let policy = EpsilonGreedyPolicy::new(0.5);
+        q_learner.q_learning(&policy, 5000);
+        assert_greedy_moves_win(&q_learner);
+    }
+
+    // Every winning position's greedy moves must all be winning moves.
+    fn assert_greedy_moves_win<R: NimRules>(q_learner: &QLearner<NimState<R>>) {
+        for state in reachable_states::<R>() {
+            let winning_moves = state.winning_moves();
+            if state.is_terminal() || winning_moves.is_empty() { continue };
+            let action_values = q_learner.get_action_values(&state);
+            let best_value = action_values.iter().map(|(_, value)| *value).fold(f64::NEG_INFINITY, f64::max);
+            for (action, value) in action_values {
+                if !winning_moves.contains(&action) {
+                    assert!(value < best_value, "{state}{action}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn q_learner_learns_optimal_nim_policy() {
+        assert_q_learner_plays_optimally::<SmallNim>();
+        assert_q_learner_plays_optimally::<SmallMisereNim>();
+    }
+
+    #[test]
+    fn q_learner_learns_optimal_nim_policy_from_replay() {
+        let mut q_learner = new_q_learner::<SmallNim>();
+        let policy = EpsilonGreedyPolicy::new(0.5);
+        let mut buffer = PrioritizedReplayBuffer::new(1000, 0.6, 0.4, 0);
+        q_learner.q_learning_with_replay_and_rng(&policy, 1000, &mut buffer, 1, 8, &mut StdRng::seed_from_u64(0));
+        assert_greedy_moves_win(&q_learner);
+    }
+
+    #[test]
+    fn q_learner_learns_optimal_nim_policy_in_parallel() {
+        let policy = EpsilonGreedyPolicy::new(0.5);
+        let mut shared = new_q_learner::<SmallNim>();
+        ParallelTrainer::new(4, 0).train_shared(&mut shared, &policy, 2000);
+        assert_greedy_moves_win(&shared);
+
+        let mut independent = new_q_learner::<SmallNim>();
+        ParallelTrainer::new(4, 0).train_independent(&mut independent, &policy, 5000);
+        assert_greedy_moves_win(&independent);
+    }
+
+    #[test]
+    fn q_learner_learns_optimal_nim_policy_from_league() {
+        let mut q_learner = new_q_learner::<SmallNim>();
+        let policy = EpsilonGreedyPolicy::new(0.5);
+        let mut league = League::new(500, 20, OpponentSampling::Uniform, 0);
+        q_learner.league_self_play(&policy, 5000, &mut league);
+        assert_eq!(league.snapshots.len(), 11);
+        // The league teaches a best response to its snapshots rather than
+        // minimax values, so check that it beats perfect play from a
+        // winning start instead of checking every position.
+        let mut perfect = PerfectAgent::new(0);
+        for _ in 0..20 {
+            assert_eq!(play_game::<NimState<SmallNim>>(&mut LearnerAgent::new(&q_learner), &mut perfect), Outcome::Win);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use rand::Rng;
+
+use super::generic_reinforcement_learner::{ContinuousState, Environment, IndexedActions};
+
+// Wraps a continuous state so that tabular learners can use it. Each
+// observation is cut into BINS equal bins over its bounds, with values
+// outside the bounds going into the first or last bin, and two states are
+// equal when all of their observations fall into the same bins.
+
+#[derive(Clone)]
+pub struct Discretised<E, const BINS: usize = 10>
+where
+    E: ContinuousState
+{
+    inner: E,
+    bins: Vec<usize>,
+}
+
+impl<E, const BINS: usize> Discretised<E, BINS>
+where
+    E: ContinuousState
+{
+    pub fn new(inner: E) -> Self {
+        let bins = inner.observation()
+            .iter()
+            .zip(E::observation_bounds())
+            .map(|(value, (low, high))| {
+                let fraction = (value - low) / (high - low);
+                ((fraction * BINS as f64).floor().max(0.0) as usize).min(BINS - 1)
+            })
+            .collect();
+        Discretised { inner, bins }
+    }
+
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+
+    pub fn bins(&self) -> &[usize] {
+        &self.bins
+    }
+}
+
+impl<E, const BINS: usize> PartialEq for Discretised<E, BINS>
+where
+    E: ContinuousState
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.bins == other.bins
+    }
+}
+
+impl<E, const BINS: usize> Eq for Discretised<E, BINS>
+where
+    E: ContinuousState
+{
+}
+
+impl<E, const BINS: usize> Hash for Discretised<E, BINS>
+where
+    E: ContinuousState
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.bins.hash(state);
+    }
+}
+
+impl<E, const BINS: usize> fmt::Display for Discretised<E, BINS>
+where
+    E: ContinuousState
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {:?}", self.inner, self.bins)
+    }
+}
+
+impl<E, const BINS: usize> Environment for Discretised<E, BINS>
+where
+    E: ContinuousState
+{
+    type A = E::A;
+
+    fn initial_state() -> Self {
+        Self::new(E::initial_state())
+    }
+
+    fn next_state(&self, action: &Self::A) -> Self {
+        Self::new(self.inner.next_state(action))
+    }
+
+    fn sample_initial_state<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        Self::new(E::sample_initial_state(rng))
+    }
+
+    fn sample_next_state<R: Rng + ?Sized>(&self, action: &Self::A, rng: &mut R) -> Self {
+        Self::new(self.inner.sample_next_state(action, rng))
+    }
+
+    fn alternates_players() -> bool {
+        E::alternates_players()
+    }
+
+    fn shaping_discount() -> Option<f64> {
+        E::shaping_discount()
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.inner.is_terminal()
+    }
+
+    fn available_actions(&self) -> Vec<Self::A> {
+        self.inner.available_actions()
+    }
+
+    fn get_reward(state: &Self, action: &Self::A, next_state: &Self) -> f64 {
+        E::get_reward(&state.inner, action, &next_state.inner)
+    }
+
+    fn get_opponent_reward(state: &Self, action: &Self::A, next_state: &Self) -> f64 {
+        E::get_opponent_reward(&state.inner, action, &next_state.inner)
+    }
+
+    fn get_result(state: &Self, action: &Self::A, next_state: &Self) -> f64 {
+        E::get_result(&state.inner, action, &next_state.inner)
+    }
+}
+
+
+impl<E, const BINS: usize> IndexedActions for Discretised<E, BINS>
+where
+    E: ContinuousState + IndexedActions
+{
+    fn num_actions() -> usize {
+        E::num_actions()
+    }
+
+    fn action_index(action: &Self::A) -> usize {
+        E::action_index(action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::classic_control::mountain_car::{MountainCar, MAX_SPEED};
+
+    use super::Discretised;
+
+    #[test]
+    fn discretised_states_share_bins() {
+        let state = Discretised::<MountainCar, 10>::new(MountainCar { position: -0.51, velocity: 0.001, steps: 0 });
+        let nearby = Discretised::<MountainCar, 10>::new(MountainCar { position: -0.52, velocity: 0.002, steps: 3 });
+        let far = Discretised::<MountainCar, 10>::new(MountainCar { position: 0.2, velocity: 0.002, steps: 3 });
+        assert_eq!(state.bins(), &[3, 5]);
+        assert!(state == nearby);
+        assert!(state != far);
+    }
+
+    #[test]
+    fn discretised_states_clamp_to_the_outer_bins() {
+        let low = Discretised::<MountainCar, 4>::new(MountainCar { position: -5.0, velocity: -MAX_SPEED, steps: 0 });
+        let high = Discretised::<MountainCar, 4>::new(MountainCar { position: 5.0, velocity: MAX_SPEED, steps: 0 });
+        assert_eq!(low.bins(), &[0, 0]);
+        assert_eq!(high.bins(), &[3, 3]);
+    }
+}
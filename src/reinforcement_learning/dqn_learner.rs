@@ -0,0 +1,237 @@
+use crate::neural_network::{Loss, NeuralNetwork};
+
+use super::epsilon_greedy_policy::greedy_index;
+use super::generic_reinforcement_learner::{IndexedActions, Policy, ReinforcementLearner, TensorEncoding};
+use super::replay_buffer::{NStepBuffer, ReplayBuffer, Transition, UniformReplayBuffer};
+
+// Deep Q-networks, Mnih et al. (2015): https://www.nature.com/articles/nature14236
+// Q-learning where a neural network maps the encoded state to one value per
+// action. Transitions go into a replay buffer and the network is trained on
+// minibatches sampled from it, with targets from a copy of the network that
+// is only refreshed every so often.
+
+pub struct DqnLearner<S, B = UniformReplayBuffer<S>>
+where
+    S: TensorEncoding + IndexedActions,
+    B: ReplayBuffer<S>
+{
+    pub network: NeuralNetwork,
+    pub target_network: NeuralNetwork,
+    pub gamma: f64,
+    pub loss: Loss,
+    pub batch_size: usize,
+    // Number of training steps between copies into the target network
+    pub target_update_interval: u32,
+    pub buffer: B,
+    n_step: NStepBuffer<S>,
+    training_steps: u32,
+}
+
+
+impl<S, B> DqnLearner<S, B>
+where
+    S: TensorEncoding + IndexedActions,
+    B: ReplayBuffer<S>
+{
+    // The network needs S::encoding_len() inputs and S::num_actions() outputs.
+    pub fn new(network: NeuralNetwork, gamma: f64, buffer: B) -> Self {
+        if network.input_size() != S::encoding_len() || network.output_size() != S::num_actions() {
+            panic!(
+                "Network is {} -> {} but the state needs {} -> {}",
+                network.input_size(), network.output_size(), S::encoding_len(), S::num_actions()
+            );
+        }
+        DqnLearner {
+            target_network: network.clone(),
+            network,
+            gamma,
+            loss: Loss::Huber(1.0),
+            batch_size: 32,
+            target_update_interval: 500,
+            buffer,
+            n_step: NStepBuffer::new(1, gamma),
+            training_steps: 0,
+        }
+    }
+
+    // Stores n-step transitions in the replay buffer instead of one-step ones.
+    pub fn with_n_steps(mut self, n: usize) -> Self {
+        self.n_step = NStepBuffer::new(n, self.gamma);
+        self
+    }
+
+    // max_a Q(S', a) over the legal actions, using the target network.
+    fn target_next_state_value(&self, next_state: &S) -> f64 {
+        let values = self.target_network.predict(&next_state.encode());
+        next_state.available_actions()
+            .iter()
+            .map(|action| values[S::action_index(action)])
+            .fold(f64::NEG_INFINITY, f64::max)
+    }
+
+    // One gradient step on a minibatch from the replay buffer. Only the
+    // output for the action taken is moved towards its target.
+    fn learn_from_replay(&mut self) {
+        let sample = self.buffer.sample(self.batch_size);
+        let mut inputs = Vec::with_capacity(sample.len());
+        let mut targets = Vec::with_capacity(sample.len());
+        let mut td_errors = Vec::with_capacity(sample.len());
+        for (index, _) in &sample {
+            let transition = self.buffer.get(*index);
+            let input = transition.state.encode();
+            let mut target = self.network.predict(&input);
+            let discount = transition.bootstrap_discount(self.gamma);
+            let next_state_value = if discount == 0.0 { 0.0 } else { self.target_next_state_value(&transition.next_state) };
+            let action_index = S::action_index(&transition.action);
+            let target_value = transition.reward + discount * next_state_value;
+            td_errors.push(target_value - target[action_index]);
+            target[action_index] = target_value;
+            inputs.push(input);
+            targets.push(target);
+        }
+        let indices: Vec<usize> = sample.iter().map(|(index, _)| *index).collect();
+        let weights: Vec<f64> = sample.iter().map(|(_, weight)| *weight).collect();
+        self.network.train_weighted_batch(&inputs, &targets, &weights, self.loss);
+        self.buffer.update_priorities(&indices, &td_errors);
+
+        self.training_steps += 1;
+        if self.training_steps % self.target_update_interval == 0 {
+            self.target_network = self.network.clone();
+        }
+    }
+
+    pub fn q_learning(&mut self, policy: &dyn Policy<S>, num_episodes: u32) {
+        let mut rng = rand::thread_rng();
+
+        for episode in 1..=num_episodes {
+            if episode % 1000 == 0 {
+                println!("Episode: {episode} / {num_episodes}");
+            }
+
+            let mut state = S::sample_initial_state(&mut rng);
+            while !state.is_terminal() {
+                let action = policy.get_action(&mut self.get_action_values(&state));
+                let next_state = state.sample_next_state(&action, &mut rng);
+                let reward = S::get_reward(&state, &action, &next_state);
+                self.update_action_value(&state, &action, &next_state, reward);
+                state = next_state;
+            }
+        }
+    }
+}
+
+
+impl<S, B> ReinforcementLearner<S> for DqnLearner<S, B>
+where
+    S: TensorEncoding + IndexedActions,
+    B: ReplayBuffer<S>
+{
+    fn get_action_value(&self, state: &S, action: &S::A) -> f64 {
+        self.network.predict(&state.encode())[S::action_index(action)]
+    }
+
+    // Only legal actions are returned, so illegal outputs are never chosen.
+    fn get_action_values(&self, state: &S) -> Vec<(S::A, f64)> {
+        let values = self.network.predict(&state.encode());
+        state.available_actions()
+            .into_iter()
+            .map(|action| {
+                let value = values[S::action_index(&action)];
+                (action, value)
+            })
+            .collect()
+    }
+
+    fn get_best_action(&self, state: &S) -> S::A {
+        let mut actions_and_values = self.get_action_values(state);
+        let values: Vec<f64> = actions_and_values.iter().map(|(_, value)| *value).collect();
+        let best = greedy_index(&values, &mut rand::thread_rng());
+        actions_and_values.swap_remove(best).0
+    }
+
+    // Stores the transition and, once there are enough of them, trains on a
+    // minibatch from the replay buffer.
+    fn update_action_value(&mut self, state: &S, action: &S::A, next_state: &S, reward: f64) {
+        let transition = Transition::new(state.clone(), action.clone(), reward, next_state.clone());
+        for completed in self.n_step.push(transition) {
+            self.buffer.push(completed);
+        }
+        if self.buffer.len() >= self.batch_size {
+            self.learn_from_replay();
+        }
+    }
+
+    fn get_state_value(&self, state: &S) -> f64 {
+        if state.is_terminal() {
+            return 0.0;
+        }
+        self.get_action_values(state)
+            .into_iter()
+            .map(|(_, value)| value)
+            .fold(f64::NEG_INFINITY, f64::max)
+    }
+
+    fn update_state_value(&mut self, _state: &S, _value: f64) {
+        panic!("DQN learner cannot directly update state value.")
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::neural_network::{Activation, NeuralNetwork, Optimiser};
+    use crate::reinforcement_learning::{epsilon_greedy_policy::EpsilonGreedyPolicy, generic_reinforcement_learner::{Environment, ReinforcementLearner}};
+    use crate::tictactoe::{BoardEntry, TicTacToeBoard, TicTacToeMove};
+
+    use crate::reinforcement_learning::replay_buffer::{PrioritizedReplayBuffer, ReplayBuffer, UniformReplayBuffer};
+
+    use super::DqnLearner;
+
+    fn tictactoe_network() -> NeuralNetwork {
+        let mut rng = StdRng::seed_from_u64(0);
+        NeuralNetwork::new(&[18, 32, 9], Activation::ReLU, Optimiser::adam(0.001), &mut rng)
+    }
+
+    fn tictactoe_learner() -> DqnLearner<TicTacToeBoard> {
+        DqnLearner::new(tictactoe_network(), 0.9, UniformReplayBuffer::new(10000, 0))
+    }
+
+    #[test]
+    fn dqn_learns_the_value_of_a_winning_move() {
+        let mut learner = tictactoe_learner();
+        learner.batch_size = 4;
+        let mut board = TicTacToeBoard::try_from([
+            "XX ",
+            "OO ",
+            "   ",
+        ].concat()).unwrap();
+        board.current_player = BoardEntry::X;
+        let winning_move = TicTacToeMove::new(0, 2);
+        let next_board = board.next_state(&winning_move);
+        for _ in 0..500 {
+            learner.update_action_value(&board, &winning_move, &next_board, 1.0);
+        }
+        assert!((learner.get_action_value(&board, &winning_move) - 1.0).abs() < 0.1);
+        assert_eq!(learner.get_best_action(&board), winning_move);
+        assert_eq!(learner.get_action_values(&board).len(), board.available_actions().len());
+    }
+
+    #[test]
+    fn dqn_trains_from_a_prioritized_n_step_buffer() {
+        let buffer = PrioritizedReplayBuffer::new(50, 0.6, 0.4, 0);
+        let mut learner = DqnLearner::<TicTacToeBoard, _>::new(tictactoe_network(), 0.9, buffer).with_n_steps(3);
+        learner.q_learning(&EpsilonGreedyPolicy::new(0.5), 30);
+        assert_eq!(learner.buffer.len(), 50);
+        assert!((0..50).any(|index| learner.buffer.get(index).steps == 3));
+    }
+
+    #[test]
+    #[should_panic]
+    fn dqn_rejects_a_network_of_the_wrong_shape() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let network = NeuralNetwork::new(&[18, 8, 7], Activation::ReLU, Optimiser::adam(0.001), &mut rng);
+        DqnLearner::<TicTacToeBoard>::new(network, 0.9, UniformReplayBuffer::new(100, 0));
+    }
+}
@@ -1,14 +1,14 @@
 use std::marker::PhantomData;
 
-use rand::Rng;
+use rand::{Rng, RngCore};
 
-use super::generic_reinforcement_learner::{State, Policy};
+use super::generic_reinforcement_learner::{Environment, Policy};
 
 
 
 pub struct EpsilonGreedyPolicy<S>
 where 
-    S: State
+    S: Environment
 {
     pub epsilon: f64,
     state: PhantomData<S>,
@@ -16,9 +16,43 @@ where
 }
 
 
+// Index of the largest value, breaking ties uniformly at random.
+pub fn greedy_index<R: Rng + ?Sized>(values: &[f64], rng: &mut R) -> usize {
+    if values.is_empty() {
+        panic!("No actions available, state is terminal?");
+    }
+    let mut max = f64::NEG_INFINITY;
+    let mut best_indices = Vec::new();
+    for (index, value) in values.iter().enumerate() {
+        if *value > max {
+            max = *value;
+            best_indices = Vec::new();
+            best_indices.push(index);
+        } else if *value == max {
+            best_indices.push(index);
+        }
+    }
+    best_indices[rng.gen_range(0..best_indices.len())]
+}
+
+
+// With probability epsilon picks a uniformly random index, otherwise a greedy one.
+pub fn epsilon_greedy_index<R: Rng + ?Sized>(values: &[f64], epsilon: f64, rng: &mut R) -> usize {
+    if values.is_empty() {
+        panic!("No moves available");
+    }
+    let random_value: f64 = rng.gen();
+    if random_value > epsilon {
+        greedy_index(values, rng)
+    } else {
+        rng.gen_range(0..values.len())
+    }
+}
+
+
 impl<S> EpsilonGreedyPolicy<S>
 where 
-    S: State
+    S: Environment
 {
     pub fn new(epsilon: f64) -> Self {
         Self {
@@ -27,67 +61,62 @@ where
             action: PhantomData
         }
     }
-
-    fn get_best_action(actions_and_values: &Vec<(S::A, f64)>) -> S::A {
-        if actions_and_values.is_empty() {
-            panic!("No actions available, state is terminal?");
-        }
-        let mut max: f64 = -1000.0;
-        let mut best_actions = Vec::new();
-        for (action, value) in actions_and_values {
-            if cfg!(debug_assertions) {
-                println!("{}: {}", action, value);
-            }
-            if *value > max {
-                max = *value;
-                best_actions = Vec::new();
-                best_actions.push(action);
-            } else if *value == max {
-                best_actions.push(action);
-            }
-        }
-        if cfg!(debug_assertions) {
-            print!("Best actions: ");
-            for action in &best_actions {
-                print!("{}, ", action);
-            }
-            println!();
-        }
-        let mut thread_rng = rand::thread_rng();
-        let length = best_actions.len();
-        let chosen_action = thread_rng.gen_range(0..length);
-        best_actions[chosen_action].clone()
-    }
 }
 
 
 impl<S> Policy<S> for EpsilonGreedyPolicy<S>
 where
-    S: State
+    S: Environment
 {
-    fn get_action(&self, actions_and_values: &mut Vec<(S::A, f64)>) -> S::A {
-        let mut thread_rng = rand::thread_rng();
+    fn sample_action(&self, actions_and_values: &mut Vec<(S::A, f64)>, rng: &mut dyn RngCore) -> S::A {
         if actions_and_values.is_empty() {
             panic!("No moves available");
         }
         if actions_and_values.len() == 1 {
             return actions_and_values.remove(0).0;
         }
-        let random_value: f64 = thread_rng.gen();
-        if random_value > self.epsilon {
-            let best_action = Self::get_best_action(actions_and_values);
-            if cfg!(debug_assertions) {
-                println!("Chosen best action: {}", best_action);
-            }
-            best_action
-        } else {
-            let length = actions_and_values.len();
-            let chosen_action = thread_rng.gen_range(0..length);
-            let action = actions_and_values.remove(chosen_action).0;
-            if cfg!(debug_assertions) {
-                println!("Chosen random action: {}", action);
+        if cfg!(debug_assertions) {
+            for (action, value) in actions_and_values.iter() {
+                println!("{}: {}", action, value);
             }
-            action
         }
+        let values: Vec<f64> = actions_and_values.iter().map(|(_, value)| *value).collect();
+        let chosen_action = epsilon_greedy_index(&values, self.epsilon, rng);
+        let action = actions_and_values.remove(chosen_action).0;
+        if cfg!(debug_assertions) {
+            println!("Chosen action: {}", action);
+        }
+        action
     }
-}
\ No newline at end of file
+
+    // Ties for the best value share the greedy probability equally.
+    fn action_probabilities(&self, actions_and_values: &[(S::A, f64)]) -> Vec<f64> {
+        let max = actions_and_values.iter().map(|(_, value)| *value).fold(f64::NEG_INFINITY, f64::max);
+        let num_best = actions_and_values.iter().filter(|(_, value)| *value == max).count();
+        let explore = self.epsilon / actions_and_values.len() as f64;
+        actions_and_values.iter()
+            .map(|(_, value)| {
+                if *value == max { explore + (1.0 - self.epsilon) / num_best as f64 } else { explore }
+            })
+            .collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::nim::{NimMove, NimState, StandardNim};
+    use crate::reinforcement_learning::generic_reinforcement_learner::Policy;
+
+    use super::EpsilonGreedyPolicy;
+
+    #[test]
+    fn epsilon_greedy_probabilities_split_ties() {
+        let policy = EpsilonGreedyPolicy::<NimState<StandardNim>>::new(0.3);
+        let values = vec![(NimMove::new(0, 1), 1.0), (NimMove::new(0, 2), 0.0), (NimMove::new(1, 1), 1.0)];
+        let probabilities = policy.action_probabilities(&values);
+        assert!((probabilities[0] - 0.45).abs() < 1e-12);
+        assert!((probabilities[1] - 0.1).abs() < 1e-12);
+        assert_eq!(probabilities[0], probabilities[2]);
+    }
+}
@@ -0,0 +1,156 @@
+use std::marker::PhantomData;
+
+use super::generic_reinforcement_learner::{ContinuousState, Environment, IndexedActions};
+
+// Sparse feature vectors as (index, value) pairs. Features not listed are zero.
+pub type Features = Vec<(usize, f64)>;
+
+pub trait FeatureExtractor<S>
+where
+    S: Environment
+{
+    fn num_features(&self) -> usize;
+    fn features(&self, state: &S, action: &S::A) -> Features;
+}
+
+
+// Features of a state alone, for approximating state values.
+pub trait StateFeatureExtractor<S>
+where
+    S: Environment
+{
+    fn num_state_features(&self) -> usize;
+    fn state_features(&self, state: &S) -> Features;
+}
+
+
+// Tile coding, section 9.5.4 of Sutton and Barto:
+// https://web.stanford.edu/class/psych209/Readings/SuttonBartoIPRLBook2ndEd.pdf
+// Each tiling cuts the observation space into a grid of tiles, and each
+// tiling is offset from the others, so that every observation falls into
+// exactly one tile per tiling and nearby observations share most tiles.
+#[derive(Clone, Debug)]
+pub struct TileCoder {
+    pub num_tilings: usize,
+    pub tiles_per_dimension: usize,
+    pub bounds: Vec<(f64, f64)>,
+}
+
+impl TileCoder {
+    pub fn new(num_tilings: usize, tiles_per_dimension: usize, bounds: Vec<(f64, f64)>) -> TileCoder {
+        TileCoder { num_tilings, tiles_per_dimension, bounds }
+    }
+
+    // The offsets push tiles beyond the upper bound, hence the extra tile.
+    fn tiles_per_tiling(&self) -> usize {
+        (self.tiles_per_dimension + 1).pow(self.bounds.len() as u32)
+    }
+
+    pub fn num_tiles(&self) -> usize {
+        self.num_tilings * self.tiles_per_tiling()
+    }
+
+    // The index of the active tile in each tiling.
+    pub fn tiles(&self, observation: &[f64]) -> Vec<usize> {
+        let tiles_per_tiling = self.tiles_per_tiling();
+        (0..self.num_tilings)
+            .map(|tiling| {
+                let mut index = 0;
+                for (dimension, (value, (low, high))) in observation.iter().zip(&self.bounds).enumerate() {
+                    let scaled = ((value - low) / (high - low)).clamp(0.0, 1.0) * self.tiles_per_dimension as f64;
+                    // Offset each dimension by a different odd multiple of
+                    // 1 / num_tilings of a tile, as the book recommends.
+                    let displacement = (2 * dimension + 1) * tiling % self.num_tilings;
+                    let offset = displacement as f64 / self.num_tilings as f64;
+                    let coordinate = (scaled + offset).floor() as usize;
+                    index = index * (self.tiles_per_dimension + 1) + coordinate;
+                }
+                tiling * tiles_per_tiling + index
+            })
+            .collect()
+    }
+}
+
+
+// Binary tile-coded features of a continuous state, with a separate set of
+// tiles for each action.
+pub struct TileCodingFeatures<S>
+where
+    S: ContinuousState + IndexedActions
+{
+    pub tile_coder: TileCoder,
+    state: PhantomData<S>,
+}
+
+impl<S> TileCodingFeatures<S>
+where
+    S: ContinuousState + IndexedActions
+{
+    // Tiles spanning the state's observation bounds.
+    pub fn new(num_tilings: usize, tiles_per_dimension: usize) -> Self {
+        let tile_coder = TileCoder::new(num_tilings, tiles_per_dimension, S::observation_bounds());
+        TileCodingFeatures { tile_coder, state: PhantomData }
+    }
+}
+
+impl<S> FeatureExtractor<S> for TileCodingFeatures<S>
+where
+    S: ContinuousState + IndexedActions
+{
+    fn num_features(&self) -> usize {
+        self.tile_coder.num_tiles() * S::num_actions()
+    }
+
+    fn features(&self, state: &S, action: &S::A) -> Features {
+        let action_offset = S::action_index(action) * self.tile_coder.num_tiles();
+        self.tile_coder.tiles(&state.observation())
+            .into_iter()
+            .map(|tile| (action_offset + tile, 1.0))
+            .collect()
+    }
+}
+
+impl<S> StateFeatureExtractor<S> for TileCodingFeatures<S>
+where
+    S: ContinuousState + IndexedActions
+{
+    fn num_state_features(&self) -> usize {
+        self.tile_coder.num_tiles()
+    }
+
+    fn state_features(&self, state: &S) -> Features {
+        self.tile_coder.tiles(&state.observation())
+            .into_iter()
+            .map(|tile| (tile, 1.0))
+            .collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::TileCoder;
+
+    #[test]
+    fn tile_coder_activates_one_tile_per_tiling() {
+        let tile_coder = TileCoder::new(4, 8, vec![(0.0, 1.0), (-1.0, 1.0)]);
+        let tiles = tile_coder.tiles(&[0.5, 0.0]);
+        assert_eq!(tiles.len(), 4);
+        for (tiling, tile) in tiles.iter().enumerate() {
+            assert_eq!(tile / 81, tiling);
+        }
+        assert!(tiles.iter().all(|tile| *tile < tile_coder.num_tiles()));
+    }
+
+    #[test]
+    fn tile_coder_generalises_to_nearby_observations() {
+        let tile_coder = TileCoder::new(8, 8, vec![(0.0, 1.0), (0.0, 1.0)]);
+        let tiles = tile_coder.tiles(&[0.5, 0.5]);
+        let shared = |observation: &[f64]| {
+            tile_coder.tiles(observation).iter().filter(|tile| tiles.contains(tile)).count()
+        };
+        assert_eq!(shared(&[0.5, 0.5]), 8);
+        assert!(shared(&[0.51, 0.5]) >= 6);
+        assert_eq!(shared(&[0.9, 0.1]), 0);
+    }
+}
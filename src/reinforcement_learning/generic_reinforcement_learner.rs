@@ -3,18 +3,124 @@
 use std::fmt::Display;
 use core::hash::Hash;
 
-pub trait State: Display + Eq + Hash + Clone
+use rand::{Rng, RngCore};
+
+// The dynamics of an environment, seen from one of its states.
+pub trait Environment: Display + Clone
 {
     type A: Action;
 
     fn initial_state() -> Self;
     fn next_state(&self, action: &Self::A) -> Self;
+    // Stochastic environments draw their randomness from the given RNG.
+    // Deterministic ones can rely on these defaults.
+    fn sample_initial_state<R: Rng + ?Sized>(_rng: &mut R) -> Self {
+        Self::initial_state()
+    }
+    fn sample_next_state<R: Rng + ?Sized>(&self, action: &Self::A, _rng: &mut R) -> Self {
+        self.next_state(action)
+    }
+    // True for two-player games where every action hands the turn to the
+    // opponent, so the value of the next state is from the opponent's view.
+    fn alternates_players() -> bool {
+        false
+    }
     fn is_terminal(&self) -> bool;
     fn available_actions(&self) -> Vec<Self::A>;
     fn num_available_actions(&self) -> usize {
         self.available_actions().len()
     }
     fn get_reward(state: &Self, action: &Self::A, next_state: &Self) -> f64;
+    // The reward the action gives the player who is not moving, in games
+    // where players alternate. Zero-sum games can rely on the default.
+    fn get_opponent_reward(state: &Self, action: &Self::A, next_state: &Self) -> f64 {
+        -Self::get_reward(state, action, next_state)
+    }
+    // The result of the action for the player who made it: positive for a
+    // win, negative for a loss and zero for a draw or a move that does not
+    // end the game. Reference players and evaluation judge games by it, so
+    // environments whose rewards are shaped or do not tell a draw from a
+    // loss override it.
+    fn get_result(state: &Self, action: &Self::A, next_state: &Self) -> f64 {
+        Self::get_reward(state, action, next_state)
+    }
+    // The discount the rewards were shaped for, if they were. Shaping only
+    // leaves the best actions unchanged when learnt with that discount, so
+    // learners refuse any other.
+    fn shaping_discount() -> Option<f64> {
+        None
+    }
+}
+
+
+// States that can be used as keys in a table of values. Continuous
+// states, such as those made of floats, are only Environments.
+pub trait State: Environment + Eq + Hash {
+}
+
+impl<T> State for T
+where
+    T: Environment + Eq + Hash
+{
+}
+
+
+// States that can be written as a single line of text and read back, so
+// that tables of values can be saved and loaded. Actions are written with
+// their Display and found again among the state's available actions.
+pub trait TextEncoding: Environment {
+    fn to_text(&self) -> String;
+    fn from_text(text: &str) -> Result<Self, String>;
+}
+
+
+// States described by a vector of real-valued observations, each of which
+// normally lies within the given bounds.
+pub trait ContinuousState: Environment {
+    fn observation(&self) -> Vec<f64>;
+    fn observation_bounds() -> Vec<(f64, f64)>;
+}
+
+
+// Environments with a fixed set of actions, numbered from 0 to num_actions() - 1.
+pub trait IndexedActions: Environment {
+    fn num_actions() -> usize;
+    fn action_index(action: &Self::A) -> usize;
+}
+
+
+// Environments whose states can be numbered from 0 to num_states() - 1, so
+// that with their indexed actions every state-action pair has a place in a
+// flat table.
+pub trait EnumerableState: IndexedActions {
+    fn num_states() -> usize;
+    fn state_index(&self) -> usize;
+    fn from_state_index(index: usize) -> Self;
+}
+
+
+// States that can be fed to a neural network as a fixed-length vector. Games
+// where players alternate should encode the board from the point of view of
+// the player to move, so that one network plays both sides.
+pub trait TensorEncoding: Environment {
+    fn encoding_len() -> usize;
+    fn encode(&self) -> Vec<f64>;
+}
+
+
+// Environments whose states come in groups of equivalent positions, such as
+// the rotations and reflections of a square board, that have the same value
+// once their actions are transformed to match. Symmetries are numbered from
+// 0 to num_symmetries() - 1, with 0 the identity.
+pub trait Symmetric: Environment {
+    fn num_symmetries() -> usize;
+    fn transform(&self, symmetry: usize) -> Self;
+    fn transform_action(action: &Self::A, symmetry: usize) -> Self::A;
+    // The symmetry that undoes the given one.
+    fn inverse_symmetry(symmetry: usize) -> usize;
+    // The symmetry taking this state to the canonical member of its group,
+    // which must be the same for every member.
+    fn canonical_symmetry(&self) -> usize;
 }
 
 
@@ -24,15 +130,21 @@ pub trait Action: Display + Eq + Hash + Clone {
 
 pub trait Policy<S>
 where 
-    S: State
+    S: Environment
 {
-    fn get_action(&self, values: &mut Vec<(S::A, f64)>) -> S::A;
+    fn get_action(&self, values: &mut Vec<(S::A, f64)>) -> S::A {
+        self.sample_action(values, &mut rand::thread_rng())
+    }
+    // get_action drawing from the given RNG, for reproducible training.
+    fn sample_action(&self, values: &mut Vec<(S::A, f64)>, rng: &mut dyn RngCore) -> S::A;
+    // The probability of get_action choosing each of the actions.
+    fn action_probabilities(&self, values: &[(S::A, f64)]) -> Vec<f64>;
 }
 
 
 pub trait ReinforcementLearner<S> 
 where 
-    S: State
+    S: Environment
 {
     fn get_action_value(&self, state: &S, action: &S::A) -> f64;
     fn get_action_values(&self, state: &S) -> Vec<(S::A, f64)>;
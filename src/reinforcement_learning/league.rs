@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::evaluation::EvaluationResults;
+use crate::utils::sample_index;
+
+use super::generic_reinforcement_learner::{Environment, State};
+use super::q_learning_learner::{QLearner, StateAction};
+use super::q_table::QTable;
+
+// League self-play: rather than playing both sides against itself, the
+// learner plays against a pool of frozen copies of itself taken earlier in
+// training. Playing against a mix of past versions keeps it from chasing
+// its own latest weakness round in a cycle.
+
+// How the opponent for each episode is chosen from the pool.
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum OpponentSampling {
+    // Always the most recent snapshot
+    Latest,
+    // Any snapshot, with equal probability
+    Uniform,
+    // One of the given number of most recent snapshots
+    Recent(usize),
+    // Snapshots in proportion to how much of the score the learner has
+    // dropped against them, raised to the given power, so that the
+    // opponents it struggles against are played more often
+    Prioritised(f64),
+}
+
+
+// A frozen copy of the learner, with the learner's record against it.
+pub struct Snapshot<S, T = HashMap<StateAction<S, <S as Environment>::A>, f64>>
+where
+    S: State,
+    T: QTable<S>
+{
+    pub episode: u32,
+    pub learner: QLearner<S, T>,
+    pub results: EvaluationResults,
+}
+
+
+// The snapshots keep the same kind of Q table as the learner.
+pub struct League<S, T = HashMap<StateAction<S, <S as Environment>::A>, f64>>
+where
+    S: State,
+    T: QTable<S>
+{
+    pub snapshots: Vec<Snapshot<S, T>>,
+    // A snapshot of the learner joins the pool every this many episodes
+    pub snapshot_interval: u32,
+    // Once the pool is this big, the oldest snapshot leaves as a new one joins
+    pub max_snapshots: usize,
+    pub sampling: OpponentSampling,
+    rng: StdRng,
+}
+
+impl<S, T> League<S, T>
+where
+    S: State,
+    T: QTable<S> + Clone
+{
+    pub fn new(snapshot_interval: u32, max_snapshots: usize, sampling: OpponentSampling, seed: u64) -> Self {
+        League { snapshots: Vec::new(), snapshot_interval, max_snapshots, sampling, rng: StdRng::seed_from_u64(seed) }
+    }
+
+    pub fn add_snapshot(&mut self, episode: u32, learner: &QLearner<S, T>) {
+        if self.snapshots.len() >= self.max_snapshots.max(1) {
+            self.snapshots.remove(0);
+        }
+        self.snapshots.push(Snapshot { episode, learner: learner.clone(), results: EvaluationResults::default() });
+    }
+
+    // The index of the snapshot to play next, or None while the pool is empty.
+    pub fn sample_opponent(&mut self) -> Option<usize> {
+        let num_snapshots = self.snapshots.len();
+        if num_snapshots == 0 {
+            return None;
+        }
+        let index = match self.sampling {
+            OpponentSampling::Latest => num_snapshots - 1,
+            OpponentSampling::Uniform => self.rng.gen_range(0..num_snapshots),
+            OpponentSampling::Recent(window) => {
+                let window = window.clamp(1, num_snapshots);
+                self.rng.gen_range(num_snapshots - window..num_snapshots)
+            },
+            OpponentSampling::Prioritised(power) => {
+                // Unplayed snapshots count as even, and every snapshot keeps
+                // a small chance of being picked
+                let weights: Vec<f64> = self.snapshots.iter()
+                    .map(|snapshot| {
+                        let results = &snapshot.results;
+                        let dropped = if results.games() == 0 { 0.5 } else { results.loss_rate() + 0.5 * results.draw_rate() };
+                        dropped.max(0.01).powf(power)
+                    })
+                    .collect();
+                let total: f64 = weights.iter().sum();
+                let probabilities: Vec<f64> = weights.iter().map(|weight| weight / total).collect();
+                sample_index(&probabilities, &mut self.rng)
+            },
+        };
+        Some(index)
+    }
+
+    // Whether the learner moves first this episode, chosen at random.
+    pub fn learner_moves_first(&mut self) -> bool {
+        self.rng.gen_bool(0.5)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::nim::{NimState, StandardNim};
+    use crate::reinforcement_learning::epsilon_greedy_policy::EpsilonGreedyPolicy;
+    use crate::reinforcement_learning::q_learning_learner::QLearner;
+    use crate::reinforcement_learning::q_table::{PerStateQTable, QTable};
+
+    use super::{League, OpponentSampling};
+
+    type Nim = NimState<StandardNim>;
+
+    fn new_q_learner() -> QLearner<Nim> {
+        QLearner::new(0.5, 0.9)
+    }
+
+    #[test]
+    fn pool_is_bounded_and_keeps_the_newest_snapshots() {
+        let mut league = League::new(10, 3, OpponentSampling::Latest, 0);
+        assert_eq!(league.sample_opponent(), None);
+        for episode in 0..5 {
+            league.add_snapshot(episode, &new_q_learner());
+        }
+        let episodes: Vec<u32> = league.snapshots.iter().map(|snapshot| snapshot.episode).collect();
+        assert_eq!(episodes, vec![2, 3, 4]);
+        assert_eq!(league.sample_opponent(), Some(2));
+
+        league.sampling = OpponentSampling::Recent(2);
+        assert!((0..100).all(|_| league.sample_opponent() >= Some(1)));
+    }
+
+    #[test]
+    fn prioritised_sampling_prefers_opponents_the_learner_loses_to() {
+        let mut league = League::new(10, 2, OpponentSampling::Prioritised(1.0), 0);
+        league.add_snapshot(0, &new_q_learner());
+        league.add_snapshot(1, &new_q_learner());
+        league.snapshots[0].results.wins = 10;
+        league.snapshots[1].results.losses = 10;
+        let picks_of_second = (0..1000).filter(|_| league.sample_opponent() == Some(1)).count();
+        assert!(picks_of_second > 950);
+    }
+
+    #[test]
+    fn snapshots_keep_the_learners_table() {
+        let mut q_learner = QLearner::with_table(PerStateQTable::<Nim>::new(), 0.5, 0.9);
+        let mut league = League::new(50, 5, OpponentSampling::Uniform, 0);
+        q_learner.league_self_play(&EpsilonGreedyPolicy::new(0.5), 100, &mut league);
+        assert_eq!(league.snapshots.len(), 3);
+        assert!(league.snapshots[0].learner.q_values.is_empty());
+        assert!(!league.snapshots[2].learner.q_values.is_empty());
+    }
+
+    #[test]
+    fn seeded_leagues_are_reproducible() {
+        let train = || {
+            let mut q_learner = new_q_learner();
+            let mut league = League::new(50, 5, OpponentSampling::Prioritised(1.0), 0);
+            q_learner.league_self_play_with_rng(&EpsilonGreedyPolicy::new(0.5), 200, &mut league, &mut StdRng::seed_from_u64(1));
+            q_learner
+        };
+        assert!(train().q_values == train().q_values);
+    }
+}
@@ -0,0 +1,194 @@
+use std::marker::PhantomData;
+
+use super::epsilon_greedy_policy::greedy_index;
+use super::features::FeatureExtractor;
+use super::generic_reinforcement_learner::{Environment, Policy, ReinforcementLearner};
+
+// Sutton and Barto RL book:
+// https://web.stanford.edu/class/psych209/Readings/SuttonBartoIPRLBook2ndEd.pdf
+// Episodic semi-gradient SARSA taken from page 244. Action values are a
+// linear function of the features, q(s, a) = w . x(s, a), so states that
+// share features share what is learnt about them and nothing needs to be
+// stored per state.
+
+pub struct LinearLearner<S, F>
+where
+    S: Environment,
+    F: FeatureExtractor<S>
+{
+    pub weights: Vec<f64>,
+    pub features: F,
+    pub alpha: f64,
+    pub gamma: f64,
+    state: PhantomData<S>,
+}
+
+
+impl<S, F> LinearLearner<S, F>
+where
+    S: Environment,
+    F: FeatureExtractor<S>
+{
+    // Starts with all weights zero. With tile coding, alpha is usually
+    // divided by the number of tilings since that many features are active.
+    pub fn new(features: F, alpha: f64, gamma: f64) -> Self {
+        let weights = vec![0.0; features.num_features()];
+        LinearLearner { weights, features, alpha, gamma, state: PhantomData }
+    }
+
+    // w = w + alpha * (target - q(S, A)) * x(S, A)
+    fn update_towards(&mut self, state: &S, action: &S::A, target: f64) {
+        let features = self.features.features(state, action);
+        let error = target - self.value_of(&features);
+        for (index, value) in features {
+            self.weights[index] += self.alpha * error * value;
+        }
+    }
+
+    fn value_of(&self, features: &[(usize, f64)]) -> f64 {
+        features.iter().map(|(index, value)| self.weights[*index] * value).sum()
+    }
+
+    // The value of the next state from the current mover's point of view.
+    fn from_our_side(value: f64) -> f64 {
+        if S::alternates_players() { -value } else { value }
+    }
+
+    // Episodic semi-gradient SARSA, which learns the value of the policy it follows.
+    pub fn semi_gradient_sarsa(&mut self, policy: &dyn Policy<S>, num_episodes: u32) {
+        let mut rng = rand::thread_rng();
+
+        for episode in 1..=num_episodes {
+            if episode % 1000 == 0 {
+                println!("Episode: {episode} / {num_episodes}");
+            }
+
+            // S, A = initial state and action of episode
+            let mut state = S::sample_initial_state(&mut rng);
+            if state.is_terminal() {
+                continue;
+            }
+            let mut action = policy.get_action(&mut self.get_action_values(&state));
+            loop {
+                // Take action A, observe R, S'
+                let next_state = state.sample_next_state(&action, &mut rng);
+                let reward = S::get_reward(&state, &action, &next_state);
+                if next_state.is_terminal() {
+                    // w = w + alpha * (R - q(S, A)) * x(S, A)
+                    self.update_towards(&state, &action, reward);
+                    break;
+                }
+                // Choose A' as a function of q(S', .) (e.g. epsilon-greedy)
+                let next_action = policy.get_action(&mut self.get_action_values(&next_state));
+                // w = w + alpha * (R + gamma * q(S', A') - q(S, A)) * x(S, A)
+                let next_value = Self::from_our_side(self.get_action_value(&next_state, &next_action));
+                self.update_towards(&state, &action, reward + self.gamma * next_value);
+                state = next_state;
+                action = next_action;
+            }
+        }
+    }
+
+    // Semi-gradient Q-learning, which learns the value of the greedy policy
+    // whatever policy it follows.
+    pub fn q_learning(&mut self, policy: &dyn Policy<S>, num_episodes: u32) {
+        let mut rng = rand::thread_rng();
+
+        for episode in 1..=num_episodes {
+            if episode % 1000 == 0 {
+                println!("Episode: {episode} / {num_episodes}");
+            }
+
+            let mut state = S::sample_initial_state(&mut rng);
+            while !state.is_terminal() {
+                let action = policy.get_action(&mut self.get_action_values(&state));
+                let next_state = state.sample_next_state(&action, &mut rng);
+                let reward = S::get_reward(&state, &action, &next_state);
+                self.update_action_value(&state, &action, &next_state, reward);
+                state = next_state;
+            }
+        }
+    }
+}
+
+
+impl<S, F> ReinforcementLearner<S> for LinearLearner<S, F>
+where
+    S: Environment,
+    F: FeatureExtractor<S>
+{
+    fn get_action_value(&self, state: &S, action: &S::A) -> f64 {
+        self.value_of(&self.features.features(state, action))
+    }
+
+    fn get_action_values(&self, state: &S) -> Vec<(S::A, f64)> {
+        state.available_actions()
+            .into_iter()
+            .map(|action| {
+                let value = self.get_action_value(state, &action);
+                (action, value)
+            })
+            .collect()
+    }
+
+    fn get_best_action(&self, state: &S) -> S::A {
+        let mut actions_and_values = self.get_action_values(state);
+        let values: Vec<f64> = actions_and_values.iter().map(|(_, value)| *value).collect();
+        let best = greedy_index(&values, &mut rand::thread_rng());
+        actions_and_values.swap_remove(best).0
+    }
+
+    // w = w + alpha * (R + gamma * max_a q(S', a) - q(S, A)) * x(S, A)
+    fn update_action_value(&mut self, state: &S, action: &S::A, next_state: &S, reward: f64) {
+        let next_state_value = Self::from_our_side(self.get_state_value(next_state));
+        self.update_towards(state, action, reward + self.gamma * next_state_value);
+    }
+
+    fn get_state_value(&self, state: &S) -> f64 {
+        if state.is_terminal() {
+            return 0.0;
+        }
+        self.get_action_values(state)
+            .into_iter()
+            .map(|(_, value)| value)
+            .fold(f64::NEG_INFINITY, f64::max)
+    }
+
+    fn update_state_value(&mut self, _state: &S, _value: f64) {
+        panic!("Linear learner cannot directly update state value.")
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::classic_control::mountain_car::{MountainCar, MAX_STEPS};
+    use crate::reinforcement_learning::{epsilon_greedy_policy::EpsilonGreedyPolicy, features::TileCodingFeatures, generic_reinforcement_learner::{Environment, ReinforcementLearner}};
+
+    use super::LinearLearner;
+
+    fn greedy_episode_length(learner: &LinearLearner<MountainCar, TileCodingFeatures<MountainCar>>) -> u32 {
+        let mut state = MountainCar { position: -0.5, velocity: 0.0, steps: 0 };
+        while !state.is_terminal() {
+            state = state.next_state(&learner.get_best_action(&state));
+        }
+        state.steps
+    }
+
+    #[test]
+    fn semi_gradient_sarsa_learns_mountain_car() {
+        // Optimistic zero weights explore enough without epsilon
+        let features = TileCodingFeatures::new(8, 8);
+        let mut learner = LinearLearner::new(features, 0.5 / 8.0, 1.0);
+        learner.semi_gradient_sarsa(&EpsilonGreedyPolicy::new(0.0), 1000);
+        assert!(greedy_episode_length(&learner) < MAX_STEPS);
+    }
+
+    #[test]
+    fn semi_gradient_q_learning_learns_mountain_car() {
+        let features = TileCodingFeatures::new(8, 8);
+        let mut learner = LinearLearner::new(features, 0.5 / 8.0, 1.0);
+        learner.q_learning(&EpsilonGreedyPolicy::new(0.0), 1000);
+        assert!(greedy_episode_length(&learner) < MAX_STEPS);
+    }
+}
@@ -0,0 +1,338 @@
+use std::io::{self, Write};
+
+use crate::evaluation::{evaluate, Agent, EvaluationResults, LearnerAgent};
+use crate::utils::csv_field;
+
+use super::generic_reinforcement_learner::{Environment, State};
+use super::q_learning_learner::QLearner;
+use super::q_table::QTable;
+use super::training_hooks::{HookControl, Step, TrainingHook};
+
+// Learning curves recorded during training, and their export as CSV or as
+// JSON Lines (one JSON object per line) for plotting elsewhere.
+
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct EpisodeMetrics {
+    pub episode: u32,
+    // Undiscounted sum of the rewards of the player who moves first. In
+    // games where players alternate, the other player's moves count by the
+    // reward they give the first player.
+    pub episode_return: f64,
+    // The same without any reward shaping, to compare learners trained with
+    // different shaping. Equal to the return unless the recorder is told how
+    // to undo the shaping.
+    pub unshaped_return: f64,
+    pub length: u32,
+    // Number of state-action pairs with a learnt value
+    pub table_size: usize,
+    pub mean_absolute_td_error: f64,
+    // Fraction of the episode's actions that weren't greedy
+    pub exploration_rate: f64,
+}
+
+
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct EvaluationMetrics {
+    pub episode: u32,
+    pub opponent: String,
+    pub results: EvaluationResults,
+}
+
+
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(Default)]
+pub struct MetricsLog {
+    pub episodes: Vec<EpisodeMetrics>,
+    pub evaluations: Vec<EvaluationMetrics>,
+}
+
+impl MetricsLog {
+    pub fn write_episodes_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "episode,return,unshaped_return,length,table_size,mean_absolute_td_error,exploration_rate")?;
+        for metrics in &self.episodes {
+            writeln!(
+                writer, "{},{},{},{},{},{},{}",
+                metrics.episode, metrics.episode_return, metrics.unshaped_return, metrics.length, metrics.table_size,
+                metrics.mean_absolute_td_error, metrics.exploration_rate
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn write_episodes_jsonl<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for metrics in &self.episodes {
+            writeln!(
+                writer,
+                "{{\"episode\":{},\"return\":{},\"unshaped_return\":{},\"length\":{},\"table_size\":{},\"mean_absolute_td_error\":{},\"exploration_rate\":{}}}",
+                metrics.episode, json_number(metrics.episode_return), json_number(metrics.unshaped_return), metrics.length, metrics.table_size,
+                json_number(metrics.mean_absolute_td_error), json_number(metrics.exploration_rate)
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn write_evaluations_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "episode,opponent,games,win_rate,draw_rate,loss_rate")?;
+        for metrics in &self.evaluations {
+            let results = &metrics.results;
+            writeln!(
+                writer, "{},{},{},{},{},{}",
+                metrics.episode, csv_field(&metrics.opponent), results.games(),
+                results.win_rate(), results.draw_rate(), results.loss_rate()
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn write_evaluations_jsonl<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for metrics in &self.evaluations {
+            let results = &metrics.results;
+            writeln!(
+                writer,
+                "{{\"episode\":{},\"opponent\":{},\"games\":{},\"win_rate\":{},\"draw_rate\":{},\"loss_rate\":{}}}",
+                metrics.episode, json_string(&metrics.opponent), results.games(),
+                json_number(results.win_rate()), json_number(results.draw_rate()), json_number(results.loss_rate())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// JSON has no NaN or infinity, so those become null.
+fn json_number(value: f64) -> String {
+    if value.is_finite() { value.to_string() } else { "null".to_string() }
+}
+
+fn json_string(value: &str) -> String {
+    let mut string = String::from("\"");
+    for char in value.chars() {
+        match char {
+            '"' => string.push_str("\\\""),
+            '\\' => string.push_str("\\\\"),
+            '\n' => string.push_str("\\n"),
+            char if (char as u32) < 0x20 => string.push_str(&format!("\\u{:04x}", char as u32)),
+            char => string.push(char),
+        }
+    }
+    string.push('"');
+    string
+}
+
+
+// Every `interval` episodes, plays `num_games` games against each of the
+// fixed opponents.
+pub struct PeriodicEvaluation<S>
+where
+    S: Environment
+{
+    pub interval: u32,
+    pub num_games: u32,
+    pub opponents: Vec<(String, Box<dyn Agent<S>>)>,
+}
+
+impl<S> PeriodicEvaluation<S>
+where
+    S: Environment
+{
+    pub fn new(interval: u32, num_games: u32) -> Self {
+        PeriodicEvaluation { interval, num_games, opponents: Vec::new() }
+    }
+
+    pub fn against(mut self, name: &str, opponent: impl Agent<S> + 'static) -> Self {
+        self.opponents.push((name.to_string(), Box::new(opponent)));
+        self
+    }
+
+    pub fn is_due(&self, episode: u32) -> bool {
+        self.interval > 0 && episode % self.interval == 0
+    }
+
+    pub fn run(&mut self, episode: u32, agent: &mut dyn Agent<S>) -> Vec<EvaluationMetrics> {
+        let num_games = self.num_games;
+        self.opponents.iter_mut()
+            .map(|(name, opponent)| EvaluationMetrics {
+                episode,
+                opponent: name.clone(),
+                results: evaluate(agent, opponent.as_mut(), num_games),
+            })
+            .collect()
+    }
+}
+
+
+// A training hook that fills in a MetricsLog as a Q-learner trains.
+pub struct MetricsRecorder<'a, S>
+where
+    S: Environment
+{
+    pub log: MetricsLog,
+    evaluation: Option<&'a mut PeriodicEvaluation<S>>,
+    // The rewards of a step without any shaping, for the player moving and
+    // for the other player
+    unshaped_reward: fn(&S, &S::A, &S) -> f64,
+    unshaped_opponent_reward: fn(&S, &S::A, &S) -> f64,
+    episode_return: f64,
+    unshaped_return: f64,
+    length: u32,
+    total_td_error: f64,
+    exploratory_actions: u32,
+}
+
+impl<'a, S> MetricsRecorder<'a, S>
+where
+    S: Environment
+{
+    pub fn new(evaluation: Option<&'a mut PeriodicEvaluation<S>>) -> Self {
+        MetricsRecorder {
+            log: MetricsLog::default(),
+            evaluation,
+            unshaped_reward: S::get_reward,
+            unshaped_opponent_reward: S::get_opponent_reward,
+            episode_return: 0.0,
+            unshaped_return: 0.0,
+            length: 0,
+            total_td_error: 0.0,
+            exploratory_actions: 0,
+        }
+    }
+
+    pub fn with_unshaped_rewards(mut self, reward: fn(&S, &S::A, &S) -> f64, opponent_reward: fn(&S, &S::A, &S) -> f64) -> Self {
+        self.unshaped_reward = reward;
+        self.unshaped_opponent_reward = opponent_reward;
+        self
+    }
+}
+
+impl<'a, S, T> TrainingHook<S, QLearner<S, T>> for MetricsRecorder<'a, S>
+where
+    S: State,
+    T: QTable<S>
+{
+    fn on_episode_start(&mut self, _episode: u32, _state: &S) {
+        self.episode_return = 0.0;
+        self.unshaped_return = 0.0;
+        self.length = 0;
+        self.total_td_error = 0.0;
+        self.exploratory_actions = 0;
+    }
+
+    fn on_step(&mut self, step: &Step<S>) {
+        let first_player_moving = !S::alternates_players() || self.length % 2 == 0;
+        if first_player_moving {
+            self.episode_return += step.reward;
+            self.unshaped_return += (self.unshaped_reward)(step.state, step.action, step.next_state);
+        } else {
+            self.episode_return += S::get_opponent_reward(step.state, step.action, step.next_state);
+            self.unshaped_return += (self.unshaped_opponent_reward)(step.state, step.action, step.next_state);
+        }
+        self.length += 1;
+        self.total_td_error += step.td_error.abs();
+        if !step.greedy {
+            self.exploratory_actions += 1;
+        }
+    }
+
+    fn on_episode_end(&mut self, episode: u32, learner: &QLearner<S, T>) -> HookControl {
+        let per_step = |total: f64| if self.length == 0 { 0.0 } else { total / self.length as f64 };
+        self.log.episodes.push(EpisodeMetrics {
+            episode,
+            episode_return: self.episode_return,
+            unshaped_return: self.unshaped_return,
+            length: self.length,
+            table_size: learner.q_values.len(),
+            mean_absolute_td_error: per_step(self.total_td_error),
+            exploration_rate: per_step(self.exploratory_actions as f64),
+        });
+        if let Some(evaluation) = self.evaluation.as_deref_mut() {
+            if evaluation.is_due(episode) {
+                let results = evaluation.run(episode, &mut LearnerAgent::new(learner));
+                self.log.evaluations.extend(results);
+            }
+        }
+        HookControl::Continue
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluation::{EvaluationResults, RandomAgent};
+    use crate::nim::{NimState, StandardNim};
+    use crate::reinforcement_learning::epsilon_greedy_policy::EpsilonGreedyPolicy;
+    use crate::reinforcement_learning::q_learning_learner::QLearner;
+
+    use super::{EpisodeMetrics, EvaluationMetrics, MetricsLog, PeriodicEvaluation};
+
+    fn log() -> MetricsLog {
+        MetricsLog {
+            episodes: vec![EpisodeMetrics {
+                episode: 1,
+                episode_return: -3.5,
+                unshaped_return: -4.0,
+                length: 4,
+                table_size: 12,
+                mean_absolute_td_error: f64::NAN,
+                exploration_rate: 0.25,
+            }],
+            evaluations: vec![EvaluationMetrics {
+                episode: 1,
+                opponent: "random, \"seeded\"".to_string(),
+                results: EvaluationResults { wins: 3, draws: 0, losses: 1 },
+            }],
+        }
+    }
+
+    #[test]
+    fn metrics_export_as_csv() {
+        let mut episodes = Vec::new();
+        log().write_episodes_csv(&mut episodes).unwrap();
+        assert_eq!(
+            String::from_utf8(episodes).unwrap(),
+            "episode,return,unshaped_return,length,table_size,mean_absolute_td_error,exploration_rate\n1,-3.5,-4,4,12,NaN,0.25\n"
+        );
+        let mut evaluations = Vec::new();
+        log().write_evaluations_csv(&mut evaluations).unwrap();
+        assert_eq!(
+            String::from_utf8(evaluations).unwrap(),
+            "episode,opponent,games,win_rate,draw_rate,loss_rate\n1,\"random, \"\"seeded\"\"\",4,0.75,0,0.25\n"
+        );
+    }
+
+    #[test]
+    fn metrics_export_as_json_lines() {
+        let mut episodes = Vec::new();
+        log().write_episodes_jsonl(&mut episodes).unwrap();
+        assert_eq!(
+            String::from_utf8(episodes).unwrap(),
+            "{\"episode\":1,\"return\":-3.5,\"unshaped_return\":-4,\"length\":4,\"table_size\":12,\"mean_absolute_td_error\":null,\"exploration_rate\":0.25}\n"
+        );
+        let mut evaluations = Vec::new();
+        log().write_evaluations_jsonl(&mut evaluations).unwrap();
+        assert_eq!(
+            String::from_utf8(evaluations).unwrap(),
+            "{\"episode\":1,\"opponent\":\"random, \\\"seeded\\\"\",\"games\":4,\"win_rate\":0.75,\"draw_rate\":0,\"loss_rate\":0.25}\n"
+        );
+    }
+
+    #[test]
+    fn q_learning_records_metrics_every_episode() {
+        let mut q_learner = QLearner::<NimState<StandardNim>>::new(0.5, 0.9);
+        let mut evaluation = PeriodicEvaluation::new(100, 10).against("random", RandomAgent::new(0));
+        let log = q_learner.q_learning_with_metrics(&EpsilonGreedyPolicy::new(0.0), 300, Some(&mut evaluation));
+
+        assert_eq!(log.episodes.len(), 300);
+        assert!(log.episodes.iter().all(|metrics| metrics.length > 0 && metrics.exploration_rate == 0.0));
+        // Returns are the first player's, who wins by taking the last object
+        assert!(log.episodes.iter().all(|metrics| metrics.episode_return == if metrics.length % 2 == 1 { 1.0 } else { -1.0 }));
+        assert!(log.episodes.windows(2).all(|pair| pair[0].table_size <= pair[1].table_size));
+        assert_eq!(log.episodes.last().unwrap().table_size, q_learner.q_values.len());
+
+        let evaluated_episodes: Vec<u32> = log.evaluations.iter().map(|metrics| metrics.episode).collect();
+        assert_eq!(evaluated_episodes, vec![100, 200, 300]);
+        assert!(log.evaluations.iter().all(|metrics| metrics.opponent == "random" && metrics.results.games() == 10));
+    }
+}
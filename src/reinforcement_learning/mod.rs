@@ -1,3 +1,17 @@
 pub mod generic_reinforcement_learner;
 pub mod q_learning_learner;
-pub mod epsilon_greedy_policy;
\ No newline at end of file
+pub mod epsilon_greedy_policy;
+pub mod discretised_state;
+pub mod features;
+pub mod linear_learner;
+pub mod dqn_learner;
+pub mod replay_buffer;
+pub mod softmax_policy;
+pub mod policy_gradient;
+pub mod metrics;
+pub mod training_hooks;
+pub mod league;
+pub mod symmetry;
+pub mod q_table;
+pub mod parallel;
+pub mod shaping;
\ No newline at end of file
@@ -0,0 +1,241 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::Hasher;
+use std::sync::Mutex;
+use std::thread;
+
+use rand::{rngs::StdRng, SeedableRng};
+
+use super::generic_reinforcement_learner::{Environment, Policy, State};
+use super::q_learning_learner::QLearner;
+use super::q_table::{QTable, StateAction};
+
+// Q-learning on several threads at once, either Hogwild-style, with every
+// worker reading and writing one shared table without waiting for the
+// others, or with independent learners whose tables are averaged at the end.
+// Each worker draws its random choices from its own seeded RNG. Independent
+// training is then reproducible; shared training is not, since the workers'
+// updates interleave differently on every run.
+
+type Shard<S> = Mutex<HashMap<StateAction<S, <S as Environment>::A>, f64>>;
+
+// A table split into separately locked shards by state, so that workers
+// updating different states rarely wait for each other.
+pub struct ShardedQTable<S>
+where
+    S: State
+{
+    shards: Vec<Shard<S>>,
+}
+
+impl<S> ShardedQTable<S>
+where
+    S: State
+{
+    pub fn new(num_shards: usize) -> Self {
+        ShardedQTable { shards: (0..num_shards.max(1)).map(|_| Mutex::new(HashMap::new())).collect() }
+    }
+
+    fn shard(&self, state: &S) -> &Shard<S> {
+        let mut hasher = DefaultHasher::new();
+        state.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % self.shards.len()]
+    }
+
+    pub fn get(&self, state: &S, action: &S::A) -> Option<f64> {
+        QTable::get(&*self.shard(state).lock().unwrap(), state, action)
+    }
+
+    pub fn set(&self, state: &S, action: &S::A, value: f64) {
+        QTable::set(&mut *self.shard(state).lock().unwrap(), state, action, value);
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.lock().unwrap().len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Every stored pair, taken one shard at a time.
+    pub fn values(&self) -> Vec<(S, S::A, f64)> {
+        self.shards.iter()
+            .flat_map(|shard| QTable::iter(&*shard.lock().unwrap()).collect::<Vec<_>>())
+            .collect()
+    }
+}
+
+// Lets each worker's QLearner use the shared table through a reference.
+impl<S> QTable<S> for &ShardedQTable<S>
+where
+    S: State
+{
+    fn get(&self, state: &S, action: &S::A) -> Option<f64> {
+        ShardedQTable::get(self, state, action)
+    }
+
+    fn set(&mut self, state: &S, action: &S::A, value: f64) {
+        ShardedQTable::set(self, state, action, value);
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (S, S::A, f64)> + '_> {
+        Box::new(self.values().into_iter())
+    }
+
+    fn len(&self) -> usize {
+        ShardedQTable::len(self)
+    }
+}
+
+
+pub struct ParallelTrainer {
+    pub num_workers: usize,
+    // Worker i draws from an RNG seeded with seed + i
+    pub seed: u64,
+    // Number of shards of the table shared in Hogwild training
+    pub num_shards: usize,
+}
+
+impl ParallelTrainer {
+    pub fn new(num_workers: usize, seed: u64) -> Self {
+        ParallelTrainer { num_workers: num_workers.max(1), seed, num_shards: 64 }
+    }
+
+    pub fn with_shards(mut self, num_shards: usize) -> Self {
+        self.num_shards = num_shards;
+        self
+    }
+
+    pub fn worker_seed(&self, worker: usize) -> u64 {
+        self.seed.wrapping_add(worker as u64)
+    }
+
+    // Hogwild training: every worker runs episodes_per_worker episodes,
+    // starting from the learner's values and updating one shared table.
+    pub fn train_shared<S, T, P>(&self, learner: &mut QLearner<S, T>, policy: &P, episodes_per_worker: u32)
+    where
+        S: State + Send + Sync,
+        S::A: Send + Sync,
+        T: QTable<S>,
+        P: Policy<S> + Sync
+    {
+        let shared = ShardedQTable::new(self.num_shards);
+        for (state, action, value) in learner.q_values.iter() {
+            shared.set(&state, &action, value);
+        }
+        thread::scope(|scope| {
+            for worker in 0..self.num_workers {
+                let shared = &shared;
+                let (alpha, gamma) = (learner.alpha, learner.gamma);
+                let seed = self.worker_seed(worker);
+                scope.spawn(move || {
+                    let mut worker_learner = QLearner::with_table(shared, alpha, gamma);
+                    worker_learner.q_learning_with_rng(policy, episodes_per_worker, &mut [], &mut StdRng::seed_from_u64(seed));
+                });
+            }
+        });
+        for (state, action, value) in shared.values() {
+            learner.q_values.set(&state, &action, value);
+        }
+    }
+
+    // Trains a copy of the learner on each worker for episodes_per_worker
+    // episodes, then gives the learner the average of their values.
+    pub fn train_independent<S, T, P>(&self, learner: &mut QLearner<S, T>, policy: &P, episodes_per_worker: u32)
+    where
+        S: State + Send,
+        T: QTable<S> + Clone + Send,
+        P: Policy<S> + Sync
+    {
+        let workers: Vec<QLearner<S, T>> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..self.num_workers)
+                .map(|worker| {
+                    let mut worker_learner = learner.clone();
+                    let seed = self.worker_seed(worker);
+                    scope.spawn(move || {
+                        worker_learner.q_learning_with_rng(policy, episodes_per_worker, &mut [], &mut StdRng::seed_from_u64(seed));
+                        worker_learner
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+        });
+        let tables: Vec<&T> = workers.iter().map(|worker| &worker.q_values).collect();
+        average_tables(&mut learner.q_values, &tables);
+    }
+}
+
+
+// Sets each pair in the table to the mean of its values over the tables
+// that have one, in the order the tables are given.
+pub fn average_tables<S, T, U>(table: &mut T, tables: &[&U])
+where
+    S: State,
+    T: QTable<S>,
+    U: QTable<S>
+{
+    let mut sums: HashMap<StateAction<S, S::A>, (f64, u32)> = HashMap::new();
+    let mut order = Vec::new();
+    for source in tables {
+        for (state, action, value) in source.iter() {
+            let state_action = StateAction::new(state, action);
+            let (sum, count) = sums.entry(state_action.clone()).or_insert_with(|| {
+                order.push(state_action);
+                (0.0, 0)
+            });
+            *sum += value;
+            *count += 1;
+        }
+    }
+    for state_action in order {
+        let (sum, count) = sums[&state_action];
+        table.set(state_action.state(), state_action.action(), sum / count as f64);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::nim::{NimState, StandardNim};
+    use crate::reinforcement_learning::epsilon_greedy_policy::EpsilonGreedyPolicy;
+    use crate::reinforcement_learning::q_learning_learner::QLearner;
+    use crate::reinforcement_learning::q_table::QTable;
+
+    use super::ParallelTrainer;
+
+    type Nim = NimState<StandardNim>;
+
+    fn values(learner: &QLearner<Nim>) -> HashMap<String, f64> {
+        QTable::iter(&learner.q_values).map(|(state, action, value)| (format!("{state} {action}"), value)).collect()
+    }
+
+    #[test]
+    fn independent_training_is_reproducible() {
+        let policy = EpsilonGreedyPolicy::new(0.5);
+        let train = |seed| {
+            let mut learner = QLearner::<Nim>::new(0.5, 0.9);
+            ParallelTrainer::new(4, seed).train_independent(&mut learner, &policy, 50);
+            values(&learner)
+        };
+        let first = train(0);
+        assert!(!first.is_empty());
+        assert_eq!(first, train(0));
+        assert_ne!(first, train(1));
+    }
+
+    #[test]
+    fn shared_training_pools_the_workers_updates() {
+        let policy = EpsilonGreedyPolicy::new(1.0);
+        let mut one_worker = QLearner::<Nim>::new(0.5, 0.9);
+        ParallelTrainer::new(1, 0).with_shards(4).train_shared(&mut one_worker, &policy, 20);
+        let mut four_workers = QLearner::<Nim>::new(0.5, 0.9);
+        ParallelTrainer::new(4, 0).with_shards(4).train_shared(&mut four_workers, &policy, 20);
+        // The first worker replays the single worker's episodes, so the
+        // others can only add pairs to the table
+        assert!(four_workers.q_values.len() >= one_worker.q_values.len());
+        assert!(values(&one_worker).keys().all(|key| values(&four_workers).contains_key(key)));
+    }
+}
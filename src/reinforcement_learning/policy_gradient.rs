@@ -0,0 +1,345 @@
+use std::marker::PhantomData;
+
+use rand::Rng;
+
+use crate::utils::sample_index;
+
+use super::epsilon_greedy_policy::greedy_index;
+use super::features::{FeatureExtractor, Features, StateFeatureExtractor};
+use super::generic_reinforcement_learner::{Environment, Policy, ReinforcementLearner};
+use super::softmax_policy::SoftmaxPolicy;
+
+// Sutton and Barto RL book, chapter 13:
+// https://web.stanford.edu/class/psych209/Readings/SuttonBartoIPRLBook2ndEd.pdf
+// The policy is a softmax over linear action preferences,
+// pi(a | s) = softmax_a(theta . x(s, a)), and is learnt directly by
+// gradient ascent rather than read off action values. State values,
+// v(s) = w . x(s), serve as the baseline for REINFORCE and as the critic
+// for actor-critic. Unlike greedy policies, the learnt policy can stay
+// stochastic when mixing between actions is best.
+
+pub struct PolicyGradientLearner<S, F, G>
+where
+    S: Environment,
+    F: FeatureExtractor<S>,
+    G: StateFeatureExtractor<S>
+{
+    pub policy_weights: Vec<f64>,
+    pub value_weights: Vec<f64>,
+    pub action_features: F,
+    pub state_features: G,
+    pub policy_alpha: f64,
+    pub value_alpha: f64,
+    pub gamma: f64,
+    state: PhantomData<S>,
+}
+
+
+impl<S, F, G> PolicyGradientLearner<S, F, G>
+where
+    S: Environment,
+    F: FeatureExtractor<S>,
+    G: StateFeatureExtractor<S>
+{
+    // Starts with all weights zero, so with the uniform random policy.
+    pub fn new(action_features: F, state_features: G, policy_alpha: f64, value_alpha: f64, gamma: f64) -> Self {
+        PolicyGradientLearner {
+            policy_weights: vec![0.0; action_features.num_features()],
+            value_weights: vec![0.0; state_features.num_state_features()],
+            action_features,
+            state_features,
+            policy_alpha,
+            value_alpha,
+            gamma,
+            state: PhantomData,
+        }
+    }
+
+    fn dot(weights: &[f64], features: &Features) -> f64 {
+        features.iter().map(|(index, value)| weights[*index] * value).sum()
+    }
+
+    // The learnt policy, which is the softmax policy over the preferences.
+    pub fn policy(&self) -> SoftmaxPolicy<S> {
+        SoftmaxPolicy::new(1.0)
+    }
+
+    pub fn action_probabilities(&self, state: &S) -> Vec<(S::A, f64)> {
+        let preferences = self.get_action_values(state);
+        let probabilities = self.policy().action_probabilities(&preferences);
+        preferences.into_iter().map(|(action, _)| action).zip(probabilities).collect()
+    }
+
+    fn sample_action<R: Rng + ?Sized>(&self, state: &S, rng: &mut R) -> S::A {
+        let mut actions_and_probabilities = self.action_probabilities(state);
+        let probabilities: Vec<f64> = actions_and_probabilities.iter().map(|(_, probability)| *probability).collect();
+        actions_and_probabilities.swap_remove(sample_index(&probabilities, rng)).0
+    }
+
+    // The value of the next state from the current mover's point of view.
+    fn from_our_side(value: f64) -> f64 {
+        if S::alternates_players() { -value } else { value }
+    }
+
+    // theta = theta + policy_alpha * scale * grad ln pi(A | S), where for a
+    // linear softmax grad ln pi(A | S) = x(S, A) - sum_b pi(b | S) x(S, b).
+    fn policy_step(&mut self, state: &S, action: &S::A, scale: f64) {
+        let step = self.policy_alpha * scale;
+        for (other_action, probability) in self.action_probabilities(state) {
+            for (index, value) in self.action_features.features(state, &other_action) {
+                self.policy_weights[index] -= step * probability * value;
+            }
+        }
+        for (index, value) in self.action_features.features(state, action) {
+            self.policy_weights[index] += step * value;
+        }
+    }
+
+    // w = w + value_alpha * error * x(S)
+    fn value_step(&mut self, state: &S, error: f64) {
+        for (index, value) in self.state_features.state_features(state) {
+            self.value_weights[index] += self.value_alpha * error * value;
+        }
+    }
+
+    // REINFORCE with baseline, page 330. Each episode is played out in full
+    // and every step's return G is compared to the baseline v(S).
+    pub fn reinforce(&mut self, num_episodes: u32) {
+        let mut rng = rand::thread_rng();
+
+        for episode in 1..=num_episodes {
+            if episode % 1000 == 0 {
+                println!("Episode: {episode} / {num_episodes}");
+            }
+
+            // Generate an episode S0, A0, R1, ..., following pi
+            let mut steps = Vec::new();
+            let mut state = S::sample_initial_state(&mut rng);
+            while !state.is_terminal() {
+                let action = self.sample_action(&state, &mut rng);
+                let next_state = state.sample_next_state(&action, &mut rng);
+                let reward = S::get_reward(&state, &action, &next_state);
+                steps.push((state, action, reward));
+                state = next_state;
+            }
+
+            // G_t = R_t+1 + gamma * G_t+1, negating G_t+1 when it is the
+            // opponent's return
+            let mut returns = vec![0.0; steps.len()];
+            let mut future_return = 0.0;
+            for (t, (_, _, reward)) in steps.iter().enumerate().rev() {
+                future_return = reward + self.gamma * Self::from_our_side(future_return);
+                returns[t] = future_return;
+            }
+
+            let mut discount = 1.0;
+            for ((state, action, _), episode_return) in steps.iter().zip(returns) {
+                let delta = episode_return - self.get_state_value(state);
+                self.value_step(state, delta);
+                self.policy_step(state, action, discount * delta);
+                discount *= self.gamma;
+            }
+        }
+    }
+
+    // One-step actor-critic, page 332. The critic's TD error replaces the
+    // full return, so learning happens at every step.
+    pub fn actor_critic(&mut self, num_episodes: u32) {
+        let mut rng = rand::thread_rng();
+
+        for episode in 1..=num_episodes {
+            if episode % 1000 == 0 {
+                println!("Episode: {episode} / {num_episodes}");
+            }
+
+            let mut state = S::sample_initial_state(&mut rng);
+            let mut discount = 1.0;
+            while !state.is_terminal() {
+                let action = self.sample_action(&state, &mut rng);
+                let next_state = state.sample_next_state(&action, &mut rng);
+                let reward = S::get_reward(&state, &action, &next_state);
+                // delta = R + gamma * v(S') - v(S), with v(S') = 0 when S' is terminal
+                let next_state_value = Self::from_our_side(self.get_state_value(&next_state));
+                let delta = reward + self.gamma * next_state_value - self.get_state_value(&state);
+                self.value_step(&state, delta);
+                self.policy_step(&state, &action, discount * delta);
+                discount *= self.gamma;
+                state = next_state;
+            }
+        }
+    }
+}
+
+
+impl<S, F, G> ReinforcementLearner<S> for PolicyGradientLearner<S, F, G>
+where
+    S: Environment,
+    F: FeatureExtractor<S>,
+    G: StateFeatureExtractor<S>
+{
+    // Action preferences rather than action values.
+    fn get_action_value(&self, state: &S, action: &S::A) -> f64 {
+        Self::dot(&self.policy_weights, &self.action_features.features(state, action))
+    }
+
+    fn get_action_values(&self, state: &S) -> Vec<(S::A, f64)> {
+        state.available_actions()
+            .into_iter()
+            .map(|action| {
+                let preference = self.get_action_value(state, &action);
+                (action, preference)
+            })
+            .collect()
+    }
+
+    // The most probable action.
+    fn get_best_action(&self, state: &S) -> S::A {
+        let mut actions_and_preferences = self.get_action_values(state);
+        let preferences: Vec<f64> = actions_and_preferences.iter().map(|(_, preference)| *preference).collect();
+        let best = greedy_index(&preferences, &mut rand::thread_rng());
+        actions_and_preferences.swap_remove(best).0
+    }
+
+    // A single actor-critic step.
+    fn update_action_value(&mut self, state: &S, action: &S::A, next_state: &S, reward: f64) {
+        let next_state_value = Self::from_our_side(self.get_state_value(next_state));
+        let delta = reward + self.gamma * next_state_value - self.get_state_value(state);
+        self.value_step(state, delta);
+        self.policy_step(state, action, delta);
+    }
+
+    fn get_state_value(&self, state: &S) -> f64 {
+        if state.is_terminal() {
+            return 0.0;
+        }
+        Self::dot(&self.value_weights, &self.state_features.state_features(state))
+    }
+
+    fn update_state_value(&mut self, state: &S, value: f64) {
+        let error = value - self.get_state_value(state);
+        self.value_step(state, error);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use core::fmt;
+
+    use crate::reinforcement_learning::features::{FeatureExtractor, Features, StateFeatureExtractor};
+    use crate::reinforcement_learning::generic_reinforcement_learner::{Action, Environment};
+
+    use super::PolicyGradientLearner;
+
+    // The short corridor with switched actions, example 13.1 of the book.
+    // The states look the same to the agent, and in the second one the
+    // actions are reversed, so the best policy goes right with probability
+    // 2 - sqrt(2), about 0.59, and no deterministic policy reaches the goal.
+    #[derive(PartialEq, Eq, Hash, Clone, Copy)]
+    enum Direction {
+        Left,
+        Right
+    }
+
+    impl Action for Direction {}
+
+    impl fmt::Display for Direction {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self {
+                Direction::Left => write!(f, "Left"),
+                Direction::Right => write!(f, "Right"),
+            }
+        }
+    }
+
+    #[derive(PartialEq, Eq, Hash, Clone, Copy)]
+    struct Corridor(usize);
+
+    impl fmt::Display for Corridor {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{}", self.0)
+        }
+    }
+
+    impl Environment for Corridor {
+        type A = Direction;
+
+        fn initial_state() -> Self {
+            Corridor(0)
+        }
+
+        fn next_state(&self, action: &Direction) -> Self {
+            let forwards = (*action == Direction::Right) != (self.0 == 1);
+            if forwards { Corridor(self.0 + 1) } else { Corridor(self.0.saturating_sub(1)) }
+        }
+
+        fn is_terminal(&self) -> bool {
+            self.0 == 3
+        }
+
+        fn available_actions(&self) -> Vec<Direction> {
+            if self.is_terminal() { Vec::new() } else { vec![Direction::Left, Direction::Right] }
+        }
+
+        fn get_reward(_state: &Self, _action: &Direction, _next_state: &Self) -> f64 {
+            -1.0
+        }
+    }
+
+    // Every state has the same action features, but the critic can tell the
+    // states apart.
+    struct CorridorFeatures;
+
+    impl FeatureExtractor<Corridor> for CorridorFeatures {
+        fn num_features(&self) -> usize {
+            2
+        }
+
+        fn features(&self, _state: &Corridor, action: &Direction) -> Features {
+            match action {
+                Direction::Left => vec![(0, 1.0)],
+                Direction::Right => vec![(1, 1.0)],
+            }
+        }
+    }
+
+    impl StateFeatureExtractor<Corridor> for CorridorFeatures {
+        fn num_state_features(&self) -> usize {
+            3
+        }
+
+        fn state_features(&self, state: &Corridor) -> Features {
+            vec![(state.0, 1.0)]
+        }
+    }
+
+    type CorridorLearner = PolicyGradientLearner<Corridor, CorridorFeatures, CorridorFeatures>;
+
+    // Starts off going right only one time in five.
+    fn corridor_learner(policy_alpha: f64, value_alpha: f64) -> CorridorLearner {
+        let mut learner = PolicyGradientLearner::new(CorridorFeatures, CorridorFeatures, policy_alpha, value_alpha, 1.0);
+        learner.policy_weights = vec![4.0f64.ln(), 0.0];
+        learner
+    }
+
+    fn probability_of_right(learner: &CorridorLearner) -> f64 {
+        learner.action_probabilities(&Corridor(0))
+            .into_iter()
+            .find(|(action, _)| *action == Direction::Right)
+            .unwrap().1
+    }
+
+    #[test]
+    fn reinforce_learns_a_stochastic_policy() {
+        let mut learner = corridor_learner(2.0f64.powi(-10), 2.0f64.powi(-6));
+        learner.reinforce(4000);
+        assert!((probability_of_right(&learner) - (2.0 - 2.0f64.sqrt())).abs() < 0.2);
+    }
+
+    #[test]
+    fn actor_critic_learns_a_stochastic_policy() {
+        let mut learner = corridor_learner(2.0f64.powi(-10), 2.0f64.powi(-6));
+        learner.actor_critic(4000);
+        assert!((probability_of_right(&learner) - (2.0 - 2.0f64.sqrt())).abs() < 0.2);
+    }
+}
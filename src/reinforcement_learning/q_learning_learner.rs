@@ -1,48 +1,68 @@
-use std::collections::{HashMap, hash_map::RandomState};
+use std::collections::HashMap;
+use std::marker::PhantomData;
 
-use rand::Rng;
+use rand::{Rng, RngCore};
 
-use super::generic_reinforcement_learner::{ReinforcementLearner, Action, State, Policy};
+use crate::evaluation::Outcome;
+
+use super::generic_reinforcement_learner::{Environment, ReinforcementLearner, State, Policy};
+use super::epsilon_greedy_policy::greedy_index;
+use super::league::League;
+use super::metrics::{MetricsLog, MetricsRecorder, PeriodicEvaluation};
+use super::training_hooks::{HookControl, Step, TrainingHook};
+use super::replay_buffer::{NStepBuffer, ReplayBuffer, Transition};
+use super::q_table::QTable;
+pub use super::q_table::StateAction;
 
 // Sutton and Barto RL book:
 // https://web.stanford.edu/class/psych209/Readings/SuttonBartoIPRLBook2ndEd.pdf
 // Q learning algorithm taken from page 158.
 
-#[derive(PartialEq, Eq, Hash)]
-pub struct StateAction<S, A>(S, A)
+// The Q values are kept in a hash map unless another QTable is given.
+#[derive(Clone)]
+pub struct QLearner<S, T = HashMap<StateAction<S, <S as Environment>::A>, f64>>
 where
     S: State,
-    A: Action;
+    T: QTable<S>
+{
+    pub q_values: T,
+    pub alpha: f64,
+    pub gamma: f64,
+    state: PhantomData<S>,
+}
 
 
-pub struct QLearner<S>
+impl<S> QLearner<S>
 where
     S: State
 {
-    pub q_values: HashMap<StateAction<S, S::A>, f64, RandomState>,
-    pub alpha: f64,
-    pub gamma: f64
+    pub fn new(alpha: f64, gamma: f64) -> Self {
+        Self::with_table(HashMap::new(), alpha, gamma)
+    }
+}
+
+impl<S, T> QLearner<S, T>
+where
+    S: State,
+    T: QTable<S>
+{
+    pub fn with_table(q_values: T, alpha: f64, gamma: f64) -> Self {
+        QLearner { q_values, alpha, gamma, state: PhantomData }
+    }
 }
 
 
-impl<S> ReinforcementLearner<S> for QLearner<S>
+impl<S, T> ReinforcementLearner<S> for QLearner<S, T>
 where
-    S: State
+    S: State,
+    T: QTable<S>
 {
     fn get_action_value(&self, state: &S, action: &S::A) -> f64 {
-        match self.q_values.get(&StateAction (state.clone(), action.clone())) {
-            Some(value) => *value,
-            None => 0.0
-        }
+        self.q_values.value(state, action)
     }
 
     fn get_action_values(&self, state: &S) -> Vec<(S::A, f64)> {
-        let mut values: Vec<(S::A, f64)> = Vec::new();
-        for action in state.available_actions() {
-            let value = self.get_action_value(state, &action);
-            values.push((action, value));
-        }
-        values
+        self.q_values.action_values(state, state.available_actions())
     }
 
     fn get_best_action(&self, state: &S) -> S::A {
@@ -50,7 +70,7 @@ where
         if actions_and_values.is_empty() {
             panic!("No actions available, state is terminal?");
         }
-        let mut max: f64 = -1000.0;
+        let mut max = f64::NEG_INFINITY;
         let mut best_actions = Vec::new();
         for (action, value) in actions_and_values {
             if cfg!(debug_assertions) {
@@ -78,15 +98,7 @@ where
     }
 
     fn update_action_value(&mut self, state: &S, action: &S::A, next_state: &S, reward: f64) {
-        let current_q_value = self.get_action_value(state, action);
-        if cfg!(debug_assertions) { println!("{next_state}"); }
-        let new_value = current_q_value +
-            self.alpha * (reward + self.gamma * (-1.0 * self.get_state_value(next_state) - current_q_value));
-        if cfg!(debug_assertions) {
-            println!("Old Q value: {current_q_value}, new Q Value: {new_value}")
-        }
-        let state_action = StateAction(state.clone(), action.clone());
-        self.q_values.insert(state_action, new_value);
+        self.td_update(state, action, next_state, reward);
     }
 
     fn get_state_value(&self, state: &S) -> f64 {
@@ -103,14 +115,97 @@ where
 }
 
 
-impl<S> QLearner<S>
+impl<S, T> QLearner<S, T>
 where
-    S: State
+    S: State,
+    T: QTable<S>
 {
+    // Q(S, A) = Q(S, A) + alpha * (R + gamma * V(S') - Q(S, A)), where V(S')
+    // is the value of S' for the player who moved into it.
+    // Returns the TD error.
+    fn td_update(&mut self, state: &S, action: &S::A, next_state: &S, reward: f64) -> f64 {
+        self.check_discount();
+        let current_q_value = self.get_action_value(state, action);
+        if cfg!(debug_assertions) { println!("{next_state}"); }
+        let next_state_value = self.value_after_move(next_state);
+        let td_error = reward + self.gamma * next_state_value - current_q_value;
+        let new_value = current_q_value + self.alpha * td_error;
+        if cfg!(debug_assertions) {
+            println!("Old Q value: {current_q_value}, new Q Value: {new_value}")
+        }
+        self.q_values.set(state, action, new_value);
+        td_error
+    }
+
+    // Shaped rewards are only sound when learnt with the discount they were
+    // shaped for.
+    fn check_discount(&self) {
+        if let Some(discount) = S::shaping_discount() {
+            assert!(
+                (discount - self.gamma).abs() < 1e-12,
+                "The rewards are shaped for a discount of {discount}, but the learner's gamma is {}", self.gamma
+            );
+        }
+    }
+
+    // The largest action value in the state, or zero once it is terminal.
+    fn max_action_value(&self, state: &S) -> f64 {
+        if state.is_terminal() {
+            return 0.0;
+        }
+        self.get_action_values(state).into_iter().map(|(_, value)| value).fold(f64::NEG_INFINITY, f64::max)
+    }
+
+    // The value of next_state for the player who moved into it. Where players
+    // alternate it is the opponent's turn, so this is what the opponent's
+    // greedy reply gives that player, by get_opponent_reward, plus their
+    // value of the position after it. Each side is then learnt with its own
+    // rewards, rather than one side's being minus the other's, which only
+    // holds in zero-sum games. The greedy reply is picked at random among
+    // ties, so tied replies are averaged over.
+    fn value_after_move(&self, next_state: &S) -> f64 {
+        if !S::alternates_players() || next_state.is_terminal() {
+            return self.max_action_value(next_state);
+        }
+        let replies = self.get_action_values(next_state);
+        let best = replies.iter().map(|(_, value)| *value).fold(f64::NEG_INFINITY, f64::max);
+        let best_replies: Vec<&S::A> = replies.iter().filter(|(_, value)| *value == best).map(|(reply, _)| reply).collect();
+        let total: f64 = best_replies.iter()
+            .map(|reply| {
+                let after_reply = next_state.next_state(reply);
+                S::get_opponent_reward(next_state, reply, &after_reply) + self.gamma * self.max_action_value(&after_reply)
+            })
+            .sum();
+        total / best_replies.len() as f64
+    }
+
     // Let's do a simple Q-learning implementation
     pub fn q_learning(&mut self, policy: &dyn Policy<S>, num_episodes: u32) {
+        self.q_learning_with_hooks(policy, num_episodes, &mut []);
+    }
+
+    // Q-learning that also records the learning curve: a row of metrics per
+    // episode and, if given a periodic evaluation, the results of playing
+    // the greedy policy against fixed opponents every so often.
+    pub fn q_learning_with_metrics(&mut self, policy: &dyn Policy<S>, num_episodes: u32, evaluation: Option<&mut PeriodicEvaluation<S>>) -> MetricsLog {
+        let mut recorder = MetricsRecorder::new(evaluation);
+        self.q_learning_with_hooks(policy, num_episodes, &mut [&mut recorder]);
+        recorder.log
+    }
+
+    // Q-learning that calls the hooks as it goes, any of which can stop
+    // training early. Returns the number of episodes completed.
+    pub fn q_learning_with_hooks(&mut self, policy: &dyn Policy<S>, num_episodes: u32, hooks: &mut [&mut dyn TrainingHook<S, Self>]) -> u32 {
+        self.q_learning_with_rng(policy, num_episodes, hooks, &mut rand::thread_rng())
+    }
+
+    // q_learning_with_hooks drawing every random choice from the given RNG,
+    // so that a seeded RNG gives the same training run every time.
+    pub fn q_learning_with_rng(&mut self, policy: &dyn Policy<S>, num_episodes: u32, hooks: &mut [&mut dyn TrainingHook<S, Self>], rng: &mut dyn RngCore) -> u32 {
         // Initialise Q(s, a) arbitrarily for any s, a, and for terminal states set Q(s, _) = 0
     
+        let mut completed_episodes = 0;
+
         // Repeat for each episode
         for episode in 1..=num_episodes {
             if cfg!(debug_assertions) || episode % 1000 == 0 {
@@ -118,7 +213,10 @@ where
             }
             
             // Initialise S
-            let mut state: S = State::initial_state();
+            let mut state = S::sample_initial_state(rng);
+            for hook in hooks.iter_mut() {
+                hook.on_episode_start(episode, &state);
+            }
             // Repeat for each step of episode
             while !state.is_terminal() {
                 if cfg!(debug_assertions) {
@@ -126,20 +224,192 @@ where
                 }
                 // Choose A from S using policy derived from Q (e.g. epsilon-greedy)
                 let mut action_values = self.get_action_values(&state);
-                let action = policy.get_action(&mut action_values);
+                let max_value = action_values.iter().map(|(_, value)| *value).fold(f64::NEG_INFINITY, f64::max);
+                let action = policy.sample_action(&mut action_values, rng);
+                let greedy = self.get_action_value(&state, &action) >= max_value;
         
                 // Take action A, observe R, S'
-                let next_state = state.next_state(&action);
+                let next_state = state.sample_next_state(&action, rng);
                 let reward = S::get_reward(&state, &action, &next_state);
                 
-                // Q(S, A) = Q(S, A) + alpha * (R + gamma * max_a Q(S', a) - Q(S, A))
-                self.update_action_value(&state, &action, &next_state, reward);
+                // Q(S, A) = Q(S, A) + alpha * (R + gamma * V(S') - Q(S, A)),
+                // looking past the opponent's reply when S' is their turn
+                let td_error = self.td_update(&state, &action, &next_state, reward);
+
+                let step = Step { episode, state: &state, action: &action, reward, next_state: &next_state, td_error, greedy };
+                for hook in hooks.iter_mut() {
+                    hook.on_step(&step);
+                }
                 
                 // S = S'
                 state = next_state;
                 // Until S is terminal
             }
             if cfg!(debug_assertions) { println!("{state}"); }
+
+            completed_episodes = episode;
+            let mut stop = false;
+            for hook in hooks.iter_mut() {
+                stop |= hook.on_episode_end(episode, self) == HookControl::Stop;
+            }
+            if stop {
+                break;
+            }
+        }
+        for hook in hooks.iter_mut() {
+            hook.on_training_end(completed_episodes, self);
+        }
+        completed_episodes
+    }
+
+    // Q(S, A) = Q(S, A) + weight * alpha * (target - Q(S, A)) for a stored
+    // transition, which may span several steps. Returns the TD error.
+    pub fn learn_from_transition(&mut self, transition: &Transition<S>, weight: f64) -> f64 {
+        self.check_discount();
+        let current_q_value = self.get_action_value(&transition.state, &transition.action);
+        // After an odd number of steps of a game where players alternate,
+        // the opponent is to move
+        let next_state_value = if transition.done {
+            0.0
+        } else if S::alternates_players() && transition.steps % 2 == 1 {
+            self.value_after_move(&transition.next_state)
+        } else {
+            self.max_action_value(&transition.next_state)
+        };
+        let discount = self.gamma.powi(transition.steps as i32);
+        let td_error = transition.reward + discount * next_state_value - current_q_value;
+        self.q_values.set(&transition.state, &transition.action, current_q_value + weight * self.alpha * td_error);
+        td_error
+    }
+
+    // Learns from a minibatch sampled from the buffer.
+    pub fn replay<B: ReplayBuffer<S>>(&mut self, buffer: &mut B, batch_size: usize) {
+        let sample = buffer.sample(batch_size);
+        let mut indices = Vec::with_capacity(sample.len());
+        let mut td_errors = Vec::with_capacity(sample.len());
+        for (index, weight) in sample {
+            let transition = buffer.get(index).clone();
+            td_errors.push(self.learn_from_transition(&transition, weight));
+            indices.push(index);
+        }
+        buffer.update_priorities(&indices, &td_errors);
+    }
+
+    // The best action in the state, breaking ties with the given RNG.
+    fn greedy_action(&self, state: &S, rng: &mut dyn RngCore) -> S::A {
+        let mut actions_and_values = self.get_action_values(state);
+        let values: Vec<f64> = actions_and_values.iter().map(|(_, value)| *value).collect();
+        actions_and_values.swap_remove(greedy_index(&values, rng)).0
+    }
+
+    // Q-learning that stores every n-step transition in the buffer and
+    // learns from a replayed minibatch after each step, rather than from
+    // the latest step alone. With n_steps above one the rewards of the
+    // policy's exploratory moves end up in the targets, so the values learnt
+    // are no longer exactly those of the greedy policy.
+    pub fn q_learning_with_replay<B: ReplayBuffer<S>>(&mut self, policy: &dyn Policy<S>, num_episodes: u32, buffer: &mut B, n_steps: usize, batch_size: usize) {
+        self.q_learning_with_replay_and_rng(policy, num_episodes, buffer, n_steps, batch_size, &mut rand::thread_rng());
+    }
+
+    // q_learning_with_replay drawing the policy's and the environment's
+    // random choices from the given RNG. The buffer samples from its own
+    // seeded RNG, so a seeded RNG here gives the same training run every time.
+    pub fn q_learning_with_replay_and_rng<B: ReplayBuffer<S>>(&mut self, policy: &dyn Policy<S>, num_episodes: u32, buffer: &mut B, n_steps: usize, batch_size: usize, rng: &mut dyn RngCore) {
+        let mut n_step = NStepBuffer::new(n_steps, self.gamma);
+
+        for episode in 1..=num_episodes {
+            if cfg!(debug_assertions) || episode % 1000 == 0 {
+                println!("Episode: {episode} / {num_episodes}");
+            }
+
+            let mut state = S::sample_initial_state(rng);
+            while !state.is_terminal() {
+                let mut action_values = self.get_action_values(&state);
+                let action = policy.sample_action(&mut action_values, rng);
+                let next_state = state.sample_next_state(&action, rng);
+                let reward = S::get_reward(&state, &action, &next_state);
+                for transition in n_step.push(Transition::new(state, action, reward, next_state.clone())) {
+                    buffer.push(transition);
+                }
+                self.replay(buffer, batch_size);
+                state = next_state;
+            }
+        }
+    }
+
+    // Q-learning against opponents drawn from the league, adding a snapshot
+    // of the learner to it on schedule. The opponents play their greedy
+    // moves and only the learner's own moves are learnt from: each of them
+    // is a two-step transition from one of the learner's turns to the next,
+    // over the opponent's reply.
+    pub fn league_self_play(&mut self, policy: &dyn Policy<S>, num_episodes: u32, league: &mut League<S, T>)
+    where
+        T: Clone
+    {
+        self.league_self_play_with_rng(policy, num_episodes, league, &mut rand::thread_rng());
+    }
+
+    // league_self_play drawing the learner's moves and the environment's
+    // randomness from the given RNG. The league draws the opponents from its
+    // own seeded RNG, so a seeded RNG here gives the same league every time.
+    pub fn league_self_play_with_rng(&mut self, policy: &dyn Policy<S>, num_episodes: u32, league: &mut League<S, T>, rng: &mut dyn RngCore)
+    where
+        T: Clone
+    {
+        if league.snapshots.is_empty() {
+            league.add_snapshot(0, self);
+        }
+
+        for episode in 1..=num_episodes {
+            if cfg!(debug_assertions) || episode % 1000 == 0 {
+                println!("Episode: {episode} / {num_episodes}");
+            }
+
+            let opponent_index = league.sample_opponent().expect("the league starts with a snapshot of the learner");
+            let learner_moves_first = league.learner_moves_first();
+            let opponent = &league.snapshots[opponent_index].learner;
+            let mut state = S::sample_initial_state(rng);
+            if !learner_moves_first && !state.is_terminal() {
+                let action = opponent.greedy_action(&state, rng);
+                state = state.sample_next_state(&action, rng);
+            }
+
+            let mut outcome = Outcome::Draw;
+            while !state.is_terminal() {
+                let mut action_values = self.get_action_values(&state);
+                let action = policy.sample_action(&mut action_values, rng);
+                let next_state = state.sample_next_state(&action, rng);
+                let reward = S::get_reward(&state, &action, &next_state);
+
+                if next_state.is_terminal() {
+                    outcome = Outcome::from_reward(S::get_result(&state, &action, &next_state));
+                    self.learn_from_transition(&Transition::new(state, action, reward, next_state), 1.0);
+                    break;
+                }
+
+                // The opponent's reply, and what it gives us
+                let reply = opponent.greedy_action(&next_state, rng);
+                let after_reply = next_state.sample_next_state(&reply, rng);
+                let reply_reward = S::get_opponent_reward(&next_state, &reply, &after_reply);
+                if after_reply.is_terminal() {
+                    outcome = Outcome::from_reward(S::get_result(&next_state, &reply, &after_reply)).reversed();
+                }
+                let transition = Transition {
+                    state,
+                    action,
+                    reward: reward + self.gamma * reply_reward,
+                    next_state: after_reply.clone(),
+                    done: after_reply.is_terminal(),
+                    steps: 2,
+                };
+                self.learn_from_transition(&transition, 1.0);
+                state = after_reply;
+            }
+            league.snapshots[opponent_index].results.record(outcome);
+
+            if league.snapshot_interval > 0 && episode % league.snapshot_interval == 0 {
+                league.add_snapshot(episode, self);
+            }
         }
     }
 }
@@ -0,0 +1,533 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufRead, Write};
+use std::marker::PhantomData;
+
+use super::generic_reinforcement_learner::{Action, EnumerableState, State, TextEncoding};
+
+// Storage for the action values of a tabular learner. Pairs that have not
+// been given a value are worth the table's default value, so that tables can
+// start out optimistic.
+
+pub trait QTable<S>
+where
+    S: State
+{
+    // The stored value, if the pair has been given one.
+    fn get(&self, state: &S, action: &S::A) -> Option<f64>;
+    fn set(&mut self, state: &S, action: &S::A, value: f64);
+    // Every stored state-action pair with its value, in no particular order.
+    fn iter(&self) -> Box<dyn Iterator<Item = (S, S::A, f64)> + '_>;
+    // Number of state-action pairs with a stored value
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn default_value(&self) -> f64 {
+        0.0
+    }
+    fn value(&self, state: &S, action: &S::A) -> f64 {
+        self.get(state, action).unwrap_or_else(|| self.default_value())
+    }
+    // The values of the given actions in the state. Tables that keep a
+    // state's values together can look them all up at once.
+    fn action_values(&self, state: &S, actions: Vec<S::A>) -> Vec<(S::A, f64)> {
+        actions.into_iter()
+            .map(|action| {
+                let value = self.value(state, &action);
+                (action, value)
+            })
+            .collect()
+    }
+}
+
+
+#[derive(Clone)]
+#[derive(PartialEq, Eq, Hash)]
+pub struct StateAction<S, A>(S, A)
+where
+    S: State,
+    A: Action;
+
+impl<S, A> StateAction<S, A>
+where
+    S: State,
+    A: Action
+{
+    pub fn new(state: S, action: A) -> Self {
+        StateAction(state, action)
+    }
+
+    pub fn state(&self) -> &S {
+        &self.0
+    }
+
+    pub fn action(&self) -> &A {
+        &self.1
+    }
+}
+
+
+// Stores only the pairs that have been visited, which suits large or
+// unbounded state spaces, at the cost of cloning and hashing the state and
+// action on every lookup.
+impl<S> QTable<S> for HashMap<StateAction<S, S::A>, f64>
+where
+    S: State
+{
+    fn get(&self, state: &S, action: &S::A) -> Option<f64> {
+        HashMap::get(self, &StateAction(state.clone(), action.clone())).copied()
+    }
+
+    fn set(&mut self, state: &S, action: &S::A, value: f64) {
+        self.insert(StateAction(state.clone(), action.clone()), value);
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (S, S::A, f64)> + '_> {
+        Box::new(HashMap::iter(self).map(|(StateAction(state, action), value)| (state.clone(), action.clone(), *value)))
+    }
+
+    fn len(&self) -> usize {
+        HashMap::len(self)
+    }
+}
+
+
+// Stores a value for every state-action pair in one flat vector, indexed by
+// state_index * num_actions + action_index, so lookups need no hashing. Only
+// practical when the state space is small enough to allocate in full.
+#[derive(Clone)]
+pub struct DenseQTable<S>
+where
+    S: EnumerableState
+{
+    values: Vec<f64>,
+    // Whether each pair has been given a value
+    visited: Vec<bool>,
+    num_visited: usize,
+    default_value: f64,
+    state: PhantomData<S>,
+}
+
+impl<S> DenseQTable<S>
+where
+    S: EnumerableState
+{
+    pub fn new() -> Self {
+        let size = S::num_states() * S::num_actions();
+        DenseQTable { values: vec![0.0; size], visited: vec![false; size], num_visited: 0, default_value: 0.0, state: PhantomData }
+    }
+
+    pub fn with_default_value(mut self, default_value: f64) -> Self {
+        self.default_value = default_value;
+        self
+    }
+
+    fn index(state: &S, action: &S::A) -> usize {
+        state.state_index() * S::num_actions() + S::action_index(action)
+    }
+}
+
+impl<S> Default for DenseQTable<S>
+where
+    S: EnumerableState
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> QTable<S> for DenseQTable<S>
+where
+    S: State + EnumerableState
+{
+    fn get(&self, state: &S, action: &S::A) -> Option<f64> {
+        let index = Self::index(state, action);
+        if self.visited[index] { Some(self.values[index]) } else { None }
+    }
+
+    fn set(&mut self, state: &S, action: &S::A, value: f64) {
+        let index = Self::index(state, action);
+        if !self.visited[index] {
+            self.visited[index] = true;
+            self.num_visited += 1;
+        }
+        self.values[index] = value;
+    }
+
+    // Rebuilds each visited pair from its index.
+    fn iter(&self) -> Box<dyn Iterator<Item = (S, S::A, f64)> + '_> {
+        let num_actions = S::num_actions();
+        Box::new(
+            (0..self.values.len())
+                .filter(|index| self.visited[*index])
+                .filter_map(move |index| {
+                    let state = S::from_state_index(index / num_actions);
+                    let action = state.available_actions()
+                        .into_iter()
+                        .find(|action| S::action_index(action) == index % num_actions)?;
+                    Some((state, action, self.values[index]))
+                })
+        )
+    }
+
+    fn len(&self) -> usize {
+        self.num_visited
+    }
+
+    fn default_value(&self) -> f64 {
+        self.default_value
+    }
+}
+
+
+// Keeps the values of each state's actions together under one key, so that
+// looking up every action of a state hashes the state once.
+#[derive(Clone)]
+pub struct PerStateQTable<S>
+where
+    S: State
+{
+    values: HashMap<S, Vec<(S::A, f64)>>,
+    num_values: usize,
+    default_value: f64,
+}
+
+impl<S> PerStateQTable<S>
+where
+    S: State
+{
+    pub fn new() -> Self {
+        PerStateQTable { values: HashMap::new(), num_values: 0, default_value: 0.0 }
+    }
+
+    pub fn with_default_value(mut self, default_value: f64) -> Self {
+        self.default_value = default_value;
+        self
+    }
+}
+
+impl<S> Default for PerStateQTable<S>
+where
+    S: State
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> QTable<S> for PerStateQTable<S>
+where
+    S: State
+{
+    fn get(&self, state: &S, action: &S::A) -> Option<f64> {
+        self.values.get(state)?
+            .iter()
+            .find(|(stored_action, _)| stored_action == action)
+            .map(|(_, value)| *value)
+    }
+
+    fn set(&mut self, state: &S, action: &S::A, value: f64) {
+        let action_values = self.values.entry(state.clone()).or_default();
+        match action_values.iter_mut().find(|(stored_action, _)| stored_action == action) {
+            Some((_, stored_value)) => *stored_value = value,
+            None => {
+                action_values.push((action.clone(), value));
+                self.num_values += 1;
+            }
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (S, S::A, f64)> + '_> {
+        Box::new(self.values.iter().flat_map(|(state, action_values)| {
+            action_values.iter().map(move |(action, value)| (state.clone(), action.clone(), *value))
+        }))
+    }
+
+    fn len(&self) -> usize {
+        self.num_values
+    }
+
+    fn default_value(&self) -> f64 {
+        self.default_value
+    }
+
+    fn action_values(&self, state: &S, actions: Vec<S::A>) -> Vec<(S::A, f64)> {
+        let stored = self.values.get(state);
+        actions.into_iter()
+            .map(|action| {
+                let value = stored
+                    .and_then(|action_values| action_values.iter().find(|(stored_action, _)| *stored_action == action))
+                    .map_or(self.default_value, |(_, value)| *value);
+                (action, value)
+            })
+            .collect()
+    }
+}
+
+
+// Holds at most a fixed number of values, forgetting the least recently used
+// pair to make room for a new one. Forgotten pairs go back to the default
+// value, so the learner has to relearn them if it visits them again.
+#[derive(Clone)]
+pub struct LruQTable<S>
+where
+    S: State
+{
+    capacity: usize,
+    // Each value with the time it was last used
+    values: HashMap<StateAction<S, S::A>, (f64, Cell<u64>)>,
+    // The pairs by the time they were last used. Reads count as uses, so
+    // this is updated through a shared reference.
+    recency: RefCell<BTreeMap<u64, StateAction<S, S::A>>>,
+    clock: Cell<u64>,
+    default_value: f64,
+}
+
+impl<S> LruQTable<S>
+where
+    S: State
+{
+    pub fn new(capacity: usize) -> Self {
+        LruQTable {
+            capacity: capacity.max(1),
+            values: HashMap::new(),
+            recency: RefCell::new(BTreeMap::new()),
+            clock: Cell::new(0),
+            default_value: 0.0,
+        }
+    }
+
+    pub fn with_default_value(mut self, default_value: f64) -> Self {
+        self.default_value = default_value;
+        self
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.set(self.clock.get() + 1);
+        self.clock.get()
+    }
+
+    // Marks the pair last used at the given time as used now.
+    fn touch(&self, last_used: &Cell<u64>) {
+        let now = self.tick();
+        let mut recency = self.recency.borrow_mut();
+        if let Some(state_action) = recency.remove(&last_used.replace(now)) {
+            recency.insert(now, state_action);
+        }
+    }
+}
+
+impl<S> QTable<S> for LruQTable<S>
+where
+    S: State
+{
+    fn get(&self, state: &S, action: &S::A) -> Option<f64> {
+        let (value, last_used) = self.values.get(&StateAction(state.clone(), action.clone()))?;
+        self.touch(last_used);
+        Some(*value)
+    }
+
+    fn set(&mut self, state: &S, action: &S::A, value: f64) {
+        let state_action = StateAction(state.clone(), action.clone());
+        if let Some((_, last_used)) = self.values.get(&state_action) {
+            self.touch(last_used);
+        }
+        if let Some((stored_value, _)) = self.values.get_mut(&state_action) {
+            *stored_value = value;
+            return;
+        }
+        if self.values.len() >= self.capacity {
+            if let Some((_, oldest)) = self.recency.get_mut().pop_first() {
+                self.values.remove(&oldest);
+            }
+        }
+        let now = self.tick();
+        self.recency.get_mut().insert(now, state_action.clone());
+        self.values.insert(state_action, (value, Cell::new(now)));
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (S, S::A, f64)> + '_> {
+        Box::new(self.values.iter().map(|(StateAction(state, action), (value, _))| (state.clone(), action.clone(), *value)))
+    }
+
+    fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    fn default_value(&self) -> f64 {
+        self.default_value
+    }
+}
+
+
+// Writes one line per stored value: the state's text, the action and the
+// value, separated by tabs.
+pub fn write_q_table<S, T, W>(table: &T, mut writer: W) -> io::Result<()>
+where
+    S: State + TextEncoding,
+    T: QTable<S>,
+    W: Write
+{
+    for (state, action, value) in table.iter() {
+        writeln!(writer, "{}\t{}\t{}", state.to_text(), action, value)?;
+    }
+    Ok(())
+}
+
+// Adds the values written by write_q_table to the table.
+pub fn read_q_table<S, T, R>(table: &mut T, reader: R) -> io::Result<()>
+where
+    S: State + TextEncoding,
+    T: QTable<S>,
+    R: BufRead
+{
+    let invalid = |line_number: usize, message: String| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("Line {}: {}", line_number + 1, message))
+    };
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 3 {
+            return Err(invalid(line_number, "Expected state, action and value".to_string()));
+        }
+        let state = S::from_text(fields[0]).map_err(|err| invalid(line_number, err))?;
+        let action = state.available_actions()
+            .into_iter()
+            .find(|action| action.to_string() == fields[1])
+            .ok_or_else(|| invalid(line_number, format!("Unavailable action {}", fields[1])))?;
+        let value = fields[2].parse().map_err(|_| invalid(line_number, format!("Invalid value {}", fields[2])))?;
+        table.set(&state, &action, value);
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+    use crate::reinforcement_learning::generic_reinforcement_learner::{EnumerableState, Environment, ReinforcementLearner};
+    use crate::reinforcement_learning::q_learning_learner::QLearner;
+    use crate::tictactoe::{TicTacToeBoard, TicTacToeMove};
+
+    use super::{read_q_table, write_q_table, DenseQTable, LruQTable, PerStateQTable, QTable, StateAction};
+
+    type Update = (TicTacToeBoard, TicTacToeMove, TicTacToeBoard, f64);
+
+    // The updates from 200 games of random moves.
+    fn random_updates() -> Vec<Update> {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut updates = Vec::new();
+        for _ in 0..200 {
+            let mut state = TicTacToeBoard::initial_state();
+            while !state.is_terminal() {
+                let action = *state.available_actions().choose(&mut rng).unwrap();
+                let next_state = state.next_state(&action);
+                let reward = TicTacToeBoard::get_reward(&state, &action, &next_state);
+                updates.push((state.clone(), action, next_state.clone(), reward));
+                state = next_state;
+            }
+        }
+        updates
+    }
+
+    fn learn<T: QTable<TicTacToeBoard>>(q_values: T, updates: &[Update]) -> QLearner<TicTacToeBoard, T> {
+        let mut learner = QLearner::with_table(q_values, 0.5, 0.9);
+        for (state, action, next_state, reward) in updates {
+            learner.update_action_value(state, action, next_state, *reward);
+        }
+        learner
+    }
+
+    #[test]
+    fn tictactoe_states_have_distinct_indices() {
+        let mut indices = HashSet::new();
+        let mut frontier = vec![TicTacToeBoard::initial_state()];
+        let mut seen = HashSet::new();
+        while let Some(state) = frontier.pop() {
+            assert!(state.state_index() < TicTacToeBoard::num_states());
+            assert!(TicTacToeBoard::from_state_index(state.state_index()) == state);
+            indices.insert(state.state_index());
+            for action in state.available_actions() {
+                let next_state = state.next_state(&action);
+                if seen.insert(next_state.clone()) {
+                    frontier.push(next_state);
+                }
+            }
+        }
+        assert_eq!(indices.len(), seen.len() + 1);
+    }
+
+    #[test]
+    fn dense_table_learns_the_same_values_as_a_hash_map() {
+        let updates = random_updates();
+        let dense = learn(DenseQTable::new(), &updates);
+        let sparse = learn(HashMap::new(), &updates);
+        assert_eq!(dense.q_values.len(), sparse.q_values.len());
+        for (state, action, _, _) in &updates {
+            assert_eq!(dense.get_action_value(state, action), sparse.get_action_value(state, action));
+        }
+    }
+
+    #[test]
+    fn best_action_is_found_however_low_the_values() {
+        let board = TicTacToeBoard::initial_state();
+        let mut q_values = HashMap::new();
+        for (i, action) in board.available_actions().iter().enumerate() {
+            q_values.set(&board, action, -5000.0 - i as f64);
+        }
+        let learner = QLearner::with_table(q_values, 0.5, 0.9);
+        assert!(learner.get_best_action(&board) == board.available_actions()[0]);
+    }
+
+    #[test]
+    fn every_table_learns_the_same_values() {
+        let updates = random_updates();
+        let sparse = learn(HashMap::new(), &updates);
+        let per_state = learn(PerStateQTable::new(), &updates);
+        let lru = learn(LruQTable::new(100000), &updates);
+        assert_eq!(per_state.q_values.len(), sparse.q_values.len());
+        assert_eq!(lru.q_values.len(), sparse.q_values.len());
+        for (state, _, _, _) in &updates {
+            let values = sparse.get_action_values(state);
+            assert!(per_state.get_action_values(state) == values);
+            assert!(lru.get_action_values(state) == values);
+        }
+    }
+
+    #[test]
+    fn lru_table_forgets_the_least_recently_used_value() {
+        let state = TicTacToeBoard::initial_state();
+        let actions = state.available_actions();
+        let mut table = LruQTable::new(2).with_default_value(0.5);
+        table.set(&state, &actions[0], 1.0);
+        table.set(&state, &actions[1], 2.0);
+        // Reading the first value makes the second the least recently used
+        assert_eq!(table.get(&state, &actions[0]), Some(1.0));
+        table.set(&state, &actions[2], 3.0);
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.get(&state, &actions[1]), None);
+        assert_eq!(table.value(&state, &actions[1]), 0.5);
+        assert_eq!(table.get(&state, &actions[0]), Some(1.0));
+        assert_eq!(table.get(&state, &actions[2]), Some(3.0));
+    }
+
+    #[test]
+    fn tables_round_trip_through_text() {
+        let sparse = learn(HashMap::new(), &random_updates());
+        let mut text = Vec::new();
+        write_q_table(&sparse.q_values, &mut text).unwrap();
+
+        let mut dense = DenseQTable::new();
+        read_q_table(&mut dense, text.as_slice()).unwrap();
+        assert_eq!(dense.len(), sparse.q_values.len());
+        for (state, action, value) in dense.iter() {
+            assert_eq!(Some(value), sparse.q_values.get(&StateAction(state, action)).copied());
+        }
+
+        assert!(read_q_table(&mut DenseQTable::<TicTacToeBoard>::new(), "XOX      X\t(0, 0)\t1".as_bytes()).is_err());
+    }
+}
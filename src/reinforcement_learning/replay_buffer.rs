@@ -0,0 +1,455 @@
+use std::collections::VecDeque;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::generic_reinforcement_learner::Environment;
+
+// Experience replay: transitions are stored as they happen and learners
+// train on samples of them later, which reuses experience and breaks up the
+// correlation between consecutive steps.
+
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct Transition<S>
+where
+    S: Environment
+{
+    pub state: S,
+    pub action: S::A,
+    // The discounted sum of the rewards over all the steps, seen from the
+    // player who chose the action
+    pub reward: f64,
+    pub next_state: S,
+    // Whether next_state is terminal, in which case it has no value
+    pub done: bool,
+    // How many environment steps apart state and next_state are
+    pub steps: u32,
+}
+
+impl<S> Transition<S>
+where
+    S: Environment
+{
+    pub fn new(state: S, action: S::A, reward: f64, next_state: S) -> Self {
+        let done = next_state.is_terminal();
+        Transition { state, action, reward, next_state, done, steps: 1 }
+    }
+
+    // What the value of next_state is multiplied by in the target. It is
+    // negated once for every turn handed to the opponent.
+    pub fn bootstrap_discount(&self, gamma: f64) -> f64 {
+        if self.done {
+            return 0.0;
+        }
+        step_discount::<S>(gamma).powi(self.steps as i32)
+    }
+}
+
+fn step_discount<S: Environment>(gamma: f64) -> f64 {
+    if S::alternates_players() { -gamma } else { gamma }
+}
+
+
+pub trait ReplayBuffer<S>
+where
+    S: Environment
+{
+    fn push(&mut self, transition: Transition<S>);
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn get(&self, index: usize) -> &Transition<S>;
+    // Indices of batch_size sampled transitions, each with the importance
+    // sampling weight to scale its update by.
+    fn sample(&mut self, batch_size: usize) -> Vec<(usize, f64)>;
+    // Buffers that prioritise transitions use the TD errors of the latest
+    // updates to decide what to sample next.
+    fn update_priorities(&mut self, _indices: &[usize], _td_errors: &[f64]) {
+    }
+}
+
+
+// A fixed-capacity ring of transitions, where the newest overwrite the oldest.
+struct Ring<S>
+where
+    S: Environment
+{
+    capacity: usize,
+    transitions: Vec<Transition<S>>,
+    next_slot: usize,
+}
+
+impl<S> Ring<S>
+where
+    S: Environment
+{
+    fn new(capacity: usize) -> Self {
+        if capacity == 0 {
+            panic!("Replay buffer capacity must be positive");
+        }
+        Ring { capacity, transitions: Vec::with_capacity(capacity), next_slot: 0 }
+    }
+
+    // Returns the slot the transition went into.
+    fn push(&mut self, transition: Transition<S>) -> usize {
+        let slot = self.next_slot;
+        if self.transitions.len() < self.capacity {
+            self.transitions.push(transition);
+        } else {
+            self.transitions[slot] = transition;
+        }
+        self.next_slot = (slot + 1) % self.capacity;
+        slot
+    }
+}
+
+
+pub struct UniformReplayBuffer<S>
+where
+    S: Environment
+{
+    ring: Ring<S>,
+    rng: StdRng,
+}
+
+impl<S> UniformReplayBuffer<S>
+where
+    S: Environment
+{
+    pub fn new(capacity: usize, seed: u64) -> Self {
+        UniformReplayBuffer { ring: Ring::new(capacity), rng: StdRng::seed_from_u64(seed) }
+    }
+}
+
+impl<S> ReplayBuffer<S> for UniformReplayBuffer<S>
+where
+    S: Environment
+{
+    fn push(&mut self, transition: Transition<S>) {
+        self.ring.push(transition);
+    }
+
+    fn len(&self) -> usize {
+        self.ring.transitions.len()
+    }
+
+    fn get(&self, index: usize) -> &Transition<S> {
+        &self.ring.transitions[index]
+    }
+
+    // Samples with replacement.
+    fn sample(&mut self, batch_size: usize) -> Vec<(usize, f64)> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        (0..batch_size).map(|_| (self.rng.gen_range(0..self.len()), 1.0)).collect()
+    }
+}
+
+
+// A binary tree whose leaves are priorities and whose internal nodes are
+// the sums of their children, so that sampling in proportion to priority
+// and updating a priority both take O(log n).
+pub struct SumTree {
+    leaves: usize,
+    /*
+    * nodes[1] is the root, the children of node i are 2i and 2i + 1, and
+    * leaf j is nodes[leaves + j]. nodes[0] is unused.
+    */
+    nodes: Vec<f64>,
+}
+
+impl SumTree {
+    pub fn new(capacity: usize) -> SumTree {
+        let leaves = capacity.next_power_of_two();
+        SumTree { leaves, nodes: vec![0.0; 2 * leaves] }
+    }
+
+    pub fn total(&self) -> f64 {
+        self.nodes[1]
+    }
+
+    pub fn get(&self, index: usize) -> f64 {
+        self.nodes[self.leaves + index]
+    }
+
+    pub fn update(&mut self, index: usize, priority: f64) {
+        let mut node = self.leaves + index;
+        self.nodes[node] = priority;
+        while node > 1 {
+            node /= 2;
+            self.nodes[node] = self.nodes[2 * node] + self.nodes[2 * node + 1];
+        }
+    }
+
+    // The leaf where the running total of priorities passes value, for
+    // value in [0, total()).
+    pub fn find(&self, mut value: f64) -> usize {
+        let mut node = 1;
+        while node < self.leaves {
+            let left = 2 * node;
+            if value < self.nodes[left] || self.nodes[left + 1] == 0.0 {
+                node = left;
+            } else {
+                value -= self.nodes[left];
+                node = left + 1;
+            }
+        }
+        node - self.leaves
+    }
+}
+
+
+// Proportional prioritised experience replay, Schaul et al. (2015):
+// https://arxiv.org/abs/1511.05952
+// Transitions are sampled with probability proportional to
+// (|TD error| + epsilon)^alpha, and updates are weighted by
+// (N * P(i))^-beta, normalised by the largest weight, to correct the bias.
+pub struct PrioritizedReplayBuffer<S>
+where
+    S: Environment
+{
+    ring: Ring<S>,
+    priorities: SumTree,
+    pub alpha: f64,
+    pub beta: f64,
+    pub epsilon: f64,
+    // New transitions get the largest priority seen so far so that each is
+    // sampled at least once
+    max_priority: f64,
+    rng: StdRng,
+}
+
+impl<S> PrioritizedReplayBuffer<S>
+where
+    S: Environment
+{
+    pub fn new(capacity: usize, alpha: f64, beta: f64, seed: u64) -> Self {
+        PrioritizedReplayBuffer {
+            ring: Ring::new(capacity),
+            priorities: SumTree::new(capacity),
+            alpha,
+            beta,
+            epsilon: 1e-3,
+            max_priority: 1.0,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl<S> ReplayBuffer<S> for PrioritizedReplayBuffer<S>
+where
+    S: Environment
+{
+    fn push(&mut self, transition: Transition<S>) {
+        let slot = self.ring.push(transition);
+        self.priorities.update(slot, self.max_priority);
+    }
+
+    fn len(&self) -> usize {
+        self.ring.transitions.len()
+    }
+
+    fn get(&self, index: usize) -> &Transition<S> {
+        &self.ring.transitions[index]
+    }
+
+    fn sample(&mut self, batch_size: usize) -> Vec<(usize, f64)> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        let total = self.priorities.total();
+        let len = self.len() as f64;
+        let indices: Vec<usize> = (0..batch_size)
+            .map(|_| self.priorities.find(self.rng.gen::<f64>() * total))
+            .collect();
+        let weights: Vec<f64> = indices.iter()
+            .map(|index| (len * self.priorities.get(*index) / total).powf(-self.beta))
+            .collect();
+        let max_weight = weights.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        indices.into_iter().zip(weights).map(|(index, weight)| (index, weight / max_weight)).collect()
+    }
+
+    fn update_priorities(&mut self, indices: &[usize], td_errors: &[f64]) {
+        for (index, td_error) in indices.iter().zip(td_errors) {
+            let priority = (td_error.abs() + self.epsilon).powf(self.alpha);
+            self.max_priority = self.max_priority.max(priority);
+            self.priorities.update(*index, priority);
+        }
+    }
+}
+
+
+// Turns one-step transitions into n-step ones, whose reward is the
+// discounted sum of the next n rewards and whose next state is n steps on,
+// section 7.1 of Sutton and Barto. Steps near the end of an episode become
+// shorter transitions ending at the terminal state.
+pub struct NStepBuffer<S>
+where
+    S: Environment
+{
+    pub n: usize,
+    pub gamma: f64,
+    pending: VecDeque<Transition<S>>,
+}
+
+impl<S> NStepBuffer<S>
+where
+    S: Environment
+{
+    pub fn new(n: usize, gamma: f64) -> Self {
+        if n == 0 {
+            panic!("n-step transitions need at least one step");
+        }
+        NStepBuffer { n, gamma, pending: VecDeque::new() }
+    }
+
+    // Adds the latest one-step transition of the episode and returns the
+    // n-step transitions that are now complete.
+    pub fn push(&mut self, transition: Transition<S>) -> Vec<Transition<S>> {
+        let done = transition.done;
+        self.pending.push_back(transition);
+        let mut completed = Vec::new();
+        if self.pending.len() >= self.n {
+            completed.push(self.combine_front());
+            self.pending.pop_front();
+        }
+        if done {
+            while !self.pending.is_empty() {
+                completed.push(self.combine_front());
+                self.pending.pop_front();
+            }
+        }
+        completed
+    }
+
+    // One transition from the oldest pending state to the newest next state.
+    // Where players alternate, the opponent's steps count by the reward they
+    // give the first player.
+    fn combine_front(&self) -> Transition<S> {
+        let first = &self.pending[0];
+        let last = &self.pending[self.pending.len() - 1];
+        let reward = self.pending.iter()
+            .enumerate()
+            .map(|(step, transition)| {
+                let reward = if S::alternates_players() && step % 2 == 1 {
+                    S::get_opponent_reward(&transition.state, &transition.action, &transition.next_state)
+                } else {
+                    transition.reward
+                };
+                self.gamma.powi(step as i32) * reward
+            })
+            .sum();
+        Transition {
+            state: first.state.clone(),
+            action: first.action.clone(),
+            reward,
+            next_state: last.next_state.clone(),
+            done: last.done,
+            steps: self.pending.len() as u32,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::classic_control::mountain_car::{MountainCar, MountainCarAction};
+    use crate::nim::{NimMove, NimState, SubtractionGame};
+    use crate::reinforcement_learning::generic_reinforcement_learner::Environment;
+
+    use super::{NStepBuffer, PrioritizedReplayBuffer, ReplayBuffer, SumTree, Transition, UniformReplayBuffer};
+
+    fn car_transition(position: f64) -> Transition<MountainCar> {
+        let state = MountainCar { position, velocity: 0.0, steps: 0 };
+        let next_state = state.next_state(&MountainCarAction::Coast);
+        Transition::new(state, MountainCarAction::Coast, -1.0, next_state)
+    }
+
+    #[test]
+    fn uniform_buffer_overwrites_the_oldest_transitions() {
+        let mut buffer = UniformReplayBuffer::new(3, 0);
+        for position in [-0.5, -0.4, -0.3, -0.2] {
+            buffer.push(car_transition(position));
+        }
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.get(0).state.position, -0.2);
+        assert_eq!(buffer.get(1).state.position, -0.4);
+    }
+
+    #[test]
+    fn buffers_with_the_same_seed_sample_the_same_transitions() {
+        let mut first = UniformReplayBuffer::new(10, 7);
+        let mut second = UniformReplayBuffer::new(10, 7);
+        for position in 0..10 {
+            first.push(car_transition(position as f64 / -20.0));
+            second.push(car_transition(position as f64 / -20.0));
+        }
+        assert_eq!(first.sample(5), second.sample(5));
+    }
+
+    #[test]
+    fn sum_tree_samples_in_proportion_to_priority() {
+        let mut tree = SumTree::new(3);
+        tree.update(0, 1.0);
+        tree.update(1, 0.0);
+        tree.update(2, 3.0);
+        assert_eq!(tree.total(), 4.0);
+        assert_eq!(tree.find(0.5), 0);
+        assert_eq!(tree.find(1.0), 2);
+        assert_eq!(tree.find(3.99), 2);
+    }
+
+    #[test]
+    fn prioritized_buffer_prefers_large_td_errors() {
+        let mut buffer = PrioritizedReplayBuffer::new(4, 1.0, 1.0, 0);
+        for position in [-0.5, -0.4, -0.3, -0.2] {
+            buffer.push(car_transition(position));
+        }
+        buffer.update_priorities(&[0, 1, 2, 3], &[1.0, 1.0, 1.0, 10.0]);
+        let sample = buffer.sample(1000);
+        let surprising = sample.iter().filter(|(index, _)| *index == 3).count();
+        assert!(surprising > 700);
+        // Rarely sampled transitions get the full weight
+        for (index, weight) in sample {
+            if index == 3 { assert!(weight < 1.0) } else { assert_eq!(weight, 1.0) }
+        }
+    }
+
+    #[test]
+    fn n_step_buffer_sums_discounted_rewards() {
+        let mut buffer = NStepBuffer::new(3, 0.5);
+        let mut state = MountainCar { position: -0.5, velocity: 0.0, steps: 0 };
+        let mut completed = Vec::new();
+        for _ in 0..4 {
+            let next_state = state.next_state(&MountainCarAction::Right);
+            completed.extend(buffer.push(Transition::new(state, MountainCarAction::Right, -1.0, next_state)));
+            state = next_state;
+        }
+        assert_eq!(completed.len(), 2);
+        assert_eq!(completed[0].reward, -1.75);
+        assert_eq!(completed[0].steps, 3);
+        assert_eq!(completed[1].state.steps, 1);
+        assert_eq!(completed[1].next_state.steps, 4);
+        assert_eq!(completed[0].bootstrap_discount(0.5), 0.125);
+    }
+
+    #[test]
+    fn n_step_buffer_flushes_at_the_end_of_the_episode() {
+        // Taking the last three counters wins, and the opponent's reward
+        // counts against the player who moved first.
+        let mut buffer = NStepBuffer::new(3, 1.0);
+        let state = NimState::<SubtractionGame>::new(vec![4]);
+        let first = state.next_state(&NimMove::new(0, 1));
+        let second = first.next_state(&NimMove::new(0, 3));
+        let mut completed = buffer.push(Transition::new(state.clone(), NimMove::new(0, 1), 0.0, first.clone()));
+        assert!(completed.is_empty());
+        completed.extend(buffer.push(Transition::new(first, NimMove::new(0, 3), 1.0, second)));
+        assert_eq!(completed.len(), 2);
+        assert!(completed.iter().all(|transition| transition.done));
+        assert_eq!(completed[0].reward, -1.0);
+        assert_eq!(completed[1].reward, 1.0);
+        assert_eq!(completed[0].bootstrap_discount(1.0), 0.0);
+    }
+}
@@ -0,0 +1,338 @@
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+use rand::Rng;
+
+use super::generic_reinforcement_learner::{Environment, IndexedActions};
+
+// Potential-based reward shaping for any environment (Ng, Harada and
+// Russell, 1999). Wrapping states in Shaped adds
+//
+//   F(s, s') = gamma * phi(s') - phi(s)
+//
+// to the reward of every step, for a potential phi chosen by the caller.
+// Summed over an episode these terms telescope to -phi of the first state,
+// so every action value is shifted by -phi(s) and the optimal policy stays
+// the same. In games where players alternate, phi is from the view of the
+// player to move, so the next state's potential is the opponent's and is
+// negated: F(s, s') = -gamma * phi(s') - phi(s), and the opponent's reward
+// for the move is shaped by -F. Terminal states always have a potential of
+// zero.
+//
+// Results come from the wrapped environment, so evaluation is unaffected by
+// the shaping, and MetricsRecorder::with_unshaped_rewards can be given
+// Shaped's unshaped rewards to report returns without it.
+
+// A potential function over the states of S, with the discount it assumes,
+// which must match the learner's for the shaping to leave the optimal policy
+// unchanged. Shaped states report it as their shaping discount, and the
+// Q-learner panics if its own gamma differs.
+pub trait Potential<S> {
+    fn potential(state: &S) -> f64;
+    fn discount() -> f64;
+}
+
+
+pub struct Shaped<S, P>
+where
+    S: Environment,
+    P: Potential<S>
+{
+    inner: S,
+    potential: PhantomData<P>,
+}
+
+impl<S, P> Shaped<S, P>
+where
+    S: Environment,
+    P: Potential<S>
+{
+    pub fn new(inner: S) -> Self {
+        Shaped { inner, potential: PhantomData }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    fn potential(state: &S) -> f64 {
+        if state.is_terminal() { 0.0 } else { P::potential(state) }
+    }
+
+    // The shaping reward F(s, s') for the player moving from state to next_state.
+    pub fn shaping(state: &S, next_state: &S) -> f64 {
+        let next_potential = if S::alternates_players() { -Self::potential(next_state) } else { Self::potential(next_state) };
+        P::discount() * next_potential - Self::potential(state)
+    }
+
+    // The wrapped environment's own rewards, for metrics to report the
+    // unshaped return alongside the shaped one.
+    pub fn unshaped_reward(state: &Self, action: &S::A, next_state: &Self) -> f64 {
+        S::get_reward(&state.inner, action, &next_state.inner)
+    }
+
+    pub fn unshaped_opponent_reward(state: &Self, action: &S::A, next_state: &Self) -> f64 {
+        S::get_opponent_reward(&state.inner, action, &next_state.inner)
+    }
+}
+
+// Shaped states are compared, hashed and shown by the wrapped state alone.
+impl<S, P> Clone for Shaped<S, P>
+where
+    S: Environment,
+    P: Potential<S>
+{
+    fn clone(&self) -> Self {
+        Self::new(self.inner.clone())
+    }
+}
+
+impl<S, P> PartialEq for Shaped<S, P>
+where
+    S: Environment + PartialEq,
+    P: Potential<S>
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl<S, P> Eq for Shaped<S, P>
+where
+    S: Environment + Eq,
+    P: Potential<S>
+{
+}
+
+impl<S, P> Hash for Shaped<S, P>
+where
+    S: Environment + Hash,
+    P: Potential<S>
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.inner.hash(state);
+    }
+}
+
+impl<S, P> fmt::Display for Shaped<S, P>
+where
+    S: Environment,
+    P: Potential<S>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.inner)
+    }
+}
+
+impl<S, P> Environment for Shaped<S, P>
+where
+    S: Environment,
+    P: Potential<S>
+{
+    type A = S::A;
+
+    fn initial_state() -> Self {
+        Self::new(S::initial_state())
+    }
+
+    fn next_state(&self, action: &Self::A) -> Self {
+        Self::new(self.inner.next_state(action))
+    }
+
+    fn sample_initial_state<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        Self::new(S::sample_initial_state(rng))
+    }
+
+    fn sample_next_state<R: Rng + ?Sized>(&self, action: &Self::A, rng: &mut R) -> Self {
+        Self::new(self.inner.sample_next_state(action, rng))
+    }
+
+    fn alternates_players() -> bool {
+        S::alternates_players()
+    }
+
+    fn shaping_discount() -> Option<f64> {
+        Some(P::discount())
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.inner.is_terminal()
+    }
+
+    fn available_actions(&self) -> Vec<Self::A> {
+        self.inner.available_actions()
+    }
+
+    fn get_reward(state: &Self, action: &Self::A, next_state: &Self) -> f64 {
+        S::get_reward(&state.inner, action, &next_state.inner) + Self::shaping(&state.inner, &next_state.inner)
+    }
+
+    // What the mover gains from shaping the opponent loses, which keeps the
+    // two-step returns of league training telescoping too.
+    fn get_opponent_reward(state: &Self, action: &Self::A, next_state: &Self) -> f64 {
+        S::get_opponent_reward(&state.inner, action, &next_state.inner) - Self::shaping(&state.inner, &next_state.inner)
+    }
+
+    fn get_result(state: &Self, action: &Self::A, next_state: &Self) -> f64 {
+        S::get_result(&state.inner, action, &next_state.inner)
+    }
+}
+
+
+impl<S, P> IndexedActions for Shaped<S, P>
+where
+    S: IndexedActions,
+    P: Potential<S>
+{
+    fn num_actions() -> usize {
+        S::num_actions()
+    }
+
+    fn action_index(action: &Self::A) -> usize {
+        S::action_index(action)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::classic_control::mountain_car::{MountainCar, MountainCarAction};
+    use crate::nim::{NimMove, NimState, StandardNim};
+    use crate::reinforcement_learning::epsilon_greedy_policy::EpsilonGreedyPolicy;
+    use crate::reinforcement_learning::generic_reinforcement_learner::{Environment, ReinforcementLearner};
+    use crate::reinforcement_learning::metrics::MetricsRecorder;
+    use crate::reinforcement_learning::q_learning_learner::QLearner;
+
+    use super::{Potential, Shaped};
+
+    type Nim = NimState<StandardNim>;
+
+    // Positions with a non-zero nim-sum are won for the player to move.
+    struct NimSum;
+
+    impl Potential<Nim> for NimSum {
+        fn potential(state: &Nim) -> f64 {
+            if state.heaps().iter().fold(0, |sum, heap| sum ^ heap) != 0 { 0.5 } else { -0.5 }
+        }
+
+        fn discount() -> f64 {
+            0.9
+        }
+    }
+
+    struct Height;
+
+    impl Potential<MountainCar> for Height {
+        fn potential(state: &MountainCar) -> f64 {
+            (3.0 * state.position).sin()
+        }
+
+        fn discount() -> f64 {
+            0.99
+        }
+    }
+
+    fn positions() -> Vec<Nim> {
+        let mut seen = HashSet::new();
+        let mut frontier = vec![Nim::initial_state()];
+        while let Some(state) = frontier.pop() {
+            for action in state.available_actions() {
+                let next_state = state.next_state(&action);
+                if !next_state.is_terminal() && seen.insert(next_state.clone()) {
+                    frontier.push(next_state);
+                }
+            }
+        }
+        seen.insert(Nim::initial_state());
+        seen.into_iter().collect()
+    }
+
+    #[test]
+    fn shaping_adds_the_change_in_potential() {
+        let state = MountainCar { position: -0.5, velocity: 0.01, steps: 0 };
+        let next_state = state.next_state(&MountainCarAction::Right);
+        let shaped = Shaped::<MountainCar, Height>::new(state);
+        let shaped_next = shaped.next_state(&MountainCarAction::Right);
+        let reward = Shaped::get_reward(&shaped, &MountainCarAction::Right, &shaped_next);
+        let expected = MountainCar::get_reward(&state, &MountainCarAction::Right, &next_state)
+            + 0.99 * Height::potential(&next_state) - Height::potential(&state);
+        assert!((reward - expected).abs() < 1e-12);
+        assert_eq!(
+            Shaped::unshaped_reward(&shaped, &MountainCarAction::Right, &shaped_next),
+            MountainCar::get_reward(&state, &MountainCarAction::Right, &next_state)
+        );
+
+        // The potential of the next position is the opponent's, and that of a
+        // finished game is zero
+        let state = Shaped::<Nim, NimSum>::new(NimState::new(vec![1, 2]));
+        let action = NimMove::new(1, 1);
+        let next_state = state.next_state(&action);
+        assert!((Shaped::get_reward(&state, &action, &next_state) - (-0.9 * -0.5 - 0.5)).abs() < 1e-12);
+        assert!((Shaped::get_opponent_reward(&state, &action, &next_state) - (0.9 * -0.5 + 0.5)).abs() < 1e-12);
+        let last = Shaped::<Nim, NimSum>::new(NimState::new(vec![0, 1]));
+        let action = NimMove::new(1, 1);
+        let finished = last.next_state(&action);
+        assert!((Shaped::get_reward(&last, &action, &finished) - (1.0 - 0.5)).abs() < 1e-12);
+        assert_eq!(Shaped::get_result(&last, &action, &finished), 1.0);
+        assert_eq!(Shaped::unshaped_reward(&last, &action, &finished), 1.0);
+    }
+
+    #[test]
+    fn shaping_leaves_the_optimal_policy_unchanged() {
+        // Sweeping every position with a learning rate of one converges to
+        // the exact values, which shaping shifts by minus the potential
+        let mut plain = QLearner::<Nim>::new(1.0, 0.9);
+        let mut shaped = QLearner::<Shaped<Nim, NimSum>>::new(1.0, 0.9);
+        let positions = positions();
+        for _ in 0..12 {
+            for state in &positions {
+                let shaped_state = Shaped::new(state.clone());
+                for action in state.available_actions() {
+                    let next_state = state.next_state(&action);
+                    plain.update_action_value(state, &action, &next_state, Nim::get_reward(state, &action, &next_state));
+                    let shaped_next = shaped_state.next_state(&action);
+                    let reward = Shaped::get_reward(&shaped_state, &action, &shaped_next);
+                    shaped.update_action_value(&shaped_state, &action, &shaped_next, reward);
+                }
+            }
+        }
+        for state in &positions {
+            let shaped_state = Shaped::new(state.clone());
+            for action in state.available_actions() {
+                let shifted = shaped.get_action_value(&shaped_state, &action) + NimSum::potential(state);
+                assert!((plain.get_action_value(state, &action) - shifted).abs() < 1e-9);
+            }
+            let best = plain.get_best_action(state);
+            assert_eq!(
+                plain.get_action_value(state, &best),
+                plain.get_action_value(state, &shaped.get_best_action(&shaped_state))
+            );
+        }
+    }
+
+    #[test]
+    fn metrics_report_shaped_and_unshaped_returns() {
+        let mut q_learner = QLearner::<Shaped<Nim, NimSum>>::new(0.5, 0.9);
+        let mut recorder = MetricsRecorder::new(None).with_unshaped_rewards(Shaped::unshaped_reward, Shaped::unshaped_opponent_reward);
+        q_learner.q_learning_with_hooks(&EpsilonGreedyPolicy::new(0.5), 200, &mut [&mut recorder]);
+        // Only taking the last object is rewarded, and the return is the
+        // first player's
+        let log = recorder.log;
+        assert!(log.episodes.iter().all(|metrics| metrics.unshaped_return == if metrics.length % 2 == 1 { 1.0 } else { -1.0 }));
+        assert!(log.episodes.iter().any(|metrics| metrics.episode_return != metrics.unshaped_return));
+
+        // Without being told how, the recorder reports the shaped return
+        let log = q_learner.q_learning_with_metrics(&EpsilonGreedyPolicy::new(0.5), 200, None);
+        assert!(log.episodes.iter().all(|metrics| metrics.episode_return == metrics.unshaped_return));
+    }
+
+    #[test]
+    #[should_panic(expected = "shaped for a discount of 0.9")]
+    fn learning_with_another_discount_panics() {
+        let mut q_learner = QLearner::<Shaped<Nim, NimSum>>::new(0.5, 0.99);
+        q_learner.q_learning(&EpsilonGreedyPolicy::new(0.5), 1);
+    }
+}
@@ -0,0 +1,72 @@
+use std::marker::PhantomData;
+
+use rand::RngCore;
+
+use crate::utils::{sample_index, softmax};
+
+use super::generic_reinforcement_learner::{Environment, Policy};
+
+// Chooses actions with probability proportional to exp(value / temperature),
+// section 13.1 of Sutton and Barto. With action values this is Boltzmann
+// exploration, and with action preferences it is the policy that the
+// policy-gradient learners parameterise.
+pub struct SoftmaxPolicy<S>
+where
+    S: Environment
+{
+    // Higher temperatures make the choice more uniform
+    pub temperature: f64,
+    state: PhantomData<S>,
+}
+
+
+impl<S> SoftmaxPolicy<S>
+where
+    S: Environment
+{
+    pub fn new(temperature: f64) -> Self {
+        Self {
+            temperature,
+            state: PhantomData,
+        }
+    }
+}
+
+
+impl<S> Policy<S> for SoftmaxPolicy<S>
+where
+    S: Environment
+{
+    fn sample_action(&self, actions_and_values: &mut Vec<(S::A, f64)>, rng: &mut dyn RngCore) -> S::A {
+        if actions_and_values.is_empty() {
+            panic!("No moves available");
+        }
+        let probabilities = self.action_probabilities(actions_and_values);
+        let chosen_action = sample_index(&probabilities, rng);
+        actions_and_values.remove(chosen_action).0
+    }
+
+    fn action_probabilities(&self, actions_and_values: &[(S::A, f64)]) -> Vec<f64> {
+        let preferences: Vec<f64> = actions_and_values.iter().map(|(_, value)| value / self.temperature).collect();
+        softmax(&preferences)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::nim::{NimMove, NimState, StandardNim};
+    use crate::reinforcement_learning::generic_reinforcement_learner::Policy;
+
+    use super::SoftmaxPolicy;
+
+    #[test]
+    fn softmax_temperature_controls_greediness() {
+        let values = vec![(NimMove::new(0, 1), 1.0), (NimMove::new(0, 2), 0.0)];
+        let warm = SoftmaxPolicy::<NimState<StandardNim>>::new(1.0).action_probabilities(&values);
+        let cold = SoftmaxPolicy::<NimState<StandardNim>>::new(0.1).action_probabilities(&values);
+        assert!((warm[0] - 1.0 / (1.0 + (-1.0f64).exp())).abs() < 1e-12);
+        assert!((warm[0] + warm[1] - 1.0).abs() < 1e-12);
+        assert!(cold[0] > 0.9999);
+    }
+}
@@ -0,0 +1,248 @@
+use core::fmt;
+
+use rand::Rng;
+
+use super::generic_reinforcement_learner::{Environment, ReinforcementLearner, Symmetric};
+
+// Wraps a state so that it is always kept in its canonical form, so that
+// tabular learners store a single entry for every group of equivalent
+// positions. Actions are those of the canonical state, so a learner trained
+// on Canonical states needs a SymmetricLearner to play the original game.
+// Positions that are symmetric themselves, such as the empty board, only
+// offer one of each group of equivalent actions.
+
+#[derive(Clone)]
+#[derive(PartialEq, Eq, Hash)]
+pub struct Canonical<S>
+where
+    S: Symmetric + Eq
+{
+    state: S,
+}
+
+impl<S> Canonical<S>
+where
+    S: Symmetric + Eq
+{
+    pub fn new(state: &S) -> Self {
+        Canonical { state: state.transform(state.canonical_symmetry()) }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.state
+    }
+
+    // The symmetries that leave the state as it is.
+    fn stabiliser(&self) -> Vec<usize> {
+        (0..S::num_symmetries())
+            .filter(|symmetry| self.state.transform(*symmetry) == self.state)
+            .collect()
+    }
+
+    // The action equivalent to the given one that the state offers.
+    pub fn representative(&self, action: &S::A) -> S::A {
+        let equivalents: Vec<S::A> = self.stabiliser()
+            .into_iter()
+            .map(|symmetry| S::transform_action(action, symmetry))
+            .collect();
+        self.state.available_actions()
+            .into_iter()
+            .find(|candidate| equivalents.contains(candidate))
+            .unwrap_or_else(|| action.clone())
+    }
+}
+
+impl<S> fmt::Display for Canonical<S>
+where
+    S: Symmetric + Eq
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.state)
+    }
+}
+
+impl<S> Environment for Canonical<S>
+where
+    S: Symmetric + Eq
+{
+    type A = S::A;
+
+    fn initial_state() -> Self {
+        Self::new(&S::initial_state())
+    }
+
+    fn next_state(&self, action: &Self::A) -> Self {
+        Self::new(&self.state.next_state(action))
+    }
+
+    fn sample_initial_state<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        Self::new(&S::sample_initial_state(rng))
+    }
+
+    fn sample_next_state<R: Rng + ?Sized>(&self, action: &Self::A, rng: &mut R) -> Self {
+        Self::new(&self.state.sample_next_state(action, rng))
+    }
+
+    fn alternates_players() -> bool {
+        S::alternates_players()
+    }
+
+    fn shaping_discount() -> Option<f64> {
+        S::shaping_discount()
+    }
+
+    fn is_terminal(&self) -> bool {
+        self.state.is_terminal()
+    }
+
+    fn available_actions(&self) -> Vec<Self::A> {
+        let actions = self.state.available_actions();
+        let stabiliser = self.stabiliser();
+        if stabiliser.len() == 1 {
+            return actions;
+        }
+        // Keep the first action of each group of equivalent ones
+        let mut representatives: Vec<Self::A> = Vec::new();
+        for action in actions {
+            if !stabiliser.iter().any(|symmetry| representatives.contains(&S::transform_action(&action, *symmetry))) {
+                representatives.push(action);
+            }
+        }
+        representatives
+    }
+
+    fn get_reward(state: &Self, action: &Self::A, next_state: &Self) -> f64 {
+        S::get_reward(&state.state, action, &next_state.state)
+    }
+
+    fn get_opponent_reward(state: &Self, action: &Self::A, next_state: &Self) -> f64 {
+        S::get_opponent_reward(&state.state, action, &next_state.state)
+    }
+
+    fn get_result(state: &Self, action: &Self::A, next_state: &Self) -> f64 {
+        S::get_result(&state.state, action, &next_state.state)
+    }
+}
+
+
+// Plays the original game with a learner trained on Canonical states, by
+// looking positions up in canonical form and mapping actions to and from it.
+pub struct SymmetricLearner<L> {
+    pub learner: L,
+}
+
+impl<L> SymmetricLearner<L> {
+    pub fn new(learner: L) -> Self {
+        SymmetricLearner { learner }
+    }
+}
+
+impl<L> SymmetricLearner<L> {
+    fn canonical_action<S: Symmetric + Eq>(state: &S, action: &S::A) -> (Canonical<S>, S::A) {
+        let canonical = Canonical::new(state);
+        let canonical_action = canonical.representative(&S::transform_action(action, state.canonical_symmetry()));
+        (canonical, canonical_action)
+    }
+}
+
+impl<S, L> ReinforcementLearner<S> for SymmetricLearner<L>
+where
+    S: Symmetric + Eq,
+    L: ReinforcementLearner<Canonical<S>>
+{
+    fn get_action_value(&self, state: &S, action: &S::A) -> f64 {
+        let (canonical, canonical_action) = Self::canonical_action(state, action);
+        self.learner.get_action_value(&canonical, &canonical_action)
+    }
+
+    fn get_action_values(&self, state: &S) -> Vec<(S::A, f64)> {
+        state.available_actions()
+            .into_iter()
+            .map(|action| {
+                let value = self.get_action_value(state, &action);
+                (action, value)
+            })
+            .collect()
+    }
+
+    fn update_action_value(&mut self, state: &S, action: &S::A, next_state: &S, reward: f64) {
+        let (canonical, canonical_action) = Self::canonical_action(state, action);
+        self.learner.update_action_value(&canonical, &canonical_action, &Canonical::new(next_state), reward);
+    }
+
+    fn get_state_value(&self, state: &S) -> f64 {
+        self.learner.get_state_value(&Canonical::new(state))
+    }
+
+    fn update_state_value(&mut self, state: &S, value: f64) {
+        self.learner.update_state_value(&Canonical::new(state), value);
+    }
+
+    fn get_best_action(&self, state: &S) -> S::A {
+        let symmetry = state.canonical_symmetry();
+        let canonical_action = self.learner.get_best_action(&Canonical::new(state));
+        S::transform_action(&canonical_action, S::inverse_symmetry(symmetry))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::reinforcement_learning::epsilon_greedy_policy::EpsilonGreedyPolicy;
+    use crate::reinforcement_learning::generic_reinforcement_learner::{Environment, ReinforcementLearner, State, Symmetric};
+    use crate::reinforcement_learning::q_learning_learner::QLearner;
+    use crate::tictactoe::TicTacToeBoard;
+
+    use super::{Canonical, SymmetricLearner};
+
+    fn reachable_states<S: State>() -> HashSet<S> {
+        let mut states = HashSet::new();
+        let mut frontier = vec![S::initial_state()];
+        while let Some(state) = frontier.pop() {
+            for action in state.available_actions() {
+                let next_state = state.next_state(&action);
+                if !next_state.is_terminal() && states.insert(next_state.clone()) {
+                    frontier.push(next_state);
+                }
+            }
+        }
+        states
+    }
+
+    #[test]
+    fn canonical_tictactoe_has_fewer_positions() {
+        // Positions after the first move, up to symmetry: corner, edge and centre
+        let initial_state = TicTacToeBoard::initial_state();
+        let after_first_move: HashSet<Canonical<TicTacToeBoard>> = initial_state
+            .available_actions()
+            .iter()
+            .map(|action| Canonical::new(&initial_state.next_state(action)))
+            .collect();
+        assert_eq!(after_first_move.len(), 3);
+        assert_eq!(Canonical::new(&initial_state).available_actions().len(), 3);
+
+        let raw = reachable_states::<TicTacToeBoard>().len();
+        let canonical = reachable_states::<Canonical<TicTacToeBoard>>().len();
+        assert!(canonical * 6 < raw, "{canonical} canonical positions out of {raw}");
+    }
+
+    #[test]
+    fn symmetric_positions_share_values() {
+        let mut q_learner = QLearner::<Canonical<TicTacToeBoard>>::new(0.5, 0.9);
+        q_learner.q_learning(&EpsilonGreedyPolicy::new(0.5), 200);
+        let learner = SymmetricLearner::new(q_learner);
+
+        for state in reachable_states::<TicTacToeBoard>() {
+            for symmetry in 0..TicTacToeBoard::num_symmetries() {
+                let transformed = state.transform(symmetry);
+                for action in state.available_actions() {
+                    let transformed_action = TicTacToeBoard::transform_action(&action, symmetry);
+                    assert_eq!(learner.get_action_value(&state, &action), learner.get_action_value(&transformed, &transformed_action));
+                }
+            }
+            assert!(state.is_valid_move(learner.get_best_action(&state)));
+        }
+    }
+}
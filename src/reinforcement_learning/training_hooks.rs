@@ -0,0 +1,210 @@
+use super::generic_reinforcement_learner::Environment;
+
+// Callbacks run by the training loop, so that checkpointing, metrics,
+// early stopping and logging can be added to training without changing
+// the loop itself. Every callback does nothing by default. L is the type of
+// learner being trained, which is passed to the hooks once each episode has
+// been learnt from.
+
+// What the training loop should do after a hook has run.
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+#[derive(PartialEq, Eq)]
+pub enum HookControl {
+    Continue,
+    Stop
+}
+
+
+// One step of an episode, after the learner has updated its values.
+pub struct Step<'a, S>
+where
+    S: Environment
+{
+    pub episode: u32,
+    pub state: &'a S,
+    pub action: &'a S::A,
+    pub reward: f64,
+    pub next_state: &'a S,
+    pub td_error: f64,
+    // Whether the action had the highest value, rather than being exploratory
+    pub greedy: bool,
+}
+
+
+pub trait TrainingHook<S, L>
+where
+    S: Environment
+{
+    fn on_episode_start(&mut self, _episode: u32, _state: &S) {
+    }
+
+    fn on_step(&mut self, _step: &Step<S>) {
+    }
+
+    // Returning HookControl::Stop ends training after this episode.
+    fn on_episode_end(&mut self, _episode: u32, _learner: &L) -> HookControl {
+        HookControl::Continue
+    }
+
+    // Called once training stops, whether it ran all its episodes or was
+    // stopped early, with the number of episodes completed.
+    fn on_training_end(&mut self, _num_episodes: u32, _learner: &L) {
+    }
+}
+
+
+// Stops training once the learner has converged, meaning that the mean
+// absolute TD error of an episode has stayed below the tolerance for
+// `patience` episodes in a row.
+pub struct EarlyStopping {
+    pub tolerance: f64,
+    pub patience: u32,
+    total_td_error: f64,
+    steps: u32,
+    quiet_episodes: u32,
+}
+
+impl EarlyStopping {
+    pub fn new(tolerance: f64, patience: u32) -> EarlyStopping {
+        EarlyStopping { tolerance, patience, total_td_error: 0.0, steps: 0, quiet_episodes: 0 }
+    }
+}
+
+impl<S, L> TrainingHook<S, L> for EarlyStopping
+where
+    S: Environment
+{
+    fn on_episode_start(&mut self, _episode: u32, _state: &S) {
+        self.total_td_error = 0.0;
+        self.steps = 0;
+    }
+
+    fn on_step(&mut self, step: &Step<S>) {
+        self.total_td_error += step.td_error.abs();
+        self.steps += 1;
+    }
+
+    fn on_episode_end(&mut self, _episode: u32, _learner: &L) -> HookControl {
+        let mean_td_error = if self.steps == 0 { 0.0 } else { self.total_td_error / self.steps as f64 };
+        if mean_td_error < self.tolerance {
+            self.quiet_episodes += 1;
+        } else {
+            self.quiet_episodes = 0;
+        }
+        if self.quiet_episodes >= self.patience { HookControl::Stop } else { HookControl::Continue }
+    }
+}
+
+
+// Hands the learner to `save` every `interval` episodes and once more when
+// training ends, for example to write its values to disk or to keep a copy.
+pub struct Checkpoint<F> {
+    pub interval: u32,
+    save: F,
+    last_saved: u32,
+}
+
+impl<F> Checkpoint<F> {
+    pub fn new(interval: u32, save: F) -> Checkpoint<F> {
+        Checkpoint { interval, save, last_saved: 0 }
+    }
+}
+
+impl<S, L, F> TrainingHook<S, L> for Checkpoint<F>
+where
+    S: Environment,
+    F: FnMut(u32, &L)
+{
+    fn on_episode_end(&mut self, episode: u32, learner: &L) -> HookControl {
+        if self.interval > 0 && episode % self.interval == 0 {
+            (self.save)(episode, learner);
+            self.last_saved = episode;
+        }
+        HookControl::Continue
+    }
+
+    fn on_training_end(&mut self, num_episodes: u32, learner: &L) {
+        if self.last_saved != num_episodes {
+            (self.save)(num_episodes, learner);
+            self.last_saved = num_episodes;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::nim::{NimState, StandardNim};
+    use crate::reinforcement_learning::epsilon_greedy_policy::EpsilonGreedyPolicy;
+    use crate::reinforcement_learning::generic_reinforcement_learner::Environment;
+    use crate::reinforcement_learning::q_learning_learner::QLearner;
+
+    use super::{Checkpoint, EarlyStopping, HookControl, Step, TrainingHook};
+
+    type Nim = NimState<StandardNim>;
+
+    fn new_q_learner() -> QLearner<Nim> {
+        QLearner::new(0.5, 0.9)
+    }
+
+    // Counts the callbacks and stops after a fixed number of episodes.
+    #[derive(Default)]
+    struct Counter {
+        episodes_started: u32,
+        steps: u32,
+        episodes_ended: u32,
+        training_ended_after: Option<u32>,
+        stop_after: u32,
+    }
+
+    impl<L> TrainingHook<Nim, L> for Counter {
+        fn on_episode_start(&mut self, _episode: u32, state: &Nim) {
+            assert!(*state == Nim::initial_state());
+            self.episodes_started += 1;
+        }
+
+        fn on_step(&mut self, step: &Step<Nim>) {
+            assert_eq!(step.episode, self.episodes_started);
+            self.steps += 1;
+        }
+
+        fn on_episode_end(&mut self, _episode: u32, _learner: &L) -> HookControl {
+            self.episodes_ended += 1;
+            if self.episodes_ended == self.stop_after { HookControl::Stop } else { HookControl::Continue }
+        }
+
+        fn on_training_end(&mut self, num_episodes: u32, _learner: &L) {
+            self.training_ended_after = Some(num_episodes);
+        }
+    }
+
+    #[test]
+    fn hooks_see_every_episode_and_can_stop_training() {
+        let mut counter = Counter { stop_after: 30, ..Counter::default() };
+        let episodes = new_q_learner().q_learning_with_hooks(&EpsilonGreedyPolicy::new(0.1), 100, &mut [&mut counter]);
+        assert_eq!(episodes, 30);
+        assert_eq!((counter.episodes_started, counter.episodes_ended), (30, 30));
+        assert_eq!(counter.training_ended_after, Some(30));
+        // Every game of 3, 4, 5 Nim takes at least three moves
+        assert!(counter.steps >= 90);
+    }
+
+    #[test]
+    fn early_stopping_waits_for_converged_values() {
+        let mut q_learner = new_q_learner();
+        let mut early_stopping = EarlyStopping::new(1e-6, 20);
+        let episodes = q_learner.q_learning_with_hooks(&EpsilonGreedyPolicy::new(0.0), 10000, &mut [&mut early_stopping]);
+        assert!(episodes > 20 && episodes < 10000);
+    }
+
+    #[test]
+    fn checkpoints_are_saved_on_schedule_and_at_the_end() {
+        let mut saved = Vec::new();
+        let mut checkpoint = Checkpoint::new(40, |episode, learner: &QLearner<Nim>| saved.push((episode, learner.q_values.len())));
+        new_q_learner().q_learning_with_hooks(&EpsilonGreedyPolicy::new(0.1), 100, &mut [&mut checkpoint]);
+        let episodes: Vec<u32> = saved.iter().map(|(episode, _)| *episode).collect();
+        assert_eq!(episodes, vec![40, 80, 100]);
+        assert!(saved.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+    }
+}
@@ -0,0 +1,356 @@
+use core::fmt;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::evaluation::{evaluate_against_references, EvaluationResults, LearnerAgent};
+use crate::reinforcement_learning::epsilon_greedy_policy::EpsilonGreedyPolicy;
+use crate::reinforcement_learning::generic_reinforcement_learner::State;
+use crate::reinforcement_learning::league::{League, OpponentSampling};
+use crate::reinforcement_learning::q_learning_learner::QLearner;
+use crate::reinforcement_learning::replay_buffer::UniformReplayBuffer;
+
+// Hyperparameter sweeps: trains a learner for every combination of the
+// given values with several seeds, on as many threads as asked for, plays
+// each trained learner against the reference opponents and ranks the
+// configurations by their mean score over the seeds.
+
+// How the Q-learner is trained.
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum LearnerKind {
+    // One-step Q-learning from every move as it is played
+    QLearning,
+    // Q-learning from minibatches of remembered moves
+    Replay,
+    // Q-learning against snapshots of itself
+    League,
+}
+
+impl LearnerKind {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "q-learning" => Ok(LearnerKind::QLearning),
+            "replay" => Ok(LearnerKind::Replay),
+            "league" => Ok(LearnerKind::League),
+            _ => Err(format!("Unknown learner {name}, expected one of: q-learning, replay, league")),
+        }
+    }
+}
+
+impl fmt::Display for LearnerKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LearnerKind::QLearning => write!(f, "q-learning"),
+            LearnerKind::Replay => write!(f, "replay"),
+            LearnerKind::League => write!(f, "league"),
+        }
+    }
+}
+
+
+#[derive(Clone, Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct SweepConfig {
+    pub learner: LearnerKind,
+    pub alpha: f64,
+    pub gamma: f64,
+    pub epsilon: f64,
+    pub num_episodes: u32,
+}
+
+impl SweepConfig {
+    // Trains a learner with this configuration, drawing every random choice
+    // from the seed, so that the same seed always gives the same learner.
+    pub fn train<S: State>(&self, seed: u64) -> QLearner<S> {
+        let mut q_learner = QLearner::new(self.alpha, self.gamma);
+        let policy = EpsilonGreedyPolicy::new(self.epsilon);
+        let mut rng = StdRng::seed_from_u64(seed);
+        match self.learner {
+            LearnerKind::QLearning => {
+                q_learner.q_learning_with_rng(&policy, self.num_episodes, &mut [], &mut rng);
+            },
+            LearnerKind::Replay => {
+                let mut buffer = UniformReplayBuffer::new(10000, seed);
+                q_learner.q_learning_with_replay_and_rng(&policy, self.num_episodes, &mut buffer, 1, 8, &mut rng);
+            },
+            LearnerKind::League => {
+                let mut league = League::new((self.num_episodes / 20).max(1), 10, OpponentSampling::Uniform, seed);
+                q_learner.league_self_play_with_rng(&policy, self.num_episodes, &mut league, &mut rng);
+            },
+        }
+        q_learner
+    }
+}
+
+impl fmt::Display for SweepConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f, "{} alpha={} gamma={} epsilon={} episodes={}",
+            self.learner, self.alpha, self.gamma, self.epsilon, self.num_episodes
+        )
+    }
+}
+
+
+pub struct Sweep {
+    pub learners: Vec<LearnerKind>,
+    pub alphas: Vec<f64>,
+    pub gammas: Vec<f64>,
+    pub epsilons: Vec<f64>,
+    pub episode_counts: Vec<u32>,
+    // Every configuration is trained once with each seed
+    pub seeds: Vec<u64>,
+    // Games against each reference opponent
+    pub num_games: u32,
+    pub num_workers: usize,
+}
+
+impl Sweep {
+    // A single configuration with the values main.rs trains with, over
+    // three seeds.
+    pub fn new() -> Self {
+        Sweep {
+            learners: vec![LearnerKind::QLearning],
+            alphas: vec![0.1],
+            gammas: vec![0.9],
+            epsilons: vec![0.1],
+            episode_counts: vec![100000],
+            seeds: vec![0, 1, 2],
+            num_games: 100,
+            num_workers: thread::available_parallelism().map_or(1, |workers| workers.get()),
+        }
+    }
+
+    // Every combination of the values, in order.
+    pub fn configurations(&self) -> Vec<SweepConfig> {
+        let mut configurations = Vec::new();
+        for learner in &self.learners {
+            for alpha in &self.alphas {
+                for gamma in &self.gammas {
+                    for epsilon in &self.epsilons {
+                        for num_episodes in &self.episode_counts {
+                            configurations.push(SweepConfig {
+                                learner: *learner,
+                                alpha: *alpha,
+                                gamma: *gamma,
+                                epsilon: *epsilon,
+                                num_episodes: *num_episodes,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        configurations
+    }
+
+    // Trains and evaluates every configuration with every seed, sharing
+    // the runs out between the workers.
+    pub fn run<S: State>(&self, include_perfect: bool) -> SweepResults {
+        let configurations = self.configurations();
+        let runs: Vec<(usize, usize)> = (0..configurations.len())
+            .flat_map(|configuration| (0..self.seeds.len()).map(move |seed| (configuration, seed)))
+            .collect();
+        let next_run = AtomicUsize::new(0);
+        let finished = Mutex::new(Vec::new());
+        thread::scope(|scope| {
+            for _ in 0..self.num_workers.max(1) {
+                scope.spawn(|| loop {
+                    let run = next_run.fetch_add(1, Ordering::Relaxed);
+                    let Some(&(configuration, seed)) = runs.get(run) else { break };
+                    let q_learner = configurations[configuration].train::<S>(self.seeds[seed]);
+                    let results = evaluate_against_references(&mut LearnerAgent::new(&q_learner), self.num_games, include_perfect);
+                    finished.lock().unwrap().push((configuration, seed, results));
+                });
+            }
+        });
+
+        let mut rows: Vec<SweepRow> = configurations.into_iter()
+            .map(|config| SweepRow { config, scores: vec![0.0; self.seeds.len()], results: Vec::new() })
+            .collect();
+        for (configuration, seed, results) in finished.into_inner().unwrap() {
+            let row = &mut rows[configuration];
+            row.scores[seed] = results.iter().map(|(_, record)| score(record)).sum::<f64>() / results.len() as f64;
+            for (opponent, record) in results {
+                match row.results.iter_mut().find(|(name, _)| name == opponent) {
+                    Some((_, total)) => {
+                        total.wins += record.wins;
+                        total.draws += record.draws;
+                        total.losses += record.losses;
+                    },
+                    None => row.results.push((opponent.to_string(), record)),
+                }
+            }
+        }
+        rows.sort_by(|a, b| b.mean_score().total_cmp(&a.mean_score()));
+        SweepResults { rows }
+    }
+}
+
+impl Default for Sweep {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+// The fraction of points scored, counting draws as half.
+fn score(record: &EvaluationResults) -> f64 {
+    if record.games() == 0 { 0.0 } else { (record.wins as f64 + 0.5 * record.draws as f64) / record.games() as f64 }
+}
+
+// Reads a list of values, "0.1,0.2,0.5", or an evenly spaced range given as
+// start:end:count, "0.1:0.5:5".
+pub fn parse_values(text: &str) -> Result<Vec<f64>, String> {
+    let parse = |value: &str| value.trim().parse::<f64>().map_err(|_| format!("Invalid value {value}"));
+    let bounds: Vec<&str> = text.split(':').collect();
+    match bounds.as_slice() {
+        [start, end, count] => {
+            let (start, end) = (parse(start)?, parse(end)?);
+            let count: usize = count.trim().parse().map_err(|_| format!("Invalid count {count}"))?;
+            match count {
+                0 => Err("A range needs at least one value".to_string()),
+                1 => Ok(vec![start]),
+                _ => Ok((0..count).map(|i| start + (end - start) * i as f64 / (count - 1) as f64).collect()),
+            }
+        },
+        [_] => text.split(',').map(parse).collect(),
+        _ => Err(format!("Expected a list or start:end:count, got {text}")),
+    }
+}
+
+
+pub struct SweepRow {
+    pub config: SweepConfig,
+    // The mean score against the reference opponents with each seed
+    pub scores: Vec<f64>,
+    // The results against each opponent over all seeds
+    pub results: Vec<(String, EvaluationResults)>,
+}
+
+impl SweepRow {
+    pub fn mean_score(&self) -> f64 {
+        self.scores.iter().sum::<f64>() / self.scores.len().max(1) as f64
+    }
+
+    // The standard deviation of the score over the seeds.
+    pub fn score_deviation(&self) -> f64 {
+        let n = self.scores.len();
+        if n < 2 {
+            return 0.0;
+        }
+        let mean = self.mean_score();
+        let variance = self.scores.iter().map(|score| (score - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+        variance.sqrt()
+    }
+}
+
+
+// The configurations from the best mean score to the worst.
+pub struct SweepResults {
+    pub rows: Vec<SweepRow>,
+}
+
+impl SweepResults {
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let opponents: Vec<&str> = self.rows.first()
+            .map(|row| row.results.iter().map(|(name, _)| name.as_str()).collect())
+            .unwrap_or_default();
+        write!(writer, "rank,learner,alpha,gamma,epsilon,episodes,score,score_deviation")?;
+        for opponent in &opponents {
+            write!(writer, ",{opponent}_wins,{opponent}_draws,{opponent}_losses")?;
+        }
+        writeln!(writer)?;
+        for (rank, row) in self.rows.iter().enumerate() {
+            let config = &row.config;
+            write!(
+                writer, "{},{},{},{},{},{},{},{}",
+                rank + 1, config.learner, config.alpha, config.gamma, config.epsilon, config.num_episodes, row.mean_score(), row.score_deviation()
+            )?;
+            for (_, record) in &row.results {
+                write!(writer, ",{},{},{}", record.wins, record.draws, record.losses)?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SweepResults {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let opponents: Vec<&str> = self.rows.first()
+            .map(|row| row.results.iter().map(|(name, _)| name.as_str()).collect())
+            .unwrap_or_default();
+        write!(f, "Rank  {:10}  {:>6}  {:>6}  {:>7}  {:>8}  {:>6}  {:>5}", "Learner", "Alpha", "Gamma", "Epsilon", "Episodes", "Score", "+/-")?;
+        for opponent in &opponents {
+            write!(f, "  {:>9}", format!("vs {opponent}"))?;
+        }
+        writeln!(f)?;
+        for (rank, row) in self.rows.iter().enumerate() {
+            let config = &row.config;
+            write!(
+                f, "{:>4}  {:10}  {:>6}  {:>6}  {:>7}  {:>8}  {:>5.1}%  {:>5.1}",
+                rank + 1, config.learner.to_string(), config.alpha, config.gamma, config.epsilon, config.num_episodes,
+                100.0 * row.mean_score(), 100.0 * row.score_deviation()
+            )?;
+            for (_, record) in &row.results {
+                write!(f, "  {:>8.1}%", 100.0 * score(record))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::nim::{NimState, StandardNim};
+
+    use super::{parse_values, LearnerKind, Sweep, SweepConfig};
+
+    #[test]
+    fn values_parse_from_lists_and_ranges() {
+        assert_eq!(parse_values("0.1,0.2, 0.5").unwrap(), vec![0.1, 0.2, 0.5]);
+        assert_eq!(parse_values("0:1:5").unwrap(), vec![0.0, 0.25, 0.5, 0.75, 1.0]);
+        assert_eq!(parse_values("0.3:1:1").unwrap(), vec![0.3]);
+        assert!(parse_values("0.1,x").is_err());
+        assert!(parse_values("0:1").is_err());
+    }
+
+    #[test]
+    fn sweep_ranks_trained_learners_above_untrained_ones() {
+        let sweep = Sweep {
+            learners: vec![LearnerKind::QLearning, LearnerKind::Replay],
+            alphas: vec![0.5],
+            gammas: vec![0.9],
+            epsilons: vec![0.5],
+            episode_counts: vec![0, 2000],
+            seeds: vec![0, 1],
+            num_games: 20,
+            num_workers: 2,
+        };
+        assert_eq!(sweep.configurations().len(), 4);
+        let results = sweep.run::<NimState<StandardNim>>(true);
+        assert_eq!(results.rows.len(), 4);
+        assert!(results.rows.windows(2).all(|pair| pair[0].mean_score() >= pair[1].mean_score()));
+        assert!(results.rows[..2].iter().all(|row| row.config.num_episodes == 2000));
+        assert!(results.rows.iter().all(|row| row.results.len() == 3 && row.results[0].1.games() == 40));
+    }
+
+    #[test]
+    fn training_is_reproducible_from_the_seed() {
+        for learner in [LearnerKind::QLearning, LearnerKind::Replay, LearnerKind::League] {
+            let config = SweepConfig { learner, alpha: 0.5, gamma: 0.9, epsilon: 0.5, num_episodes: 200 };
+            let first = config.train::<NimState<StandardNim>>(3);
+            let second = config.train::<NimState<StandardNim>>(3);
+            assert!(first.q_values == second.q_values, "{learner} training differs between runs");
+        }
+    }
+}
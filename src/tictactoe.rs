@@ -1,14 +1,15 @@
 use core::fmt;
+use core::hash::{Hash, Hasher};
+use std::marker::PhantomData;
 use std::{io::{self, BufRead}};
 use rand::Rng;
 
-use crate::{reinforcement_learning::{generic_reinforcement_learner::{State, Action, ReinforcementLearner}, q_learning_learner::QLearner}, utils::prompt};
-
-use std::{thread, time::Duration, io::{Write}};
+use crate::{human_play::{format_value, parse_command, HumanInput, TwoPlayerGame}, reinforcement_learning::{generic_reinforcement_learner::{Environment, Action, EnumerableState, IndexedActions, Symmetric, TensorEncoding, TextEncoding}, shaping::{Potential, Shaped}}, utils::prompt};
 
 #[derive(Clone, Copy)]
 #[derive(Debug)]
 #[derive(PartialEq, Eq, Hash)]
+#[derive(PartialOrd, Ord)]
 pub enum BoardEntry {
     Blank,
     X,
@@ -63,48 +64,119 @@ impl fmt::Display for TicTacToeMove {
 }
 
 
+// How an m,n,k-game rewards its players. Each player is rewarded from their
+// own side: the loser gets the loss reward, both players get the draw
+// reward, and only the mover pays the step penalty. Schemes need not be
+// zero-sum, as the Q-learner learns each side from its own rewards.
+pub trait RewardScheme {
+    fn win() -> f64 {
+        1.0
+    }
+    fn draw() -> f64 {
+        0.0
+    }
+    fn loss() -> f64 {
+        -1.0
+    }
+    // Taken from the reward of every move, so only ever paid by the mover
+    fn step_penalty() -> f64 {
+        0.0
+    }
+}
+
+// +1 win, 0 draw, -1 loss, no step penalty, as the learners have always used.
+pub struct StandardRewards;
+
+impl RewardScheme for StandardRewards {}
+
+// Shaping towards making two in a row and stopping the opponent doing so:
+// a tenth of the number of lines the player to move needs one more mark to
+// complete, less the opponent's.
+pub struct Threats;
+
+impl<const M: usize, const N: usize, const K: usize, P: RewardScheme> Potential<MNKBoard<M, N, K, P>> for Threats {
+    fn potential(state: &MNKBoard<M, N, K, P>) -> f64 {
+        let opponent = if state.current_player == BoardEntry::X { BoardEntry::O } else { BoardEntry::X };
+        0.1 * (state.threats(state.current_player) as f64 - state.threats(opponent) as f64)
+    }
+
+    fn discount() -> f64 {
+        0.9
+    }
+}
+
+
 /*
  Board struct
+
+ An m,n,k-game: an M x N board on which the first player to get K of their
+ marks in a row, column or diagonal wins. Tic-tac-toe is the 3,3,3-game.
 */
-#[derive(PartialEq, Eq, Hash)]
-#[derive(Clone)]
-pub struct TicTacToeBoard {
+pub struct MNKBoard<const M: usize, const N: usize, const K: usize, P = StandardRewards>
+where
+    P: RewardScheme
+{
     /*
-    * Internal state represented by a length-9 base-3 number.
+    * One bitmask per player. Bit (x * (N + 1) + y) is set when that player
+    * has a mark in the cell. Each row takes N + 1 bits, the extra bit being
+    * an always-empty sentinel so that shifted lines never wrap from one row
+    * into the next.
     */
-    internal_state: u32,
+    x_mask: u128,
+    o_mask: u128,
     pub current_player: BoardEntry,
+    rewards: PhantomData<P>,
+}
+
+pub type TicTacToeBoard = MNKBoard<3, 3, 3>;
+pub type FourByFourBoard = MNKBoard<4, 4, 4>;
+pub type GomokuLiteBoard = MNKBoard<9, 9, 5>;
+pub type ShapedTicTacToeBoard = Shaped<TicTacToeBoard, Threats>;
+
+// By hand, so that reward schemes need not implement these themselves.
+impl<const M: usize, const N: usize, const K: usize, P: RewardScheme> Clone for MNKBoard<M, N, K, P> {
+    fn clone(&self) -> Self {
+        MNKBoard { x_mask: self.x_mask, o_mask: self.o_mask, current_player: self.current_player, rewards: PhantomData }
+    }
+}
+
+impl<const M: usize, const N: usize, const K: usize, P: RewardScheme> PartialEq for MNKBoard<M, N, K, P> {
+    fn eq(&self, other: &Self) -> bool {
+        (self.x_mask, self.o_mask, self.current_player) == (other.x_mask, other.o_mask, other.current_player)
+    }
+}
+
+impl<const M: usize, const N: usize, const K: usize, P: RewardScheme> Eq for MNKBoard<M, N, K, P> {}
+
+impl<const M: usize, const N: usize, const K: usize, P: RewardScheme> Hash for MNKBoard<M, N, K, P> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.x_mask, self.o_mask, self.current_player).hash(state);
+    }
 }
 
-impl fmt::Display for TicTacToeBoard {
+impl<const M: usize, const N: usize, const K: usize, P: RewardScheme> fmt::Display for MNKBoard<M, N, K, P> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let mut string = String::from(
-            "x\\y| 0 | 1 | 2 |\n\
-            ---+---+---+---+\n"
-        );
-        for x in 0..3 {
-            string.push_str(format!(" {x} |").as_str());
-            for y in 0..3 {
-                string.push_str(format!(" {} |", self.get(x, y)).as_str());
-            }
-            string.push_str("\n---+---+---+---+\n");
-        }
-        write!(f, "{}", string)
+        write!(f, "{}", self.grid(3, |x, y| self.get(x, y).to_string()))
     }
 }
 
-impl State for TicTacToeBoard {
+impl<const M: usize, const N: usize, const K: usize, P: RewardScheme> Environment for MNKBoard<M, N, K, P> {
     type A = TicTacToeMove;
 
-    fn initial_state() -> TicTacToeBoard {
-        
-        let who_starts = rand::thread_rng().gen_range(1..=2);
+    fn initial_state() -> Self {
+        Self::sample_initial_state(&mut rand::thread_rng())
+    }
+
+    fn sample_initial_state<R: Rng + ?Sized>(rng: &mut R) -> Self {
+        let who_starts = rng.gen_range(1..=2);
         let player: BoardEntry = if who_starts == 1 {
             BoardEntry::X
         } else {
             BoardEntry::O
         };
-        TicTacToeBoard { internal_state: 0, current_player: player }
+        // Refuses to compile boards too big for the bitmasks
+        let () = Self::FITS;
+        MNKBoard { x_mask: 0, o_mask: 0, current_player: player, rewards: PhantomData }
     }
 
     fn next_state(&self, action: &TicTacToeMove) -> Self {
@@ -118,55 +190,66 @@ impl State for TicTacToeBoard {
         self.has_someone_won().is_some()
     }
 
-    // OPTIMISE
+    fn alternates_players() -> bool {
+        true
+    }
+
     fn available_actions(&self) -> Vec<TicTacToeMove> {
-        // Get available actions from the board
-        let mut moves = Vec::new();
-        for x in 0..=2 {
-            for y in 0..=2 {
-                let this_move = TicTacToeMove { x, y };
-                if self.is_valid_move(this_move) {
-                    moves.push(this_move);
-                }
-            }
+        // Get available actions from the empty cells
+        let mut empty = Self::BOARD & !self.occupied();
+        let mut moves = Vec::with_capacity(empty.count_ones() as usize);
+        while empty != 0 {
+            let position = empty.trailing_zeros() as usize;
+            moves.push(TicTacToeMove { x: position / Self::ROW_WIDTH, y: position % Self::ROW_WIDTH });
+            empty &= empty - 1;
         }
         moves
     }
 
     fn num_available_actions(&self) -> usize {
-        self.available_actions().len()
+        (Self::BOARD & !self.occupied()).count_ones() as usize
     }
 
     fn get_reward(state: &Self, _action: &TicTacToeMove, next_state: &Self) -> f64 {
-        if !next_state.is_terminal() { return 0.0; }
+        let outcome = match next_state.has_someone_won() {
+            Some(BoardEntry::Blank) => P::draw(),
+            Some(entry) if entry == state.current_player => P::win(),
+            _ => 0.0
+        };
+        outcome - P::step_penalty()
+    }
+
+    fn get_opponent_reward(state: &Self, _action: &TicTacToeMove, next_state: &Self) -> f64 {
         match next_state.has_someone_won() {
-            Some(entry) => {
-                if entry == state.current_player {
-                    1.0
-                } else {
-                    0.0
-                }
-            },
-            None => 0.0
+            Some(BoardEntry::Blank) => P::draw(),
+            Some(entry) if entry == state.current_player => P::loss(),
+            _ => 0.0
+        }
+    }
+
+    fn get_result(state: &Self, _action: &TicTacToeMove, next_state: &Self) -> f64 {
+        match next_state.has_someone_won() {
+            Some(entry) if entry == state.current_player => 1.0,
+            _ => 0.0
         }
     }
 }
 
 
-impl TryFrom<String> for TicTacToeBoard {
+impl<const M: usize, const N: usize, const K: usize, P: RewardScheme> TryFrom<String> for MNKBoard<M, N, K, P> {
     type Error = String;
 
     fn try_from(str: String) -> Result<Self, Self::Error> {
-        if str.len() != 9 {
+        if str.chars().count() != M * N {
             return Err("Wrong length".to_string())
         }
 
-        let mut board = TicTacToeBoard::new();
+        let mut board = Self::new();
         for (i, char) in str.chars().enumerate() {
             match BoardEntry::try_from(char) {
                 Ok(entry) => {
-                    let x = i / 3;
-                    let y = i % 3;
+                    let x = i / N;
+                    let y = i % N;
                     board.put(x, y, entry);
                 },
                 Err(err) => return Err(err)
@@ -176,38 +259,233 @@ impl TryFrom<String> for TicTacToeBoard {
     }
 }
 
-impl TicTacToeBoard {
-    fn new() -> TicTacToeBoard {
-        TicTacToeBoard::initial_state()
+impl<const M: usize, const N: usize, const K: usize, P: RewardScheme> IndexedActions for MNKBoard<M, N, K, P> {
+    fn num_actions() -> usize {
+        M * N
+    }
+
+    fn action_index(action: &TicTacToeMove) -> usize {
+        action.x * N + action.y
     }
+}
 
-    fn translate_coords_to_internal_state_position(&self, x: usize, y: usize) -> u32 {
-        ((x * 3) + y).try_into().unwrap()
+impl<const M: usize, const N: usize, const K: usize, P: RewardScheme> TensorEncoding for MNKBoard<M, N, K, P> {
+    fn encoding_len() -> usize {
+        2 * M * N
     }
 
-    fn get(&self, x: usize, y: usize) -> BoardEntry {
-        let entry = (self.internal_state / 10_u32.pow(self.translate_coords_to_internal_state_position(x, y))) % 10;
-        match entry {
-            0 => BoardEntry::Blank,
-            1 => BoardEntry::O,
-            2 => BoardEntry::X,
-            _ => panic!("Unknown entry in ({}, {}): {}", x, y, entry)
+    // One plane marking the current player's cells followed by one marking
+    // the opponent's.
+    fn encode(&self) -> Vec<f64> {
+        let mut encoding = vec![0.0; 2 * M * N];
+        for x in 0..M {
+            for y in 0..N {
+                let entry = self.get(x, y);
+                if entry == BoardEntry::Blank {
+                    continue;
+                }
+                let plane = if entry == self.current_player { 0 } else { 1 };
+                encoding[plane * M * N + x * N + y] = 1.0;
+            }
         }
+        encoding
     }
+}
 
-    fn put(&mut self, x: usize, y: usize, entry: BoardEntry) {
-        let position = self.translate_coords_to_internal_state_position(x, y);
-        let entry_number = match entry {
-            BoardEntry::Blank => 0,
-            BoardEntry::O => 1,
-            BoardEntry::X => 2
+// Reflections of the board, and on square boards its rotations, which map
+// lines onto lines. Bit 0 of the symmetry flips the rows, bit 1 flips the
+// columns and bit 2 then swaps rows with columns.
+impl<const M: usize, const N: usize, const K: usize, P: RewardScheme> Symmetric for MNKBoard<M, N, K, P> {
+    fn num_symmetries() -> usize {
+        if M == N { 8 } else { 4 }
+    }
+
+    fn transform(&self, symmetry: usize) -> Self {
+        let mut board = MNKBoard { x_mask: 0, o_mask: 0, current_player: self.current_player, rewards: PhantomData };
+        for x in 0..M {
+            for y in 0..N {
+                let (new_x, new_y) = Self::transform_coords(x, y, symmetry);
+                board.put(new_x, new_y, self.get(x, y));
+            }
+        }
+        board
+    }
+
+    fn transform_action(action: &TicTacToeMove, symmetry: usize) -> TicTacToeMove {
+        let (x, y) = Self::transform_coords(action.x, action.y, symmetry);
+        TicTacToeMove { x, y }
+    }
+
+    fn inverse_symmetry(symmetry: usize) -> usize {
+        // Undoing a swap after the flips means swapping before them, which
+        // is the same as flipping the other way round and then swapping
+        if symmetry & 4 == 0 {
+            symmetry
+        } else {
+            4 | (symmetry & 1) << 1 | (symmetry & 2) >> 1
+        }
+    }
+
+    // The symmetry giving the smallest bitmasks.
+    fn canonical_symmetry(&self) -> usize {
+        (0..Self::num_symmetries())
+            .min_by_key(|symmetry| {
+                let board = self.transform(*symmetry);
+                (board.x_mask, board.o_mask)
+            })
+            .unwrap_or(0)
+    }
+}
+
+// Tic-tac-toe is small enough to number every board: each cell is a base-3
+// digit (blank, X or O), with a second block of indices for when O is to
+// move. Most of the 2 * 3^9 indices are unreachable positions.
+impl<P: RewardScheme> EnumerableState for MNKBoard<3, 3, 3, P> {
+    fn num_states() -> usize {
+        2 * 3usize.pow(9)
+    }
+
+    fn state_index(&self) -> usize {
+        let mut index = 0;
+        for x in 0..3 {
+            for y in 0..3 {
+                let digit = match self.get(x, y) {
+                    BoardEntry::Blank => 0,
+                    BoardEntry::X => 1,
+                    BoardEntry::O => 2,
+                };
+                index = 3 * index + digit;
+            }
+        }
+        if self.current_player == BoardEntry::O {
+            index += 3usize.pow(9);
+        }
+        index
+    }
+
+    fn from_state_index(index: usize) -> Self {
+        let current_player = if index >= 3usize.pow(9) { BoardEntry::O } else { BoardEntry::X };
+        let mut board = MNKBoard { x_mask: 0, o_mask: 0, current_player, rewards: PhantomData };
+        let mut digits = index % 3usize.pow(9);
+        for cell in (0..9).rev() {
+            let entry = match digits % 3 {
+                0 => BoardEntry::Blank,
+                1 => BoardEntry::X,
+                _ => BoardEntry::O,
+            };
+            board.put(cell / 3, cell % 3, entry);
+            digits /= 3;
+        }
+        board
+    }
+}
+
+// The cells row by row, as read by TryFrom<String>, followed by the player
+// to move.
+impl<const M: usize, const N: usize, const K: usize, P: RewardScheme> TextEncoding for MNKBoard<M, N, K, P> {
+    fn to_text(&self) -> String {
+        let mut text = String::new();
+        for x in 0..M {
+            for y in 0..N {
+                text.push_str(&self.get(x, y).to_string());
+            }
+        }
+        text.push_str(&self.current_player.to_string());
+        text
+    }
+
+    fn from_text(text: &str) -> Result<Self, String> {
+        let mut chars: Vec<char> = text.chars().collect();
+        let current_player = match chars.pop().map(BoardEntry::try_from) {
+            Some(Ok(BoardEntry::Blank)) | None => return Err("Missing player to move".to_string()),
+            Some(player) => player?,
         };
-        let position_10_pow = 10_u32.pow(position);
-        self.internal_state = ((self.internal_state / position_10_pow) + entry_number) * position_10_pow + (self.internal_state % position_10_pow);
+        let mut board = Self::try_from(chars.into_iter().collect::<String>())?;
+        board.current_player = current_player;
+        Ok(board)
+    }
+}
+
+impl<const M: usize, const N: usize, const K: usize, P: RewardScheme> MNKBoard<M, N, K, P> {
+    const ROW_WIDTH: usize = N + 1;
+    const FITS: () = assert!(M * (N + 1) <= 128, "Board too big for its bitmasks");
+    // Every cell of the board, leaving out the sentinels
+    const BOARD: u128 = {
+        let row = (1u128 << N) - 1;
+        let mut mask = 0;
+        let mut x = 0;
+        while x < M {
+            mask |= row << (x * (N + 1));
+            x += 1;
+        }
+        mask
+    };
+    // Shifts to the next cell along rows, columns, the down diagonal and
+    // the up diagonal
+    const DIRECTIONS: [usize; 4] = [1, N + 1, N + 2, N];
+
+    fn new() -> Self {
+        Self::initial_state()
+    }
+
+    // The board with each cell drawn by `cell`, centred in `width` characters.
+    fn grid(&self, width: usize, cell: impl Fn(usize, usize) -> String) -> String {
+        let separator = format!("---+{}\n", format!("{}+", "-".repeat(width)).repeat(N));
+        let mut string = String::from("x\\y|");
+        for y in 0..N {
+            string.push_str(format!("{:^width$}|", y).as_str());
+        }
+        string.push('\n');
+        string.push_str(separator.as_str());
+        for x in 0..M {
+            string.push_str(format!("{:^3}|", x).as_str());
+            for y in 0..N {
+                string.push_str(format!("{:^width$}|", cell(x, y)).as_str());
+            }
+            string.push('\n');
+            string.push_str(separator.as_str());
+        }
+        string
+    }
+
+    fn transform_coords(x: usize, y: usize, symmetry: usize) -> (usize, usize) {
+        let x = if symmetry & 1 != 0 { M - 1 - x } else { x };
+        let y = if symmetry & 2 != 0 { N - 1 - y } else { y };
+        if symmetry & 4 != 0 { (y, x) } else { (x, y) }
+    }
+
+    fn translate_coords_to_bit(x: usize, y: usize) -> u128 {
+        1 << (x * Self::ROW_WIDTH + y)
+    }
+
+    fn occupied(&self) -> u128 {
+        self.x_mask | self.o_mask
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> BoardEntry {
+        let bit = Self::translate_coords_to_bit(x, y);
+        if self.x_mask & bit != 0 {
+            BoardEntry::X
+        } else if self.o_mask & bit != 0 {
+            BoardEntry::O
+        } else {
+            BoardEntry::Blank
+        }
+    }
+
+    fn put(&mut self, x: usize, y: usize, entry: BoardEntry) {
+        let bit = Self::translate_coords_to_bit(x, y);
+        self.x_mask &= !bit;
+        self.o_mask &= !bit;
+        match entry {
+            BoardEntry::X => self.x_mask |= bit,
+            BoardEntry::O => self.o_mask |= bit,
+            BoardEntry::Blank => (),
+        }
     }
 
     pub fn is_valid_move(&self, action: TicTacToeMove) -> bool {
-        action.x <= 2 && action.y <= 2 && self.get(action.x, action.y) == BoardEntry::Blank
+        action.x < M && action.y < N && self.occupied() & Self::translate_coords_to_bit(action.x, action.y) == 0
     }
 
     fn change_player(&mut self) {
@@ -218,44 +496,50 @@ impl TicTacToeBoard {
         };
     }
 
-    pub fn has_someone_won(&self) -> Option<BoardEntry> {
-        // Check rows
-        for y in 0..3 {
-            let first = self.get(0, y);
-            if first == BoardEntry::Blank { continue };
-            if first == self.get(1, y) && first == self.get(2, y) {
-                return Some(first);
-            }
-        }
-        // Check columns
-        for x in 0..3 {
-            let first = self.get(x, 0);
-            if first == BoardEntry::Blank { continue };
-            if first == self.get(x, 1) && first == self.get(x, 2) {
-                return Some(first);
+    // Each bit left set after the shifts starts K marks in a row.
+    fn has_k_in_a_row(marks: u128) -> bool {
+        Self::DIRECTIONS.iter().any(|direction| {
+            let line_starts = (1..K).fold(marks, |starts, i| starts & (marks >> (i * direction)));
+            line_starts != 0
+        })
+    }
+
+    // The number of lines on which the player has all but one of the K
+    // marks, with the last cell empty.
+    pub fn threats(&self, player: BoardEntry) -> u32 {
+        let (marks, others) = match player {
+            BoardEntry::X => (self.x_mask, self.o_mask),
+            BoardEntry::O => (self.o_mask, self.x_mask),
+            BoardEntry::Blank => return 0,
+        };
+        let mut threats = 0;
+        for start in 0..M * Self::ROW_WIDTH {
+            for direction in Self::DIRECTIONS {
+                if start + (K - 1) * direction >= 128 {
+                    continue;
+                }
+                let line = (0..K).fold(0u128, |line, i| line | 1 << (start + i * direction));
+                // Lines running off the board take in a sentinel or a cell past the last row
+                if line & !Self::BOARD == 0 && line & others == 0 && (line & marks).count_ones() as usize == K - 1 {
+                    threats += 1;
+                }
             }
         }
-    
-        // Check down diagonal
-        let first = self.get(0, 0);
-        if first != BoardEntry::Blank && first == self.get(1, 1) && first == self.get(2, 2) {
-                return Some(first);
+        threats
+    }
+
+    pub fn has_someone_won(&self) -> Option<BoardEntry> {
+        if Self::has_k_in_a_row(self.x_mask) {
+            return Some(BoardEntry::X);
         }
-    
-        // Check up diagonal
-        let first = self.get(0, 2);
-        if first != BoardEntry::Blank && first == self.get(1, 1) && first == self.get(2, 0) {
-                return Some(first);
+        if Self::has_k_in_a_row(self.o_mask) {
+            return Some(BoardEntry::O);
         }
     
         // Check if the board is filled
-        for x in 0..=2 {
-            for y in 0..=2 {
-            if self.get(x, y) == BoardEntry::Blank {
-                // Nobody has won yet
-                return None;
-            }
-            }
+        if self.occupied() != Self::BOARD {
+            // Nobody has won yet
+            return None;
         }
     
         // Indicates a draw
@@ -264,12 +548,16 @@ impl TicTacToeBoard {
 }
 
 
-pub fn get_move_input<R>(board: &TicTacToeBoard, reader: R) -> Result<TicTacToeMove, ()>
+#[allow(clippy::result_unit_err)]
+pub fn get_move_input<R, const M: usize, const N: usize, const K: usize, P: RewardScheme>(board: &MNKBoard<M, N, K, P>, reader: R) -> Result<HumanInput<TicTacToeMove>, ()>
     where R: BufRead
 {
     let mut output = io::stdout();
 
     let xy_str = prompt(reader, &mut output, &format!("Player {}, input your move: \n", board.current_player));
+    if let Some(command) = parse_command(&xy_str) {
+        return Ok(command);
+    }
 
     let xy: Vec<&str> = xy_str.splitn(2, ',').collect();
     if xy.len() != 2 {
@@ -292,81 +580,95 @@ pub fn get_move_input<R>(board: &TicTacToeBoard, reader: R) -> Result<TicTacToeM
     };
     let human_move = TicTacToeMove { x, y };
     if board.is_valid_move(human_move) {
-        return Ok(human_move);
+        Ok(HumanInput::Move(human_move))
     } else {
         println!("Invalid move, please choose a different cell.");
-        return Err(());
+        Err(())
     }
 }
 
 
-fn human_turn(stdin: &io::Stdin, board: &TicTacToeBoard) -> TicTacToeBoard {
-    loop {
-        let input = stdin.lock();
-        let human_move = match get_move_input(&board, input) {
-            Ok(chosen_move) => chosen_move,
-            Err(_) => continue
-        };
-        return board.next_state(&human_move);
+impl<const M: usize, const N: usize, const K: usize, P: RewardScheme> TwoPlayerGame for MNKBoard<M, N, K, P> {
+    type Player = BoardEntry;
+
+    // Humans are Os by default because they are soft and squishy.
+    fn players() -> [BoardEntry; 2] {
+        [BoardEntry::X, BoardEntry::O]
     }
-}
 
-fn machine_turn(q_learning_learner: &QLearner<TicTacToeBoard>, board: &TicTacToeBoard) -> TicTacToeBoard{
-    // Machine's turn
-    print!("Machine is making a move");
-    io::stdout().flush().unwrap();
-    for _ in 1..=3 {
-        thread::sleep(Duration::from_millis(300));
-        print!(".");
-        io::stdout().flush().unwrap();
-    }
-    println!();
-    let machine_move = q_learning_learner.get_best_action(&board);
-    return board.next_state(&machine_move);
-}
+    fn starting_with(player: BoardEntry) -> Self {
+        MNKBoard { x_mask: 0, o_mask: 0, current_player: player, rewards: PhantomData }
+    }
+
+    fn player_to_move(&self) -> BoardEntry {
+        self.current_player
+    }
 
+    fn winner(&self) -> Option<BoardEntry> {
+        self.has_someone_won().filter(|winner| *winner != BoardEntry::Blank)
+    }
 
-pub fn play_vs_human(q_learning_learner: QLearner<TicTacToeBoard>) {
-    let stdin = io::stdin();
-    let mut board = TicTacToeBoard::initial_state();
-    
-    println!("==================================");
-    println!("THE GAME BEGINS");
-    // Humans are Os because they are soft and squishy.
-    let human_player = BoardEntry::O;
-    println!("{board}");
-    loop {
-        if board.current_player == human_player {
-            board = human_turn(&stdin, &board);
-        } else {
-            board = machine_turn(&q_learning_learner, &board);
-        }
-        println!("{board}");
-
-        match board.has_someone_won() {
-            Some(someone) => {
-                if human_player == someone {
-                    println!("{board}");
-                    println!("Player {human_player} has won!");
-                } else if someone == BoardEntry::X {
-                    println!("Machine has won!")
-                } else if someone == BoardEntry::Blank {
-                    println!("{board}");
-                    println!("It's a draw!");
-                }
-                break;
+    fn read_move<R: BufRead>(&self, reader: R) -> Option<HumanInput<TicTacToeMove>> {
+        get_move_input(self, reader).ok()
+    }
+
+    // Values go in the empty cells, which are widened to fit them.
+    fn show_values(&self, values: &[(TicTacToeMove, f64)], recommended: &TicTacToeMove) -> String {
+        self.grid(7, |x, y| {
+            let cell = TicTacToeMove { x, y };
+            match values.iter().find(|(action, _)| *action == cell) {
+                Some((action, value)) => format_value(*value, action == recommended),
+                None => self.get(x, y).to_string(),
             }
-            None => {}
-        };
+        })
+    }
+
+    fn can_solve() -> bool {
+        M * N <= 9
     }
 }
 
 
 #[cfg(test)]
 mod tests {
-    use crate::{reinforcement_learning::generic_reinforcement_learner::State, tictactoe::TicTacToeMove};
+    use std::collections::HashSet;
+
+    use crate::{human_play::HumanInput, reinforcement_learning::{generic_reinforcement_learner::{Environment, ReinforcementLearner, Symmetric}, q_learning_learner::QLearner, shaping::Potential}, tictactoe::TicTacToeMove};
+
+    use super::{TicTacToeBoard, FourByFourBoard, GomokuLiteBoard, MNKBoard, RewardScheme, ShapedTicTacToeBoard, Threats, get_move_input};
 
-    use super::{TicTacToeBoard, get_move_input};
+    struct DrawAverse;
+
+    impl RewardScheme for DrawAverse {
+        fn draw() -> f64 {
+            -0.5
+        }
+        fn loss() -> f64 {
+            -2.0
+        }
+        fn step_penalty() -> f64 {
+            0.01
+        }
+    }
+
+    // Would rather lose than draw.
+    struct DrawHating;
+
+    impl RewardScheme for DrawHating {
+        fn draw() -> f64 {
+            -1.5
+        }
+    }
+
+    // The given position, with X to move.
+    fn board<P: RewardScheme>(cells: &str) -> MNKBoard<3, 3, 3, P> {
+        let mut board: MNKBoard<3, 3, 3, P> = match MNKBoard::try_from(cells.to_string()) {
+            Ok(res) => res,
+            Err(err) => panic!("{err}"),
+        };
+        board.current_player = super::BoardEntry::X;
+        board
+    }
 
     #[test]
     fn tictactoe_board_changes_player() {
@@ -422,12 +724,230 @@ mod tests {
         let board = TicTacToeBoard::new();
         
         let input = b"2, 1";
-        assert_eq!(get_move_input(&board, &input[..]), Ok(TicTacToeMove { x: 2, y : 1}));
+        assert_eq!(get_move_input(&board, &input[..]), Ok(HumanInput::Move(TicTacToeMove { x: 2, y : 1})));
 
         let input = b"3, 1";
         assert_eq!(get_move_input(&board, &input[..]), Err(()));
 
         let input = b"1";
         assert_eq!(get_move_input(&board, &input[..]), Err(()));
+
+        let input = b"quit";
+        assert_eq!(get_move_input(&board, &input[..]), Ok(HumanInput::Quit));
+    }
+
+    #[test]
+    fn four_by_four_board_needs_four_in_a_row() {
+        let board = match FourByFourBoard::try_from([
+            "OOO ",
+            "XXX ",
+            "    ",
+            "    ",
+        ].concat()) {
+            Ok(res) => res,
+            Err(err) => panic!("{err}"),
+        };
+        assert!(!board.is_terminal());
+        assert_eq!(board.available_actions().len(), 10);
+
+        let mut board = board;
+        board.current_player = super::BoardEntry::O;
+        let board = board.next_state(&TicTacToeMove::new(0, 3));
+        assert_eq!(board.has_someone_won(), Some(super::BoardEntry::O));
+    }
+
+    #[test]
+    fn gomoku_lite_board_wins_on_diagonals() {
+        let mut cells = vec![' '; 81];
+        for i in 0..5 {
+            // Up diagonal from (8, 2) to (4, 6)
+            cells[(8 - i) * 9 + 2 + i] = 'X';
+        }
+        let board = match GomokuLiteBoard::try_from(cells.iter().collect::<String>()) {
+            Ok(res) => res,
+            Err(err) => panic!("{err}"),
+        };
+        assert_eq!(board.has_someone_won(), Some(super::BoardEntry::X));
+    }
+
+    #[test]
+    fn board_symmetries_commute_with_moves() {
+        let board = match TicTacToeBoard::try_from(["XO ", "X  ", "   "].concat()) {
+            Ok(res) => res,
+            Err(err) => panic!("{err}"),
+        };
+        let canonical = board.transform(board.canonical_symmetry());
+        let mut transformed_boards = Vec::new();
+        for symmetry in 0..TicTacToeBoard::num_symmetries() {
+            let transformed = board.transform(symmetry);
+            assert!(transformed.transform(TicTacToeBoard::inverse_symmetry(symmetry)) == board);
+            assert!(transformed.transform(transformed.canonical_symmetry()) == canonical);
+            for action in board.available_actions() {
+                assert!(board.next_state(&action).transform(symmetry) == transformed.next_state(&TicTacToeBoard::transform_action(&action, symmetry)));
+            }
+            if !transformed_boards.contains(&transformed) {
+                transformed_boards.push(transformed);
+            }
+        }
+        // The position has no symmetry of its own, so all eight are different
+        assert_eq!(transformed_boards.len(), 8);
+
+        // Non-square boards only have their reflections and half turn
+        assert_eq!(super::MNKBoard::<3, 4, 3>::num_symmetries(), 4);
+    }
+
+    #[test]
+    fn lines_do_not_wrap_around_the_board_edges() {
+        let board = match FourByFourBoard::try_from([
+            "  XX",
+            "XX  ",
+            "   X",
+            "X   ",
+        ].concat()) {
+            Ok(res) => res,
+            Err(err) => panic!("{err}"),
+        };
+        assert_eq!(board.has_someone_won(), None);
+        assert_eq!(board.available_actions().len(), 10);
+        assert_eq!(board.to_string().matches('X').count(), 6);
+    }
+
+    #[test]
+    fn reward_schemes_set_the_outcome_values() {
+        // X to move can win at the top right
+        let state = board::<DrawAverse>("XX OO    ");
+        let win = TicTacToeMove::new(0, 2);
+        let next_state = state.next_state(&win);
+        assert_eq!(MNKBoard::get_reward(&state, &win, &next_state), 1.0 - 0.01);
+        assert_eq!(MNKBoard::get_opponent_reward(&state, &win, &next_state), -2.0);
+        assert_eq!(MNKBoard::get_result(&state, &win, &next_state), 1.0);
+
+        let elsewhere = TicTacToeMove::new(2, 2);
+        let next_state = state.next_state(&elsewhere);
+        assert_eq!(MNKBoard::get_reward(&state, &elsewhere, &next_state), -0.01);
+        assert_eq!(MNKBoard::get_opponent_reward(&state, &elsewhere, &next_state), 0.0);
+
+        // X fills the board without anyone winning
+        let state = board::<DrawAverse>("XOXXOOOX ");
+        let draw = TicTacToeMove::new(2, 2);
+        let next_state = state.next_state(&draw);
+        assert_eq!(next_state.has_someone_won(), Some(super::BoardEntry::Blank));
+        assert_eq!(MNKBoard::get_reward(&state, &draw, &next_state), -0.5 - 0.01);
+        assert_eq!(MNKBoard::get_opponent_reward(&state, &draw, &next_state), -0.5);
+        assert_eq!(MNKBoard::get_result(&state, &draw, &next_state), 0.0);
+
+        // The standard scheme is unchanged
+        let state = board::<super::StandardRewards>("XX OO    ");
+        let next_state = state.next_state(&win);
+        assert_eq!(TicTacToeBoard::get_reward(&state, &win, &next_state), 1.0);
+        assert_eq!(TicTacToeBoard::get_opponent_reward(&state, &win, &next_state), -1.0);
+    }
+
+    // Self-play Q-learning updates, with a learning rate of one, swept over
+    // every position reachable from the given one until the values are exact.
+    fn learn_from<P: RewardScheme>(start: MNKBoard<3, 3, 3, P>) -> QLearner<MNKBoard<3, 3, 3, P>> {
+        let mut positions = HashSet::new();
+        let mut frontier = vec![start];
+        while let Some(state) = frontier.pop() {
+            if !state.is_terminal() && positions.insert(state.clone()) {
+                frontier.extend(state.available_actions().iter().map(|action| state.next_state(action)));
+            }
+        }
+        let mut learner = QLearner::new(1.0, 0.9);
+        for _ in 0..positions.len() {
+            for state in &positions {
+                for action in state.available_actions() {
+                    let next_state = state.next_state(&action);
+                    let reward = MNKBoard::get_reward(state, &action, &next_state);
+                    learner.update_action_value(state, &action, &next_state, reward);
+                }
+            }
+        }
+        learner
+    }
+
+    #[test]
+    fn self_play_learns_each_side_from_its_own_rewards() {
+        // Top left lets O fill the board for a draw, bottom right lets O win
+        let state = board::<DrawAverse>(" OOOXXXO ");
+        let learner = learn_from(state.clone());
+        // Both players get the draw reward, where negating O's value would
+        // have made the draw worth +0.45 to X
+        let draw = learner.get_action_value(&state, &TicTacToeMove::new(0, 0));
+        assert!((draw - (-0.01 + 0.9 * -0.5)).abs() < 1e-9);
+        let loss = learner.get_action_value(&state, &TicTacToeMove::new(2, 2));
+        assert!((loss - (-0.01 + 0.9 * -2.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn reward_schemes_change_the_preferred_move() {
+        // Bottom right draws at once, anything else loses later
+        let cells = "OX X O O ";
+        let draw = TicTacToeMove::new(2, 2);
+        let standard = board::<super::StandardRewards>(cells);
+        assert!(learn_from(standard.clone()).get_best_action(&standard) == draw);
+        let draw_hating = board::<DrawHating>(cells);
+        assert!(learn_from(draw_hating.clone()).get_best_action(&draw_hating) != draw);
+    }
+
+    #[test]
+    fn threats_count_lines_missing_one_mark() {
+        let state = board::<super::StandardRewards>("XX O O  X");
+        // The top row and the down diagonal
+        assert_eq!(state.threats(super::BoardEntry::X), 2);
+        // The middle row
+        assert_eq!(state.threats(super::BoardEntry::O), 1);
+        assert_eq!(board::<super::StandardRewards>("X O O   X").threats(super::BoardEntry::X), 0);
+    }
+
+    #[test]
+    fn shaping_is_a_difference_of_potentials() {
+        let gamma = <Threats as Potential<TicTacToeBoard>>::discount();
+        // X to move has the top row and O nothing
+        let state = ShapedTicTacToeBoard::new(board("XX  O    "));
+        let action = TicTacToeMove::new(2, 0);
+        let next_state = state.next_state(&action);
+        let potential = 0.1 * 1.0;
+        // Then O is to move, with nothing against X's top row and left column
+        let next_potential = 0.1 * (0.0 - 2.0);
+        assert!((Threats::potential(state.inner()) - potential).abs() < 1e-12);
+        let reward = ShapedTicTacToeBoard::get_reward(&state, &action, &next_state);
+        assert!((reward - (gamma * -next_potential - potential)).abs() < 1e-12);
+
+        // Nothing is owed for the potential of a finished game
+        let win = TicTacToeMove::new(0, 2);
+        let next_state = state.next_state(&win);
+        let reward = ShapedTicTacToeBoard::get_reward(&state, &win, &next_state);
+        assert!((reward - (1.0 - potential)).abs() < 1e-12);
+        assert_eq!(ShapedTicTacToeBoard::get_result(&state, &win, &next_state), 1.0);
+    }
+
+    // The target of league training for a move and the opponent's reply,
+    // given the learner's value of the position after them.
+    fn league_target<S: Environment>(state: &S, action: &S::A, reply: &S::A, value: f64) -> f64 {
+        let gamma = <Threats as Potential<TicTacToeBoard>>::discount();
+        let next_state = state.next_state(action);
+        let after_reply = next_state.next_state(reply);
+        let reward = S::get_reward(state, action, &next_state) + gamma * S::get_opponent_reward(&next_state, reply, &after_reply);
+        if after_reply.is_terminal() { reward } else { reward + gamma * gamma * value }
+    }
+
+    #[test]
+    fn shaping_shifts_league_targets_by_the_potential() {
+        // O blocks X's top row, and then O wins on its reply
+        let positions = [
+            ("XX  O    ", TicTacToeMove::new(2, 0), TicTacToeMove::new(0, 2)),
+            ("OO XX    ", TicTacToeMove::new(2, 2), TicTacToeMove::new(0, 2)),
+        ];
+        for (cells, action, reply) in positions {
+            let state = board::<super::StandardRewards>(cells);
+            let after_reply = state.next_state(&action).next_state(&reply);
+            // Shaped values are shifted by minus the potential too
+            let value = 0.3;
+            let shaped_value = value - Threats::potential(&after_reply);
+            let plain = league_target(&state, &action, &reply, value);
+            let shaped = league_target(&ShapedTicTacToeBoard::new(state.clone()), &action, &reply, shaped_value);
+            assert!((shaped - plain + Threats::potential(&state)).abs() < 1e-12);
+        }
     }
 }
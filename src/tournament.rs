@@ -0,0 +1,307 @@
+use core::fmt;
+use std::io::{self, Write};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::evaluation::{evaluate, Agent, EvaluationResults, Z_95};
+use crate::reinforcement_learning::generic_reinforcement_learner::Environment;
+use crate::utils::csv_field;
+
+// Round-robin tournaments between agents for a two-player game, with Elo
+// ratings fitted to the results. Every pair of entrants plays the same
+// number of games, starting alternately, so the ratings can track the
+// strength of checkpoints over training or compare algorithms.
+
+pub struct Tournament<'a, S>
+where
+    S: Environment
+{
+    pub entrants: Vec<(String, Box<dyn Agent<S> + 'a>)>,
+}
+
+impl<'a, S> Tournament<'a, S>
+where
+    S: Environment
+{
+    pub fn new() -> Self {
+        Tournament { entrants: Vec::new() }
+    }
+
+    pub fn add(mut self, name: &str, agent: impl Agent<S> + 'a) -> Self {
+        self.entrants.push((name.to_string(), Box::new(agent)));
+        self
+    }
+
+    // Plays games_per_pairing games between every pair of entrants.
+    pub fn play(&mut self, games_per_pairing: u32) -> TournamentResults {
+        let num_entrants = self.entrants.len();
+        let mut pairings = Vec::new();
+        for first in 0..num_entrants {
+            for second in first + 1..num_entrants {
+                let (left, right) = self.entrants.split_at_mut(second);
+                pairings.push((first, second, evaluate(left[first].1.as_mut(), right[0].1.as_mut(), games_per_pairing)));
+            }
+        }
+        let mut results = vec![vec![EvaluationResults::default(); num_entrants]; num_entrants];
+        for (first, second, pairing) in pairings {
+            results[first][second] = pairing;
+            results[second][first] = pairing.reversed();
+        }
+        let names = self.entrants.iter().map(|(name, _)| name.clone()).collect();
+        TournamentResults::new(names, results)
+    }
+}
+
+impl<'a, S> Default for Tournament<'a, S>
+where
+    S: Environment
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+// Each entrant's results against each other entrant, with their ratings.
+pub struct TournamentResults {
+    pub names: Vec<String>,
+    // results[i][j] is entrant i's record against entrant j
+    pub results: Vec<Vec<EvaluationResults>>,
+    pub ratings: Vec<f64>,
+    // Half-width of the 95% interval of each rating
+    pub rating_errors: Vec<f64>,
+}
+
+impl TournamentResults {
+    pub fn new(names: Vec<String>, results: Vec<Vec<EvaluationResults>>) -> Self {
+        let ratings = elo_ratings(&results);
+        let rating_errors = bootstrap_rating_errors(&results, BOOTSTRAP_SAMPLES, 0);
+        TournamentResults { names, results, ratings, rating_errors }
+    }
+
+    // The fraction of points scored over all games, counting draws as half.
+    pub fn score(&self, entrant: usize) -> f64 {
+        let (points, games) = self.results[entrant].iter()
+            .fold((0.0, 0), |(points, games), record| {
+                (points + record.wins as f64 + 0.5 * record.draws as f64, games + record.games())
+            });
+        if games == 0 { 0.0 } else { points / games as f64 }
+    }
+
+    // Entrants from the highest rated to the lowest.
+    pub fn ranking(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.names.len()).collect();
+        order.sort_by(|a, b| self.ratings[*b].total_cmp(&self.ratings[*a]));
+        order
+    }
+
+    // The ranking table, one row per entrant from the highest rated down.
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "rank,name,elo,elo_error,score")?;
+        for (rank, entrant) in self.ranking().into_iter().enumerate() {
+            writeln!(
+                writer, "{},{},{:.1},{:.1},{}",
+                rank + 1, csv_field(&self.names[entrant]), self.ratings[entrant], self.rating_errors[entrant], self.score(entrant)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// A ranking table followed by a cross table of the score of the entrant in
+// each row against the entrant in each column.
+impl fmt::Display for TournamentResults {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ranking = self.ranking();
+        let width = self.names.iter().map(String::len).max().unwrap_or(0).max(4);
+        writeln!(f, "Rank  {:width$}  {:>6}  {:>6}  {:>6}", "Name", "Elo", "+/-", "Score")?;
+        for (rank, entrant) in ranking.iter().enumerate() {
+            writeln!(
+                f, "{:>4}  {:width$}  {:>6.0}  {:>6.0}  {:>5.1}%",
+                rank + 1, self.names[*entrant], self.ratings[*entrant], self.rating_errors[*entrant], 100.0 * self.score(*entrant)
+            )?;
+        }
+        writeln!(f)?;
+        write!(f, "{:width$}", "")?;
+        for column in 1..=ranking.len() {
+            write!(f, "  {column:>6}")?;
+        }
+        writeln!(f)?;
+        for row in &ranking {
+            write!(f, "{:width$}", self.names[*row])?;
+            for column in &ranking {
+                let record = &self.results[*row][*column];
+                if row == column || record.games() == 0 {
+                    write!(f, "  {:>6}", "-")?;
+                } else {
+                    let score = (record.wins as f64 + 0.5 * record.draws as f64) / record.games() as f64;
+                    write!(f, "  {:>5.1}%", 100.0 * score)?;
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+
+// Number of resampled tournaments used for the rating errors
+pub const BOOTSTRAP_SAMPLES: u32 = 200;
+
+// Elo ratings that best explain the results, where entrant i is expected to
+// score 1 / (1 + 10^((r_j - r_i) / 400)) against entrant j, counting draws as
+// half a win. Fitted by maximum likelihood with the minorisation-maximisation
+// algorithm for the Bradley-Terry model. Every pairing that was played gets
+// one extra virtual draw, so that entrants who won or lost every game still
+// get finite ratings. The ratings average zero.
+pub fn elo_ratings(results: &[Vec<EvaluationResults>]) -> Vec<f64> {
+    let num_entrants = results.len();
+    let points = |i: usize, j: usize| {
+        let record = &results[i][j];
+        if record.games() == 0 { 0.0 } else { record.wins as f64 + 0.5 * record.draws as f64 + 0.5 }
+    };
+    let games = |i: usize, j: usize| {
+        let played = results[i][j].games();
+        if played == 0 { 0.0 } else { played as f64 + 1.0 }
+    };
+
+    // Bradley-Terry strengths, gamma_i = 10^(r_i / 400)
+    let mut strengths = vec![1.0; num_entrants];
+    for _ in 0..1000 {
+        let mut largest_change: f64 = 0.0;
+        for i in 0..num_entrants {
+            let total_points: f64 = (0..num_entrants).map(|j| points(i, j)).sum();
+            let denominator: f64 = (0..num_entrants)
+                .filter(|j| *j != i)
+                .map(|j| games(i, j) / (strengths[i] + strengths[j]))
+                .sum();
+            if denominator > 0.0 {
+                let strength = total_points / denominator;
+                largest_change = largest_change.max((strength / strengths[i]).ln().abs());
+                strengths[i] = strength;
+            }
+        }
+        if largest_change < 1e-10 {
+            break;
+        }
+    }
+
+    let ratings: Vec<f64> = strengths.iter().map(|strength| 400.0 * strength.log10()).collect();
+    let mean = ratings.iter().sum::<f64>() / num_entrants.max(1) as f64;
+    ratings.iter().map(|rating| rating - mean).collect()
+}
+
+// The half-width of a 95% interval for each rating, estimated by replaying
+// every pairing's games with outcomes resampled from its results and
+// refitting the ratings.
+pub fn bootstrap_rating_errors(results: &[Vec<EvaluationResults>], samples: u32, seed: u64) -> Vec<f64> {
+    let num_entrants = results.len();
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut sums = vec![0.0; num_entrants];
+    let mut sums_of_squares = vec![0.0; num_entrants];
+    for _ in 0..samples {
+        let mut resampled = vec![vec![EvaluationResults::default(); num_entrants]; num_entrants];
+        for i in 0..num_entrants {
+            for j in i + 1..num_entrants {
+                let record = &results[i][j];
+                let games = record.games();
+                let mut sample = EvaluationResults::default();
+                for _ in 0..games {
+                    let draw = rng.gen_range(0..games);
+                    if draw < record.wins {
+                        sample.wins += 1;
+                    } else if draw < record.wins + record.draws {
+                        sample.draws += 1;
+                    } else {
+                        sample.losses += 1;
+                    }
+                }
+                resampled[i][j] = sample;
+                resampled[j][i] = sample.reversed();
+            }
+        }
+        for (entrant, rating) in elo_ratings(&resampled).into_iter().enumerate() {
+            sums[entrant] += rating;
+            sums_of_squares[entrant] += rating * rating;
+        }
+    }
+    if samples < 2 {
+        return vec![0.0; num_entrants];
+    }
+    let n = samples as f64;
+    (0..num_entrants)
+        .map(|entrant| {
+            let mean = sums[entrant] / n;
+            let variance = (sums_of_squares[entrant] / n - mean * mean).max(0.0) * n / (n - 1.0);
+            Z_95 * variance.sqrt()
+        })
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::evaluation::{EvaluationResults, HeuristicAgent, PerfectAgent, RandomAgent};
+    use crate::tictactoe::TicTacToeBoard;
+
+    use super::{elo_ratings, Tournament, TournamentResults};
+
+    fn record(wins: u32, draws: u32, losses: u32) -> EvaluationResults {
+        EvaluationResults { wins, draws, losses }
+    }
+
+    #[test]
+    fn elo_difference_matches_expected_score() {
+        // Scoring 75% is worth 400 * log10(3), about 191 points
+        let results = vec![
+            vec![record(0, 0, 0), record(700, 100, 200)],
+            vec![record(200, 100, 700), record(0, 0, 0)],
+        ];
+        let ratings = elo_ratings(&results);
+        assert!((ratings[0] - ratings[1] - 400.0 * 3.0f64.log10()).abs() < 1.0);
+        assert!((ratings[0] + ratings[1]).abs() < 1e-9);
+    }
+
+    #[test]
+    fn unbeaten_entrants_get_finite_ratings() {
+        let results = vec![
+            vec![record(0, 0, 0), record(10, 0, 0)],
+            vec![record(0, 0, 10), record(0, 0, 0)],
+        ];
+        let ratings = elo_ratings(&results);
+        assert!(ratings[0].is_finite() && ratings[0] > ratings[1]);
+    }
+
+    #[test]
+    fn tournament_ranks_stronger_agents_higher() {
+        let mut tournament = Tournament::<TicTacToeBoard>::new()
+            .add("random", RandomAgent::new(0))
+            .add("heuristic", HeuristicAgent::new(1))
+            .add("perfect", PerfectAgent::new(2));
+        let results = tournament.play(100);
+        let names: Vec<&str> = results.ranking().into_iter().map(|entrant| results.names[entrant].as_str()).collect();
+        assert_eq!(names, vec!["perfect", "heuristic", "random"]);
+        assert!(results.rating_errors.iter().all(|error| *error > 0.0 && error.is_finite()));
+        assert_eq!(results.results[2][0].losses, 0);
+    }
+
+    #[test]
+    fn results_export_as_csv() {
+        let results = TournamentResults::new(
+            vec!["weak".to_string(), "strong, \"seeded\"".to_string()],
+            vec![
+                vec![record(0, 0, 0), record(0, 0, 10)],
+                vec![record(10, 0, 0), record(0, 0, 0)],
+            ],
+        );
+        let mut csv = Vec::new();
+        results.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "rank,name,elo,elo_error,score");
+        assert!(lines[1].starts_with("1,\"strong, \"\"seeded\"\"\","));
+        assert!(lines[1].ends_with(",1"));
+        assert!(lines[2].starts_with("2,weak,") && lines[2].ends_with(",0"));
+    }
+}
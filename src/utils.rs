@@ -1,4 +1,5 @@
-use std::io::{BufRead, Write};
+use std::{io::{self, BufRead, Write}, thread, time::Duration};
+use rand::Rng;
 
 pub fn prompt<R, W>(mut reader: R, mut writer: &mut W, question: &str) -> String
 where
@@ -9,4 +10,54 @@ where
     let mut s = String::new();
     reader.read_line(&mut s).expect("Unable to read");
     s
-}
\ No newline at end of file
+}
+
+pub fn show_machine_thinking() {
+    print!("Machine is making a move");
+    io::stdout().flush().unwrap();
+    for _ in 1..=3 {
+        thread::sleep(Duration::from_millis(300));
+        print!(".");
+        io::stdout().flush().unwrap();
+    }
+    println!();
+}
+
+// Box-Muller transform, so that we only need rand's uniform sampling.
+pub fn sample_standard_normal<R: Rng + ?Sized>(rng: &mut R) -> f64 {
+    // gen() samples from [0, 1), so 1 - u is never 0 and its log is finite.
+    let u1: f64 = 1.0 - rng.gen::<f64>();
+    let u2: f64 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+
+pub fn softmax(preferences: &[f64]) -> Vec<f64> {
+    // Subtract the max for numerical stability
+    let max = preferences.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let exponentials: Vec<f64> = preferences.iter().map(|preference| (preference - max).exp()).collect();
+    let total: f64 = exponentials.iter().sum();
+    exponentials.iter().map(|exponential| exponential / total).collect()
+}
+
+// Samples an index with the given probabilities.
+pub fn sample_index<R: Rng + ?Sized>(probabilities: &[f64], rng: &mut R) -> usize {
+    let mut remaining: f64 = rng.gen();
+    for (index, probability) in probabilities.iter().enumerate() {
+        if remaining < *probability {
+            return index;
+        }
+        remaining -= probability;
+    }
+    // Rounding errors can leave a sliver of probability at the end
+    probabilities.len() - 1
+}
+
+// Quotes fields containing separators or quotes, doubling any quotes.
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}